@@ -0,0 +1,133 @@
+//! BOC entry points taking a `byte[]` against their direct `ByteBuffer` variants,
+//! called the way the JVM calls them. Starting the JVM needs a JDK, see `JAVA_HOME`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use jni::objects::{JByteArray, JByteBuffer, JClass, JObject};
+use jni::sys::{jbyteArray, jint, jlong};
+use jni::{InitArgsBuilder, JNIEnv, JavaVM};
+use ton_types::{BuilderData, Cell};
+
+// Links the exported functions below
+extern crate nekoton_jni;
+
+extern "C" {
+    fn Java_com_mazekine_nekoton_Native_cellFromBoc(
+        env: JNIEnv,
+        class: JClass,
+        boc_bytes: JByteArray,
+    ) -> jlong;
+    fn Java_com_mazekine_nekoton_Native_cellFromBocBuffer(
+        env: JNIEnv,
+        class: JClass,
+        boc_buffer: JByteBuffer,
+        length: jint,
+    ) -> jlong;
+    fn Java_com_mazekine_nekoton_Native_cellToBoc(
+        env: JNIEnv,
+        class: JClass,
+        cell_handle: jlong,
+    ) -> jbyteArray;
+    fn Java_com_mazekine_nekoton_Native_cellToBocBuffer(
+        env: JNIEnv,
+        class: JClass,
+        cell_handle: jlong,
+        boc_buffer: JByteBuffer,
+    ) -> jint;
+    fn Java_com_mazekine_nekoton_Native_cleanupCell(env: JNIEnv, class: JClass, cell_handle: jlong);
+}
+
+/// Tree of distinct full cells, about `leaves` * 128 bytes once serialized
+fn tree(leaves: u32) -> Cell {
+    let mut level = (0..leaves)
+        .map(|index| {
+            let mut builder = BuilderData::new();
+            let mut data = [0xA5; 127];
+            data[..4].copy_from_slice(&index.to_be_bytes());
+            builder.append_raw(&data, data.len() * 8).unwrap();
+            builder.into_cell().unwrap()
+        })
+        .collect::<Vec<_>>();
+    while level.len() > 1 {
+        level = level
+            .chunks(4)
+            .map(|children| {
+                let mut builder = BuilderData::new();
+                for child in children {
+                    builder.checked_append_reference(child.clone()).unwrap();
+                }
+                builder.into_cell().unwrap()
+            })
+            .collect();
+    }
+    level.remove(0)
+}
+
+fn direct_buffer(c: &mut Criterion) {
+    let vm = JavaVM::new(InitArgsBuilder::new().build().unwrap()).unwrap();
+    let mut env = vm.attach_current_thread().unwrap();
+    let class = || JClass::from(JObject::null());
+
+    let mut group = c.benchmark_group("boc");
+    for leaves in [64, 1024, 4096] {
+        let mut boc = ton_types::serialize_toc(&tree(leaves)).unwrap();
+        let length = boc.len() as jint;
+        group.throughput(Throughput::Bytes(boc.len() as u64));
+
+        let array = env.byte_array_from_slice(&boc).unwrap();
+        // The buffer wraps `boc`, which outlives it
+        let buffer = unsafe { env.new_direct_byte_buffer(boc.as_mut_ptr(), boc.len()) }.unwrap();
+        let raw_env = || unsafe { env.unsafe_clone() };
+
+        group.bench_function(BenchmarkId::new("parse/byte_array", length), |b| {
+            b.iter(|| unsafe {
+                let array = JByteArray::from_raw(array.as_raw());
+                let handle =
+                    Java_com_mazekine_nekoton_Native_cellFromBoc(raw_env(), class(), array);
+                Java_com_mazekine_nekoton_Native_cleanupCell(raw_env(), class(), handle);
+            })
+        });
+        group.bench_function(BenchmarkId::new("parse/direct_buffer", length), |b| {
+            b.iter(|| unsafe {
+                let buffer = JByteBuffer::from_raw(buffer.as_raw());
+                let handle = Java_com_mazekine_nekoton_Native_cellFromBocBuffer(
+                    raw_env(),
+                    class(),
+                    buffer,
+                    length,
+                );
+                Java_com_mazekine_nekoton_Native_cleanupCell(raw_env(), class(), handle);
+            })
+        });
+
+        let cell_handle = unsafe {
+            Java_com_mazekine_nekoton_Native_cellFromBoc(
+                raw_env(),
+                class(),
+                JByteArray::from_raw(array.as_raw()),
+            )
+        };
+        group.bench_function(BenchmarkId::new("serialize/byte_array", length), |b| {
+            b.iter(|| unsafe {
+                let boc =
+                    Java_com_mazekine_nekoton_Native_cellToBoc(raw_env(), class(), cell_handle);
+                raw_env().delete_local_ref(JObject::from_raw(boc)).unwrap();
+            })
+        });
+        group.bench_function(BenchmarkId::new("serialize/direct_buffer", length), |b| {
+            b.iter(|| unsafe {
+                let buffer = JByteBuffer::from_raw(buffer.as_raw());
+                Java_com_mazekine_nekoton_Native_cellToBocBuffer(
+                    raw_env(),
+                    class(),
+                    cell_handle,
+                    buffer,
+                )
+            })
+        });
+        unsafe { Java_com_mazekine_nekoton_Native_cleanupCell(raw_env(), class(), cell_handle) };
+    }
+    group.finish();
+}
+
+criterion_group!(benches, direct_buffer);
+criterion_main!(benches);
@@ -0,0 +1,1892 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use ton_abi::contract::AbiVersion;
+use ton_abi::{Contract, Function, Param, ParamType, TokenValue};
+use ton_block::{Account, Deserializable, Message, Transaction};
+use ton_types::{BuilderData, Cell, IBitstring, SliceData};
+
+use crate::cell;
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+
+static ABIS: Lazy<Registry<Contract>> = Lazy::new(Registry::new);
+
+pub fn get_abi(handle: jlong) -> Result<Arc<Contract>> {
+    Ok(ABIS.get(handle)?)
+}
+
+pub fn parse_tokens(params: &[Param], json: &str) -> Result<Vec<ton_abi::Token>> {
+    let mut value = if json.trim().is_empty() {
+        Value::Object(Default::default())
+    } else {
+        serde_json::from_str(json)?
+    };
+    if let Value::Object(values) = &mut value {
+        if let Some(unknown) = values
+            .keys()
+            .find(|name| !params.iter().any(|param| &param.name == *name))
+        {
+            return Err(anyhow!("Unknown input parameter: {unknown}"));
+        }
+        normalize_params(params, values)?;
+    }
+    Ok(nekoton_abi::parse_abi_tokens(params, value)?)
+}
+
+fn normalize_params(params: &[Param], values: &mut serde_json::Map<String, Value>) -> Result<()> {
+    for param in params {
+        if let Some(value) = values.get_mut(&param.name) {
+            normalize_map_keys(&param.kind, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Address map keys are accepted in any address form and rewritten to the raw
+/// `workchain:hex` form, so the key codec always builds the 267-bit address key
+fn normalize_map_keys(kind: &ParamType, value: &mut Value) -> Result<()> {
+    match (kind, value) {
+        (ParamType::Map(key_kind, value_kind), Value::Object(entries)) => {
+            let mut normalized = serde_json::Map::with_capacity(entries.len());
+            for (key, mut value) in std::mem::take(entries) {
+                normalize_map_keys(value_kind, &mut value)?;
+                let key = match key_kind.as_ref() {
+                    ParamType::Address => nekoton_utils::repack_address(&key)
+                        .map_err(|e| anyhow!("Invalid address map key {key}: {e}"))?
+                        .to_string(),
+                    _ => key,
+                };
+                normalized.insert(key, value);
+            }
+            *entries = normalized;
+        }
+        (ParamType::Tuple(params), Value::Object(fields)) => normalize_params(params, fields)?,
+        (ParamType::Array(kind) | ParamType::FixedArray(kind, _), Value::Array(items)) => {
+            for item in items {
+                normalize_map_keys(kind, item)?;
+            }
+        }
+        (ParamType::Optional(kind) | ParamType::Ref(kind), value) => {
+            normalize_map_keys(kind, value)?
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Headers that ton_abi fills in by itself when no value is provided
+const DEFAULT_HEADERS: [&str; 3] = ["time", "expire", "pubkey"];
+
+fn parse_json_object(json: &str, what: &str) -> Result<serde_json::Map<String, Value>> {
+    match json.trim() {
+        "" => Ok(serde_json::Map::new()),
+        json => match serde_json::from_str(json)? {
+            Value::Object(values) => Ok(values),
+            _ => Err(anyhow!("{what} must be a JSON object")),
+        },
+    }
+}
+
+/// Parses header values against the header list declared by the ABI, in any order
+pub fn parse_headers(params: &[Param], json: &str) -> Result<HashMap<String, TokenValue>> {
+    let values = parse_json_object(json, "Headers")?;
+    if let Some(unknown) = values
+        .keys()
+        .find(|name| !params.iter().any(|param| &param.name == *name))
+    {
+        return Err(anyhow!("Unknown header: {unknown}"));
+    }
+    if let Some(missing) = params.iter().find(|param| {
+        !values.contains_key(&param.name) && !DEFAULT_HEADERS.contains(&param.name.as_str())
+    }) {
+        return Err(anyhow!("Missing value for header `{}`", missing.name));
+    }
+
+    let provided = params
+        .iter()
+        .filter(|param| values.contains_key(&param.name))
+        .cloned()
+        .collect::<Vec<_>>();
+    let tokens = nekoton_abi::parse_abi_tokens(&provided, Value::Object(values))?;
+    Ok(tokens
+        .into_iter()
+        .map(|token| (token.name, token.value))
+        .collect())
+}
+
+/// An empty array means no public key
+pub fn parse_public_key(public_key: &[u8]) -> Result<Option<&[u8]>> {
+    match public_key.len() {
+        0 => Ok(None),
+        32 => Ok(Some(public_key)),
+        _ => Err(anyhow!("Public key must be 32 bytes")),
+    }
+}
+
+fn empty_data() -> Result<SliceData> {
+    let mut empty_dict = BuilderData::new();
+    empty_dict.append_bit_zero()?;
+    SliceData::load_builder(empty_dict)
+}
+
+/// Writes the provided static variables and the public key (if any, under key 0)
+/// into `data`, keeping every other entry as it was
+fn update_init_data(
+    contract: &Contract,
+    data: SliceData,
+    public_key: Option<&[u8]>,
+    init_data: &str,
+    require_all: bool,
+) -> Result<Cell> {
+    let mut values = parse_json_object(init_data, "Init data")?;
+
+    if let Some(unknown) = values
+        .keys()
+        .find(|name| !contract.data.values().any(|item| &item.value.name == *name))
+    {
+        return Err(anyhow!("Unknown init data parameter: {unknown}"));
+    }
+    if let Some(missing) = contract
+        .data
+        .values()
+        .find(|item| require_all && !values.contains_key(&item.value.name))
+    {
+        return Err(anyhow!(
+            "Missing value for init data parameter `{}`",
+            missing.value.name
+        ));
+    }
+
+    let params = contract
+        .data
+        .values()
+        .filter(|item| values.contains_key(&item.value.name))
+        .map(|item| item.value.clone())
+        .collect::<Vec<_>>();
+    normalize_params(&params, &mut values)?;
+    let tokens = nekoton_abi::parse_abi_tokens(&params, Value::Object(values))?;
+
+    let mut data = contract.update_data(data, &tokens)?;
+    if let Some(public_key) = public_key {
+        data = Contract::insert_pubkey(data, public_key)?;
+    }
+    Ok(data.into_cell())
+}
+
+/// Builds the persistent data cell from the ABI `data` section, filling only the
+/// provided static variables and inserting the public key (if any) under key 0
+pub fn encode_init_data(
+    contract: &Contract,
+    public_key: Option<&[u8]>,
+    init_data: &str,
+) -> Result<Cell> {
+    update_init_data(contract, empty_data()?, public_key, init_data, false)
+}
+
+/// Merges the provided static variables into existing data, e.g. from a TVC
+pub fn merge_init_data(
+    contract: &Contract,
+    data: Cell,
+    public_key: Option<&[u8]>,
+    init_data: &str,
+) -> Result<Cell> {
+    update_init_data(
+        contract,
+        SliceData::load_cell(data)?,
+        public_key,
+        init_data,
+        false,
+    )
+}
+
+/// Every static variable is required; an empty `base_data_boc` starts from an
+/// empty data cell, otherwise its values are kept unless overwritten
+fn encode_init_data_boc(
+    abi_handle: jlong,
+    public_key: &[u8],
+    init_data: &str,
+    base_data_boc: &[u8],
+) -> Result<Vec<u8>> {
+    let contract = get_abi(abi_handle)?;
+    let public_key = parse_public_key(public_key)?;
+    let data = match base_data_boc.is_empty() {
+        true => empty_data()?,
+        false => SliceData::load_cell(cell::parse_boc(base_data_boc)?)?,
+    };
+    let data = update_init_data(&contract, data, public_key, init_data, true)?;
+    cell::serialize_boc(&data)
+}
+
+/// Data in the `fields` layout, as ABI 2.2+ contracts keep it once deployed,
+/// is decoded by the ABI `fields` when it is no data map. The public key is the
+/// `_pubkey` field there
+fn decode_init_data(abi_handle: jlong, data_boc: &[u8]) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let data = SliceData::load_cell(cell::parse_boc(data_boc)?)?;
+    let data_map = Contract::get_pubkey(&data)
+        .and_then(|public_key| Ok((public_key, contract.decode_data(data.clone())?)));
+
+    let (public_key, tokens) = match data_map {
+        Ok((public_key, tokens)) => (public_key.map(hex::encode), tokens),
+        Err(e) if contract.fields.is_empty() => return Err(e.into()),
+        Err(_) => {
+            let mut tokens = contract.decode_storage_fields(data, false)?;
+            let public_key = match tokens.iter().position(|token| token.name == "_pubkey") {
+                Some(index) => match tokens.remove(index).value {
+                    TokenValue::Uint(value) => {
+                        let bytes = value.number.to_bytes_be();
+                        let mut public_key = [0u8; 32];
+                        public_key[32 - bytes.len()..].copy_from_slice(&bytes);
+                        Some(hex::encode(public_key))
+                    }
+                    _ => return Err(anyhow!("Field `_pubkey` must be an uint256")),
+                },
+                None => None,
+            };
+            (public_key, tokens)
+        }
+    };
+
+    Ok(json!({
+        "publicKey": public_key,
+        "data": nekoton_abi::make_abi_tokens(&tokens)?,
+    })
+    .to_string())
+}
+
+/// The persistent state of a deployed account, decoded by the ABI `fields`
+/// section (ABI 2.1+). Every field is required, so a mismatching ABI fails
+fn decode_contract_fields(abi_handle: jlong, account_boc: &[u8]) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    if contract.fields.is_empty() {
+        return Err(anyhow!("ABI has no `fields` section"));
+    }
+    let account = Account::construct_from_cell(cell::parse_boc(account_boc)?)?;
+    let data = match account
+        .state_init()
+        .and_then(|state_init| state_init.data())
+    {
+        Some(data) => data.clone(),
+        None => return Err(anyhow!("Account is not deployed")),
+    };
+
+    let tokens = contract.decode_storage_fields(SliceData::load_cell(data)?, false)?;
+    Ok(nekoton_abi::make_abi_tokens(&tokens)?.to_string())
+}
+
+pub fn parse_abi(abi_json: &str) -> Result<jlong> {
+    let contract = Contract::load(abi_json.as_bytes())?;
+    Ok(ABIS.insert(Arc::new(contract)))
+}
+
+fn abi_version(contract: &Contract) -> String {
+    format!(
+        "{}.{}",
+        contract.abi_version.major, contract.abi_version.minor
+    )
+}
+
+/// Sorted by name, since the contract keeps its functions in a hash map
+fn get_abi_function_names(abi_handle: jlong) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let mut names = contract.functions.keys().collect::<Vec<_>>();
+    names.sort();
+    Ok(serde_json::to_string(&names)?)
+}
+
+fn params_to_json(params: &[Param]) -> Value {
+    params
+        .iter()
+        .map(|param| {
+            json!({
+                "name": param.name,
+                "type": param.kind.type_signature(),
+            })
+        })
+        .collect()
+}
+
+fn get_abi_function_signature(abi_handle: jlong, function_name: &str) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let function = get_function(&contract, function_name)?;
+
+    Ok(json!({
+        "name": function.name,
+        "inputs": params_to_json(&function.inputs),
+        "outputs": params_to_json(&function.outputs),
+    })
+    .to_string())
+}
+
+/// The typed tree of a param type; containers describe what they hold
+fn param_type_to_json(kind: &ParamType) -> Value {
+    let mut value = json!({ "type": kind.type_signature() });
+    let (name, details) = match kind {
+        ParamType::Uint(size) => ("uint", json!({ "size": size })),
+        ParamType::Int(size) => ("int", json!({ "size": size })),
+        ParamType::VarUint(size) => ("varuint", json!({ "size": size })),
+        ParamType::VarInt(size) => ("varint", json!({ "size": size })),
+        ParamType::FixedBytes(size) => ("fixedbytes", json!({ "size": size })),
+        ParamType::Tuple(params) => ("tuple", json!({ "components": param_tree_to_json(params) })),
+        ParamType::Array(item) => ("array", json!({ "item": param_type_to_json(item) })),
+        ParamType::FixedArray(item, size) => (
+            "fixedarray",
+            json!({ "item": param_type_to_json(item), "size": size }),
+        ),
+        ParamType::Map(key, item) => (
+            "map",
+            json!({ "key": param_type_to_json(key), "value": param_type_to_json(item) }),
+        ),
+        ParamType::Optional(inner) => ("optional", json!({ "inner": param_type_to_json(inner) })),
+        ParamType::Ref(inner) => ("ref", json!({ "inner": param_type_to_json(inner) })),
+        ParamType::Bool => ("bool", json!({})),
+        ParamType::Cell => ("cell", json!({})),
+        ParamType::Address => ("address", json!({})),
+        ParamType::AddressStd => ("address_std", json!({})),
+        ParamType::Bytes => ("bytes", json!({})),
+        ParamType::String => ("string", json!({})),
+        ParamType::Token => ("gram", json!({})),
+        ParamType::Time => ("time", json!({})),
+        ParamType::Expire => ("expire", json!({})),
+        ParamType::PublicKey => ("pubkey", json!({})),
+    };
+    value["kind"] = Value::from(name);
+    if let (Value::Object(value), Value::Object(details)) = (&mut value, details) {
+        value.extend(details);
+    }
+    value
+}
+
+fn param_tree_to_json(params: &[Param]) -> Value {
+    params
+        .iter()
+        .map(|param| {
+            let mut value = param_type_to_json(&param.kind);
+            value["name"] = Value::from(param.name.as_str());
+            value
+        })
+        .collect()
+}
+
+fn get_function<'a>(contract: &'a Contract, function_name: &str) -> Result<&'a Function> {
+    contract
+        .function(function_name)
+        .with_context(|| format!("Unknown function `{function_name}`"))
+}
+
+/// Canonical signature the function id is computed from, e.g. `transfer(address,uint128)()v2`
+fn get_function_signature(abi_handle: jlong, function_name: &str) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    Ok(get_function(&contract, function_name)?.get_function_signature())
+}
+
+/// Same bit pattern as the unsigned id, like op-codes
+fn get_function_ids(abi_handle: jlong, function_name: &str) -> Result<(jint, jint)> {
+    let contract = get_abi(abi_handle)?;
+    let function = get_function(&contract, function_name)?;
+    Ok((function.input_id as jint, function.output_id as jint))
+}
+
+fn get_function_params(abi_handle: jlong, function_name: &str) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let function = get_function(&contract, function_name)?;
+    Ok(json!({
+        "name": function.name,
+        "inputs": param_tree_to_json(&function.inputs),
+        "outputs": param_tree_to_json(&function.outputs),
+    })
+    .to_string())
+}
+
+fn function_id_to_json(id: u32) -> String {
+    format!("0x{id:08x}")
+}
+
+/// Everything the ABI declares; functions, events and data are sorted since the
+/// contract keeps them in hash maps
+fn get_abi_metadata(abi_handle: jlong) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+
+    let mut functions = contract.functions.values().collect::<Vec<_>>();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut events = contract.events.values().collect::<Vec<_>>();
+    events.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut data = contract.data.values().collect::<Vec<_>>();
+    data.sort_by_key(|item| item.key);
+
+    Ok(json!({
+        "version": abi_version(&contract),
+        "headers": params_to_json(&contract.header),
+        "functions": functions
+            .into_iter()
+            .map(|function| json!({
+                "name": function.name,
+                "inputs": params_to_json(&function.inputs),
+                "outputs": params_to_json(&function.outputs),
+                "inputId": function_id_to_json(function.input_id),
+                "outputId": function_id_to_json(function.output_id),
+            }))
+            .collect::<Vec<_>>(),
+        "events": events
+            .into_iter()
+            .map(|event| json!({
+                "name": event.name,
+                "inputs": params_to_json(&event.inputs),
+                "id": function_id_to_json(event.id),
+            }))
+            .collect::<Vec<_>>(),
+        "data": data
+            .into_iter()
+            .map(|item| json!({
+                "key": item.key,
+                "name": item.value.name,
+                "type": item.value.kind.type_signature(),
+            }))
+            .collect::<Vec<_>>(),
+    })
+    .to_string())
+}
+
+fn get_abi_capabilities(abi_handle: jlong) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let version = &contract.abi_version;
+    let has_header = |name: &str| contract.header.iter().any(|param| param.name == name);
+
+    Ok(json!({
+        "version": abi_version(&contract),
+        // ABI 1.x stores the signature in a reference, 2.x inlines it in the body
+        "signatureInRef": version.major < 2,
+        "signatureIncludesAddress": (version.major, version.minor) >= (2, 3),
+        "pubkeyInHeader": has_header("pubkey"),
+        "timeInHeader": has_header("time"),
+        "expireInHeader": has_header("expire"),
+    })
+    .to_string())
+}
+
+/// Complete external body without a signature, for contracts that don't check
+/// one. Signed calls go through `encodeExternalInput` or `createExternalMessage`
+fn encode_function_call(abi_handle: jlong, function_name: &str, inputs: &str) -> Result<Vec<u8>> {
+    let contract = get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let tokens = parse_tokens(&function.inputs, inputs)
+        .with_context(|| format!("Invalid inputs for function `{function_name}`"))?;
+    let (body, _) = function.create_unsigned_call(&HashMap::new(), &tokens, false, true, None)?;
+    let body = Function::fill_sign(&contract.abi_version, None, None, body)?;
+    cell::serialize_boc(&body.into_cell()?)
+}
+
+/// Function id followed by the inputs, without headers or a signature
+fn encode_internal_input(abi_handle: jlong, function_name: &str, inputs: &str) -> Result<Vec<u8>> {
+    let contract = get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let tokens = parse_tokens(&function.inputs, inputs)
+        .with_context(|| format!("Invalid inputs for function `{function_name}`"))?;
+    let body = function.encode_internal_input(&tokens)?;
+    cell::serialize_boc(&body.into_cell()?)
+}
+
+/// `body` lacks the signature and only becomes a valid body through
+/// `signExternalInput`; `hash` is what to sign. ABI 2.3 signs the destination
+/// along with the body, so the hash is only valid for `dst`
+fn encode_external_input(
+    abi_handle: jlong,
+    function_name: &str,
+    headers: &str,
+    inputs: &str,
+    dst: &str,
+) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let headers = parse_headers(&function.header, headers)?;
+    let tokens = parse_tokens(&function.inputs, inputs)?;
+    let dst = nekoton_utils::repack_address(dst)?;
+    let (body, hash) = function.create_unsigned_call(&headers, &tokens, false, true, Some(dst))?;
+    Ok(json!({
+        "body": base64::encode(cell::serialize_boc(&body.into_cell()?)?),
+        "hash": hex::encode(hash),
+    })
+    .to_string())
+}
+
+/// `public_key` may be empty; only ABI 1.x puts it next to the signature
+fn sign_external_input(
+    abi_handle: jlong,
+    body_boc: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<Vec<u8>> {
+    let contract = get_abi(abi_handle)?;
+    let signature = <[u8; 64]>::try_from(signature)
+        .map_err(|_| anyhow!("Signature must be 64 bytes, got {}", signature.len()))?;
+    let body = BuilderData::from_cell(&cell::parse_boc(body_boc)?)?;
+    let body = Function::fill_sign(
+        &contract.abi_version,
+        Some(&signature),
+        parse_public_key(public_key)?,
+        body,
+    )?;
+    cell::serialize_boc(&body.into_cell()?)
+}
+
+/// Internal and external bodies differ in layout: only external ones carry the
+/// signature and headers in front of the function id
+fn decode_function_input(
+    abi_handle: jlong,
+    function_name: &str,
+    body_boc: &[u8],
+    internal: bool,
+) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let body = SliceData::load_cell(cell::parse_boc(body_boc)?)?;
+    let tokens = function
+        .decode_input(body, internal, false)
+        .with_context(|| format!("Body does not match the inputs of `{function_name}`"))?;
+    Ok(nekoton_abi::make_abi_tokens(&tokens)?.to_string())
+}
+
+fn decode_function_output(
+    abi_handle: jlong,
+    function_name: &str,
+    output_boc: &[u8],
+) -> Result<String> {
+    let contract = get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let body = SliceData::load_cell(cell::parse_boc(output_boc)?)?;
+    let tokens = function
+        .decode_output(body, false)
+        .with_context(|| format!("Body does not match the outputs of `{function_name}`"))?;
+    Ok(nekoton_abi::make_abi_tokens(&tokens)?.to_string())
+}
+
+/// A param list in the same form the ABI JSON uses for function inputs
+fn parse_param_list(params: &str) -> Result<Vec<Param>> {
+    serde_json::from_str(params).context("Params must be a JSON array of ABI params")
+}
+
+pub fn parse_abi_version(abi_version: &str) -> Result<AbiVersion> {
+    AbiVersion::parse(abi_version.trim())
+        .map_err(|e| anyhow!("Invalid ABI version {abi_version}: {e}"))
+}
+
+/// Values that overflow a cell continue in a chain of refs, the way function
+/// bodies do with the same ABI version
+fn pack_into_cell(params: &str, values: &str, abi_version: &str) -> Result<Vec<u8>> {
+    let params = parse_param_list(params)?;
+    let tokens = parse_tokens(&params, values)?;
+    let cell = nekoton_abi::pack_into_cell(&tokens, parse_abi_version(abi_version)?)?;
+    cell::serialize_boc(&cell)
+}
+
+/// With `allow_partial`, data left over after the last param is ignored
+fn unpack_from_cell(
+    params: &str,
+    cell_boc: &[u8],
+    allow_partial: bool,
+    abi_version: &str,
+) -> Result<String> {
+    let params = parse_param_list(params)?;
+    let cursor = SliceData::load_cell(cell::parse_boc(cell_boc)?)?;
+    let tokens = nekoton_abi::unpack_from_cell(
+        &params,
+        cursor,
+        allow_partial,
+        parse_abi_version(abi_version)?,
+    )
+    .context("Cell does not match the params")?;
+    Ok(nekoton_abi::make_abi_tokens(&tokens)?.to_string())
+}
+
+/// Either a single name or a JSON array of names; empty matches everything
+fn parse_name_filter(filter: &str) -> Result<Vec<String>> {
+    let filter = filter.trim();
+    if filter.is_empty() {
+        Ok(Vec::new())
+    } else if filter.starts_with('[') {
+        serde_json::from_str(filter).context("Invalid name filter JSON")
+    } else {
+        Ok(vec![filter.to_owned()])
+    }
+}
+
+fn matches_filter(filter: &[String], name: &str) -> bool {
+    filter.is_empty() || filter.iter().any(|item| item == name)
+}
+
+/// `None` when the body doesn't start with the id of one of the ABI events
+fn decode_event_body(contract: &Contract, body: SliceData) -> Result<Option<(String, Value)>> {
+    let Ok(id) = body.clone().get_next_u32() else {
+        return Ok(None);
+    };
+    let Ok(event) = contract.event_by_id(id) else {
+        return Ok(None);
+    };
+    let tokens = event
+        .decode_input(body, false)
+        .with_context(|| format!("Body does not match the event `{}`", event.name))?;
+    Ok(Some((
+        event.name.clone(),
+        nekoton_abi::make_abi_tokens(&tokens)?,
+    )))
+}
+
+/// `None` when the body is not one of the filtered events
+fn decode_event(abi_handle: jlong, body_boc: &[u8], event_filter: &str) -> Result<Option<String>> {
+    let contract = get_abi(abi_handle)?;
+    let filter = parse_name_filter(event_filter)?;
+    let body = SliceData::load_cell(cell::parse_boc(body_boc)?)?;
+
+    Ok(match decode_event_body(&contract, body)? {
+        Some((event, data)) if matches_filter(&filter, &event) => {
+            Some(json!({ "event": event, "data": data }).to_string())
+        }
+        _ => None,
+    })
+}
+
+/// Matches the inbound message against the ABI functions, then decodes the
+/// function output and events from the external out messages.
+/// `None` when the transaction is not a call of one of the filtered functions
+fn decode_transaction(
+    abi_handle: jlong,
+    transaction_boc: &[u8],
+    method_filter: &str,
+) -> Result<Option<String>> {
+    let contract = get_abi(abi_handle)?;
+    let filter = parse_name_filter(method_filter)?;
+    let transaction = Transaction::construct_from_cell(cell::parse_boc(transaction_boc)?)?;
+
+    let Some(in_msg) = transaction.read_in_msg()? else {
+        return Ok(None);
+    };
+    let Some(body) = in_msg.body() else {
+        return Ok(None);
+    };
+    let Ok(decoded) = contract.decode_input(body, in_msg.is_internal(), false) else {
+        return Ok(None);
+    };
+    if !matches_filter(&filter, &decoded.function_name) {
+        return Ok(None);
+    }
+    let function = contract.function(&decoded.function_name)?;
+
+    let mut output = Value::Null;
+    let mut events = Vec::new();
+    transaction.out_msgs.iterate_slices(|slice| {
+        let message = Message::construct_from_cell(slice.reference(0)?)?;
+        let (true, Some(body)) = (message.is_outbound_external(), message.body()) else {
+            return Ok(true);
+        };
+        if output.is_null() && function.is_my_output_message(body.clone(), false)? {
+            output = nekoton_abi::make_abi_tokens(&function.decode_output(body, false)?)?;
+        } else if let Some((event, data)) = decode_event_body(&contract, body)? {
+            events.push(json!({ "event": event, "data": data }));
+        }
+        Ok(true)
+    })?;
+
+    Ok(Some(
+        json!({
+            "method": decoded.function_name,
+            "input": nekoton_abi::make_abi_tokens(&decoded.tokens)?,
+            "output": output,
+            "events": events,
+        })
+        .to_string(),
+    ))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAbi(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_json: JString,
+) -> jlong {
+    error::guard!(env, {
+        let abi_json: String = match env.get_string(&abi_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match parse_abi(&abi_json) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiVersion(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let version = match get_abi(abi_handle) {
+            Ok(contract) => abi_version(&contract),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(version) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupAbi(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = ABIS.remove(abi_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiFunctionNames(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let names = match get_abi_function_names(abi_handle) {
+            Ok(names) => names,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(names.as_bytes()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiFunctionSignature(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let signature = match get_abi_function_signature(abi_handle, &function_name) {
+            Ok(signature) => signature,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(signature) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getFunctionSignature(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let signature = match get_function_signature(abi_handle, &function_name) {
+            Ok(signature) => signature,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(signature) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getFunctionInputId(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+) -> jint {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match get_function_ids(abi_handle, &function_name) {
+            Ok((input_id, _)) => input_id,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getFunctionOutputId(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+) -> jint {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match get_function_ids(abi_handle, &function_name) {
+            Ok((_, output_id)) => output_id,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                0
+            }
+        }
+    })
+}
+
+/// Unlike `getAbiFunctionSignature`, tuples, arrays, maps and optionals are
+/// expanded down to their leaf types
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getFunctionParams(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let params = match get_function_params(abi_handle, &function_name) {
+            Ok(params) => params,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(params) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeInitData(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    public_key: JByteArray,
+    params_json: JString,
+    base_data_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let params: String = match env.get_string(&params_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let base_data_boc = match env.convert_byte_array(base_data_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let data = match encode_init_data_boc(abi_handle, &public_key, &params, &base_data_boc) {
+            Ok(data) => data,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&data) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeInitData(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    data_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let data_boc = match env.convert_byte_array(data_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match decode_init_data(abi_handle, &data_boc) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeContractFields(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    account_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let account_boc = match env.convert_byte_array(account_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match decode_contract_fields(abi_handle, &account_boc) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiMetadata(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let metadata = match get_abi_metadata(abi_handle) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(metadata) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiCapabilities(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let capabilities = match get_abi_capabilities(abi_handle) {
+            Ok(capabilities) => capabilities,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(capabilities) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeFunctionCall(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    inputs_json: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let body = match encode_function_call(abi_handle, &function_name, &inputs) {
+            Ok(body) => body,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&body) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeInternalInput(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    inputs_json: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let body = match encode_internal_input(abi_handle, &function_name, &inputs) {
+            Ok(body) => body,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&body) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeExternalInput(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    headers_json: JString,
+    inputs_json: JString,
+    dst_address: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let headers: String = match env.get_string(&headers_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let dst: String = match env.get_string(&dst_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let unsigned =
+            match encode_external_input(abi_handle, &function_name, &headers, &inputs, &dst) {
+                Ok(unsigned) => unsigned,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Abi, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(unsigned) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signExternalInput(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    body_boc: JByteArray,
+    signature: JByteArray,
+    public_key: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let body_boc = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let signature = match env.convert_byte_array(signature) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let body = match sign_external_input(abi_handle, &body_boc, &signature, &public_key) {
+            Ok(body) => body,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&body) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeFunctionInput(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    body_boc: JByteArray,
+    internal: jboolean,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let body_boc = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result =
+            match decode_function_input(abi_handle, &function_name, &body_boc, internal != 0) {
+                Ok(result) => result,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Abi, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeFunctionOutput(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    output_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let output_boc = match env.convert_byte_array(output_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match decode_function_output(abi_handle, &function_name, &output_boc) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    transaction_boc: JByteArray,
+    method_filter: JString,
+) -> jstring {
+    error::guard!(env, {
+        let transaction_boc = match env.convert_byte_array(transaction_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let method_filter: String = match env.get_string(&method_filter) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let decoded = match decode_transaction(abi_handle, &transaction_boc, &method_filter) {
+            Ok(Some(decoded)) => decoded,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(decoded) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeEvent(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    message_body_boc: JByteArray,
+    event_name_filter: JString,
+) -> jstring {
+    error::guard!(env, {
+        let body_boc = match env.convert_byte_array(message_body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let event_filter: String = match env.get_string(&event_name_filter) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let decoded = match decode_event(abi_handle, &body_boc, &event_filter) {
+            Ok(Some(decoded)) => decoded,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(decoded) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `abiVersion` is `major.minor`, e.g. `2.3`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_packIntoCell(
+    mut env: JNIEnv,
+    _class: JClass,
+    params_json: JString,
+    values_json: JString,
+    abi_version: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let params: String = match env.get_string(&params_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let values: String = match env.get_string(&values_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let abi_version: String = match env.get_string(&abi_version) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let boc = match pack_into_cell(&params, &values, &abi_version) {
+            Ok(boc) => boc,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&boc) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Takes the ABI version too, since the layout of large values depends on it
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_unpackFromCell(
+    mut env: JNIEnv,
+    _class: JClass,
+    params_json: JString,
+    cell_boc: JByteArray,
+    allow_partial: jboolean,
+    abi_version: JString,
+) -> jstring {
+    error::guard!(env, {
+        let params: String = match env.get_string(&params_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let cell_boc = match env.convert_byte_array(cell_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let abi_version: String = match env.get_string(&abi_version) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let values = match unpack_from_cell(&params, &cell_boc, allow_partial != 0, &abi_version) {
+            Ok(values) => values,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(values) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    const TRANSFER_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", "expire"],
+        "functions": [
+            {
+                "name": "transfer",
+                "inputs": [
+                    {"name": "to", "type": "address"},
+                    {"name": "amount", "type": "uint128"}
+                ],
+                "outputs": []
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    #[test]
+    fn function_call_round_trips() {
+        let abi = parse_abi(TRANSFER_ABI).unwrap();
+        let inputs = json!({ "to": ADDRESS, "amount": "1000000000" }).to_string();
+
+        let body = encode_function_call(abi, "transfer", &inputs).unwrap();
+        let decoded = decode_function_input(abi, "transfer", &body, false).unwrap();
+        let decoded: Value = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(decoded["to"], ADDRESS);
+        assert_eq!(decoded["amount"], "1000000000");
+    }
+
+    #[test]
+    fn function_call_rejects_mismatched_inputs() {
+        let abi = parse_abi(TRANSFER_ABI).unwrap();
+        let missing = json!({ "to": ADDRESS }).to_string();
+        // 2^128 doesn't fit into uint128
+        let too_wide =
+            json!({ "to": ADDRESS, "amount": "340282366920938463463374607431768211456" })
+                .to_string();
+        let unknown = json!({ "to": ADDRESS, "amount": "1", "memo": "" }).to_string();
+
+        assert!(encode_function_call(abi, "transfer", &missing).is_err());
+        assert!(encode_function_call(abi, "transfer", &too_wide).is_err());
+        assert!(encode_function_call(abi, "transfer", &unknown).is_err());
+    }
+
+    const CUSTOM_HEADER_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", {"name": "nonce", "type": "uint32"}, "expire"],
+        "functions": [
+            {
+                "name": "transfer",
+                "inputs": [
+                    {"name": "to", "type": "address"},
+                    {"name": "amount", "type": "uint128"}
+                ],
+                "outputs": []
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    #[test]
+    fn custom_header_is_encoded_in_declared_position() {
+        let abi = parse_abi(CUSTOM_HEADER_ABI).unwrap();
+        let headers = json!({ "time": 1_700_000_000_000u64, "nonce": 7, "expire": 1_700_000_060 });
+        let inputs = json!({ "to": ADDRESS, "amount": "1" }).to_string();
+
+        let unsigned =
+            encode_external_input(abi, "transfer", &headers.to_string(), &inputs, ADDRESS).unwrap();
+        let unsigned: Value = serde_json::from_str(&unsigned).unwrap();
+        let body = base64::decode(unsigned["body"].as_str().unwrap()).unwrap();
+        let signed = sign_external_input(abi, &body, &[1; 64], &[]).unwrap();
+
+        // Signature flag and signature, then the headers in ABI order, then the function id
+        let mut body = SliceData::load_cell(cell::parse_boc(&signed).unwrap()).unwrap();
+        assert!(body.get_next_bit().unwrap());
+        body.get_next_bits(512).unwrap();
+        assert_eq!(body.get_next_u64().unwrap(), 1_700_000_000_000);
+        assert_eq!(body.get_next_u32().unwrap(), 7);
+        assert_eq!(body.get_next_u32().unwrap(), 1_700_000_060);
+        let function = get_abi(abi)
+            .unwrap()
+            .function("transfer")
+            .unwrap()
+            .get_input_id();
+        assert_eq!(body.get_next_u32().unwrap(), function);
+    }
+
+    #[test]
+    fn custom_header_without_value_is_rejected() {
+        let abi = parse_abi(CUSTOM_HEADER_ABI).unwrap();
+        let inputs = json!({ "to": ADDRESS, "amount": "1" }).to_string();
+        assert!(encode_external_input(abi, "transfer", "{}", &inputs, ADDRESS).is_err());
+    }
+
+    const FLAGS_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", "expire"],
+        "functions": [
+            {
+                "name": "setFlags",
+                "inputs": [
+                    {"name": "first", "type": "bool"},
+                    {"name": "level", "type": "uint8"},
+                    {"name": "last", "type": "bool"}
+                ],
+                "outputs": []
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    #[test]
+    fn bools_take_one_bit_each() {
+        let abi = parse_abi(FLAGS_ABI).unwrap();
+        let inputs = json!({ "first": true, "level": 200, "last": true }).to_string();
+
+        let body = encode_internal_input(abi, "setFlags", &inputs).unwrap();
+        let mut slice = SliceData::load_cell(cell::parse_boc(&body).unwrap()).unwrap();
+        // Function id, then exactly 1 + 8 + 1 bits
+        assert_eq!(slice.remaining_bits(), 32 + 1 + 8 + 1);
+        slice.get_next_u32().unwrap();
+        assert!(slice.get_next_bit().unwrap());
+        assert_eq!(slice.get_next_byte().unwrap(), 200);
+        assert!(slice.get_next_bit().unwrap());
+
+        let decoded = decode_function_input(abi, "setFlags", &body, true).unwrap();
+        let decoded: Value = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(decoded["first"], true);
+        assert_eq!(decoded["level"].to_string().trim_matches('"'), "200");
+        assert_eq!(decoded["last"], true);
+    }
+
+    fn capabilities(version: &str, header: &str) -> Value {
+        let abi = format!(
+            r#"{{"ABI version": 2, "version": "{version}", "header": {header}, "functions": [], "data": [], "events": []}}"#
+        );
+        let abi = parse_abi(&abi).unwrap();
+        serde_json::from_str(&get_abi_capabilities(abi).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn capabilities_follow_version_and_header() {
+        let v2_0 = capabilities("2.0", r#"["pubkey", "time", "expire"]"#);
+        let v2_2 = capabilities("2.2", r#"["time"]"#);
+        let v2_3 = capabilities("2.3", r#"["pubkey", "expire"]"#);
+
+        assert_eq!(v2_0["version"], "2.0");
+        assert_eq!(v2_2["version"], "2.2");
+        assert_eq!(v2_3["version"], "2.3");
+        for capabilities in [&v2_0, &v2_2, &v2_3] {
+            assert_eq!(capabilities["signatureInRef"], false);
+        }
+        assert_eq!(v2_0["signatureIncludesAddress"], false);
+        assert_eq!(v2_2["signatureIncludesAddress"], false);
+        assert_eq!(v2_3["signatureIncludesAddress"], true);
+
+        let header = |capabilities: &Value| {
+            [
+                capabilities["pubkeyInHeader"].clone(),
+                capabilities["timeInHeader"].clone(),
+                capabilities["expireInHeader"].clone(),
+            ]
+        };
+        assert_eq!(header(&v2_0), [true, true, true]);
+        assert_eq!(header(&v2_2), [false, true, false]);
+        assert_eq!(header(&v2_3), [true, false, true]);
+    }
+
+    const BALANCES_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", "expire"],
+        "functions": [
+            {
+                "name": "balances",
+                "inputs": [],
+                "outputs": [{"name": "balances", "type": "map(address,uint128)"}]
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    const OTHER_ADDRESS: &str =
+        "-1:4444444444444444444444444444444444444444444444444444444444444444";
+
+    #[test]
+    fn address_map_keys_decode_as_raw_addresses() {
+        let abi = parse_abi(BALANCES_ABI).unwrap();
+        let contract = get_abi(abi).unwrap();
+        let function = contract.function("balances").unwrap();
+        // Keys may come in any address form
+        let friendly = nekoton_utils::pack_std_smc_addr(
+            true,
+            &nekoton_utils::repack_address(OTHER_ADDRESS).unwrap(),
+            true,
+        )
+        .unwrap();
+        let balances = json!({ "balances": { ADDRESS: "5", friendly: "7" } });
+
+        let tokens = parse_tokens(&function.outputs, &balances.to_string()).unwrap();
+        let output = function
+            .encode_internal_output(function.get_output_id(), &tokens)
+            .unwrap();
+        let output = cell::serialize_boc(&output.into_cell().unwrap()).unwrap();
+        let decoded = decode_function_output(abi, "balances", &output).unwrap();
+        let decoded: Value = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(
+            decoded["balances"],
+            json!({ ADDRESS: "5", OTHER_ADDRESS: "7" })
+        );
+    }
+
+    #[test]
+    fn malformed_address_map_key_is_rejected() {
+        let abi = parse_abi(BALANCES_ABI).unwrap();
+        let contract = get_abi(abi).unwrap();
+        let function = contract.function("balances").unwrap();
+        let balances = json!({ "balances": { "0:not-an-address": "5" } });
+        assert!(parse_tokens(&function.outputs, &balances.to_string()).is_err());
+    }
+
+    const SUBMIT_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", "expire"],
+        "functions": [
+            {
+                "name": "submit",
+                "inputs": [
+                    {"name": "amount", "type": "uint128"},
+                    {"name": "delta", "type": "int64"},
+                    {"name": "owner", "type": "address"},
+                    {"name": "payload", "type": "cell"},
+                    {"name": "memo", "type": "bytes"},
+                    {"name": "flags", "type": "bool[]"},
+                    {"name": "limit", "type": "optional(uint32)"},
+                    {"name": "route", "type": "tuple", "components": [
+                        {"name": "hop", "type": "uint8"},
+                        {"name": "to", "type": "address"}
+                    ]},
+                    {"name": "weights", "type": "map(uint32,uint16)"}
+                ],
+                "outputs": [
+                    {"name": "total", "type": "uint128"},
+                    {"name": "route", "type": "tuple", "components": [
+                        {"name": "hop", "type": "uint8"},
+                        {"name": "to", "type": "address"}
+                    ]}
+                ]
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    fn submit_inputs(limit: Value) -> Value {
+        let payload = BuilderData::with_raw(vec![0xCA, 0xFE], 16).unwrap();
+        let payload = cell::serialize_boc(&payload.into_cell().unwrap()).unwrap();
+        json!({
+            "amount": "123456789012345678901234567890",
+            "delta": "-42",
+            "owner": ADDRESS,
+            "payload": base64::encode(payload),
+            "memo": "",
+            "flags": [true, false, true],
+            "limit": limit,
+            "route": { "hop": 3, "to": ADDRESS },
+            "weights": { "1": 10, "2": 20 },
+        })
+    }
+
+    #[test]
+    fn every_param_kind_round_trips() {
+        let abi = parse_abi(SUBMIT_ABI).unwrap();
+        for limit in [json!(500), Value::Null] {
+            let inputs = submit_inputs(limit.clone()).to_string();
+            // Internal bodies have no time header, so encoding is deterministic
+            let body = encode_internal_input(abi, "submit", &inputs).unwrap();
+            let decoded = decode_function_input(abi, "submit", &body, true).unwrap();
+            // Decoded values encode to the same body, whatever their JSON form
+            assert_eq!(
+                encode_internal_input(abi, "submit", &decoded).unwrap(),
+                body
+            );
+
+            let decoded: Value = serde_json::from_str(&decoded).unwrap();
+            assert_eq!(decoded["amount"], "123456789012345678901234567890");
+            assert_eq!(decoded["delta"], "-42");
+            assert_eq!(decoded["owner"], ADDRESS);
+            assert_eq!(decoded["flags"], json!([true, false, true]));
+            assert_eq!(decoded["limit"].is_null(), limit.is_null());
+            assert_eq!(decoded["route"]["to"], ADDRESS);
+            assert_eq!(decoded["weights"].as_object().unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn function_output_decodes_to_typed_json() {
+        let abi = parse_abi(SUBMIT_ABI).unwrap();
+        let contract = get_abi(abi).unwrap();
+        let function = contract.function("submit").unwrap();
+        let outputs = json!({ "total": "1000", "route": { "hop": 3, "to": ADDRESS } });
+
+        let tokens = parse_tokens(&function.outputs, &outputs.to_string()).unwrap();
+        let output = function
+            .encode_internal_output(function.get_output_id(), &tokens)
+            .unwrap();
+        let output = cell::serialize_boc(&output.into_cell().unwrap()).unwrap();
+        let decoded = decode_function_output(abi, "submit", &output).unwrap();
+        let decoded: Value = serde_json::from_str(&decoded).unwrap();
+        assert_eq!(decoded["total"], "1000");
+        assert_eq!(decoded["route"]["to"], ADDRESS);
+
+        // An input body is not an output of the same function
+        let body =
+            encode_function_call(abi, "submit", &submit_inputs(Value::Null).to_string()).unwrap();
+        assert!(decode_function_output(abi, "submit", &body).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use jni::objects::{JByteArray, JByteBuffer, JClass};
+use jni::sys::{jint, jstring};
+use jni::JNIEnv;
+use ton_block::{Account, Deserializable, ShardAccount};
+
+use crate::{cell, error, models};
+
+/// A `ShardAccount` cell holds the last transaction hash and lt, with the account as its only ref
+const SHARD_ACCOUNT_BITS: usize = 256 + 64;
+
+/// Accepts a bare account or one wrapped in its `ShardAccount`, as returned in
+/// shard states and by some GQL queries
+fn parse_account_stuff(account_boc: &[u8]) -> Result<String> {
+    let cell = cell::parse_boc(account_boc)?;
+    let json = if cell.references_count() == 1 && cell.bit_length() == SHARD_ACCOUNT_BITS {
+        let shard_account = ShardAccount::construct_from_cell(cell)?;
+        models::account_to_json(
+            &shard_account.read_account()?,
+            Some(shard_account.last_trans_hash()),
+        )?
+    } else {
+        models::account_to_json(&Account::construct_from_cell(cell)?, None)?
+    };
+    Ok(json.to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAccountStuff(
+    mut env: JNIEnv,
+    _class: JClass,
+    account_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let account_boc = match env.convert_byte_array(account_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let account = match parse_account_stuff(&account_boc) {
+            Ok(account) => account,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(account) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `parseAccountStuff` reading the first `length` bytes of a direct `ByteBuffer`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAccountStuffBuffer(
+    mut env: JNIEnv,
+    _class: JClass,
+    account_buffer: JByteBuffer,
+    length: jint,
+) -> jstring {
+    error::guard!(env, {
+        let account =
+            match cell::with_direct_buffer(&env, &account_buffer, length, parse_account_stuff) {
+                Ok(account) => account,
+                Err(e) => {
+                    error::throw(&mut env, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(account) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,269 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jbyteArray, jstring};
+use jni::JNIEnv;
+use ton_block::{MsgAddrStd, MsgAddressInt};
+use ton_types::UInt256;
+
+use crate::error;
+
+/// Accepts the raw `workchain:hex` form as well as the user-friendly base64
+/// and base64url forms, whose checksum is validated
+pub fn parse_address(address: &str) -> Result<MsgAddressInt> {
+    let address = address.trim();
+    if address.contains(':') {
+        return MsgAddressInt::from_str(address)
+            .map_err(|e| anyhow!("Invalid raw address {address:?}: {e}"));
+    }
+    if address.len() != 48 {
+        return Err(anyhow!(
+            "Invalid user-friendly address {address:?}: expected 48 characters, got {}",
+            address.len()
+        ));
+    }
+    let url_safe = address.contains(['-', '_']);
+    nekoton_utils::unpack_std_smc_addr(address, url_safe)
+        .map_err(|e| anyhow!("Invalid user-friendly address {address:?}: {e}"))
+}
+
+const TAG_BOUNCEABLE: u8 = 0x11;
+const TAG_NON_BOUNCEABLE: u8 = 0x51;
+const TAG_TEST_ONLY: u8 = 0x80;
+
+/// Inverse of [`address_to_bytes`]
+pub fn address_from_bytes(bytes: &[u8]) -> Result<MsgAddressInt> {
+    if bytes.len() != 33 {
+        return Err(anyhow!(
+            "Address must be 33 bytes (workchain + account id), got {}",
+            bytes.len()
+        ));
+    }
+    Ok(MsgAddressInt::AddrStd(MsgAddrStd {
+        anycast: None,
+        workchain_id: bytes[0] as i8,
+        address: UInt256::from_slice(&bytes[1..]).into(),
+    }))
+}
+
+/// Flag byte, workchain byte, account id and a CRC16/XMODEM checksum, base64 encoded
+pub fn format_address(
+    address: &MsgAddressInt,
+    user_friendly: bool,
+    url_safe: bool,
+    test_only: bool,
+    bounce: bool,
+) -> Result<String> {
+    if !user_friendly {
+        return Ok(address.to_string());
+    }
+
+    let mut packed = Vec::with_capacity(36);
+    let tag = if bounce {
+        TAG_BOUNCEABLE
+    } else {
+        TAG_NON_BOUNCEABLE
+    };
+    packed.push(if test_only { tag | TAG_TEST_ONLY } else { tag });
+    packed.extend_from_slice(&address_to_bytes(address)?);
+    let crc = crc16(&packed);
+    packed.extend_from_slice(&crc.to_be_bytes());
+
+    let config = if url_safe {
+        base64::URL_SAFE
+    } else {
+        base64::STANDARD
+    };
+    Ok(base64::encode_config(packed, config))
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= u16::from(*byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Workchain byte followed by the 32-byte account id
+pub fn address_to_bytes(address: &MsgAddressInt) -> Result<Vec<u8>> {
+    match address {
+        MsgAddressInt::AddrStd(address) => {
+            let mut bytes = Vec::with_capacity(33);
+            bytes.push(address.workchain_id as u8);
+            bytes.extend_from_slice(&address.address.get_bytestring(0));
+            Ok(bytes)
+        }
+        MsgAddressInt::AddrVar(_) => Err(anyhow!("Variable-length addresses are not supported")),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    address_str: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address_str) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let bytes = match parse_address(&address).and_then(|address| address_to_bytes(&address)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&bytes) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_formatAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    address_bytes: JByteArray,
+    user_friendly: jboolean,
+    url_safe: jboolean,
+    test_only: jboolean,
+    bounce: jboolean,
+) -> jstring {
+    error::guard!(env, {
+        let bytes = match env.convert_byte_array(address_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let address = address_from_bytes(&bytes).and_then(|address| {
+            format_address(
+                &address,
+                user_friendly != 0,
+                url_safe != 0,
+                test_only != 0,
+                bounce != 0,
+            )
+        });
+        let address = match address {
+            Ok(address) => address,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(address) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELECTOR: &str = "-1:3333333333333333333333333333333333333333333333333333333333333333";
+    const ELECTOR_FRIENDLY: &str = "Ef8zMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzMzM0vF";
+    // Its standard base64 form has `/` where the url-safe one has `_`
+    const ALL_ONES: &str = "0:ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+    fn flags(friendly: &str) -> u8 {
+        base64::decode_config(friendly, base64::URL_SAFE)
+            .or_else(|_| base64::decode(friendly))
+            .unwrap()[0]
+    }
+
+    #[test]
+    fn elector_address_matches_its_known_form() {
+        let elector = parse_address(ELECTOR).unwrap();
+        assert_eq!(
+            format_address(&elector, true, true, false, true).unwrap(),
+            ELECTOR_FRIENDLY
+        );
+        assert_eq!(parse_address(ELECTOR_FRIENDLY).unwrap(), elector);
+        assert_eq!(
+            format_address(&elector, false, true, true, true).unwrap(),
+            ELECTOR
+        );
+    }
+
+    #[test]
+    fn user_friendly_forms_round_trip() {
+        for raw in [ELECTOR, ALL_ONES] {
+            let address = parse_address(raw).unwrap();
+            for (url_safe, bounce) in [(false, false), (false, true), (true, false), (true, true)] {
+                let friendly = format_address(&address, true, url_safe, false, bounce).unwrap();
+                assert_eq!(friendly.len(), 48);
+                assert_eq!(parse_address(&friendly).unwrap(), address, "{friendly}");
+                let tag = if bounce {
+                    TAG_BOUNCEABLE
+                } else {
+                    TAG_NON_BOUNCEABLE
+                };
+                assert_eq!(flags(&friendly), tag);
+            }
+        }
+
+        let all_ones = parse_address(ALL_ONES).unwrap();
+        assert!(format_address(&all_ones, true, true, false, true)
+            .unwrap()
+            .contains('_'));
+        assert!(format_address(&all_ones, true, false, false, true)
+            .unwrap()
+            .contains('/'));
+        let test_only = format_address(&all_ones, true, true, true, true).unwrap();
+        assert_eq!(flags(&test_only), TAG_BOUNCEABLE | TAG_TEST_ONLY);
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected_with_the_reason() {
+        let mut corrupted = ELECTOR_FRIENDLY.to_owned();
+        corrupted.replace_range(46.., "AA");
+        for (address, reason) in [
+            (
+                "0:zz33333333333333333333333333333333333333333333333333333333333333",
+                "Invalid raw address",
+            ),
+            ("Ef8zMzMz", "expected 48 characters"),
+            (corrupted.as_str(), "Invalid user-friendly address"),
+        ] {
+            let error = parse_address(address).unwrap_err().to_string();
+            assert!(error.contains(reason), "{address}: {error}");
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let elector = parse_address(ELECTOR).unwrap();
+        let bytes = address_to_bytes(&elector).unwrap();
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(bytes[0], 0xFF);
+        assert_eq!(address_from_bytes(&bytes).unwrap(), elector);
+        assert!(address_from_bytes(&bytes[..32]).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jint, jstring};
+use jni::JNIEnv;
+use num_bigint::BigUint;
+
+use crate::error;
+
+fn parse_decimals(decimals: jint) -> Result<u32> {
+    u8::try_from(decimals)
+        .map(u32::from)
+        .map_err(|_| anyhow!("Decimals must be between 0 and 255, got {decimals}"))
+}
+
+fn is_digits(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Decimal string in the smallest units, such as nano EVER
+fn parse_u128(value: &str) -> Result<u128> {
+    let value = value.trim();
+    if !is_digits(value) {
+        return Err(anyhow!("Invalid amount: {value}"));
+    }
+    value
+        .parse()
+        .map_err(|_| anyhow!("Amount does not fit into 128 bits: {value}"))
+}
+
+/// `1.5` with 9 decimals is `1500000000`. More fractional digits than `decimals`
+/// are an error rather than rounded away
+fn tokens_to_nano(tokens: &str, decimals: jint) -> Result<String> {
+    let decimals = parse_decimals(decimals)?;
+    let tokens = tokens.trim();
+    let (integer, fraction) = tokens.split_once('.').unwrap_or((tokens, ""));
+    if !is_digits(integer) || (tokens.contains('.') && !is_digits(fraction)) {
+        return Err(anyhow!("Invalid token amount: {tokens}"));
+    }
+    if fraction.len() > decimals as usize {
+        return Err(anyhow!(
+            "Token amount {tokens} has more than {decimals} fractional digits"
+        ));
+    }
+
+    // Right-padding the fraction to `decimals` digits makes it the nano part
+    let nano = format!("{integer}{fraction:0<width$}", width = decimals as usize);
+    Ok(nano.parse::<BigUint>()?.to_string())
+}
+
+/// `1500000000` with 9 decimals is `1.5`, without trailing fractional zeros
+fn nano_to_tokens(nano: &str, decimals: jint) -> Result<String> {
+    let decimals = parse_decimals(decimals)?;
+    let nano = nano.trim();
+    if !is_digits(nano) {
+        return Err(anyhow!("Invalid amount: {nano}"));
+    }
+    let nano: BigUint = nano.parse()?;
+
+    let scale = BigUint::from(10u8).pow(decimals);
+    let integer = &nano / &scale;
+    let fraction = (&nano % &scale).to_string();
+    let fraction = format!("{fraction:0>width$}", width = decimals as usize);
+    Ok(match fraction.trim_end_matches('0') {
+        "" => integer.to_string(),
+        fraction => format!("{integer}.{fraction}"),
+    })
+}
+
+fn amount_add(a: &str, b: &str) -> Result<String> {
+    let sum = parse_u128(a)?
+        .checked_add(parse_u128(b)?)
+        .ok_or_else(|| anyhow!("Amount overflow: {a} + {b}"))?;
+    Ok(sum.to_string())
+}
+
+fn amount_sub(a: &str, b: &str) -> Result<String> {
+    let difference = parse_u128(a)?
+        .checked_sub(parse_u128(b)?)
+        .ok_or_else(|| anyhow!("Amount underflow: {a} - {b}"))?;
+    Ok(difference.to_string())
+}
+
+/// -1, 0 or 1 as `a` is less than, equal to or greater than `b`
+fn amount_compare(a: &str, b: &str) -> Result<jint> {
+    Ok(match parse_u128(a)?.cmp(&parse_u128(b)?) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_tokensToNano(
+    mut env: JNIEnv,
+    _class: JClass,
+    tokens: JString,
+    decimals: jint,
+) -> jstring {
+    error::guard!(env, {
+        let tokens: String = match env.get_string(&tokens) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let nano = match tokens_to_nano(&tokens, decimals) {
+            Ok(nano) => nano,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(nano) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nanoToTokens(
+    mut env: JNIEnv,
+    _class: JClass,
+    nano: JString,
+    decimals: jint,
+) -> jstring {
+    error::guard!(env, {
+        let nano: String = match env.get_string(&nano) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let tokens = match nano_to_tokens(&nano, decimals) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(tokens) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_amountAdd(
+    mut env: JNIEnv,
+    _class: JClass,
+    a: JString,
+    b: JString,
+) -> jstring {
+    error::guard!(env, {
+        let a: String = match env.get_string(&a) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let b: String = match env.get_string(&b) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let sum = match amount_add(&a, &b) {
+            Ok(sum) => sum,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(sum) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_amountSub(
+    mut env: JNIEnv,
+    _class: JClass,
+    a: JString,
+    b: JString,
+) -> jstring {
+    error::guard!(env, {
+        let a: String = match env.get_string(&a) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let b: String = match env.get_string(&b) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let difference = match amount_sub(&a, &b) {
+            Ok(difference) => difference,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(difference) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_amountCompare(
+    mut env: JNIEnv,
+    _class: JClass,
+    a: JString,
+    b: JString,
+) -> jint {
+    error::guard!(env, {
+        let a: String = match env.get_string(&a) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let b: String = match env.get_string(&b) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+
+        match amount_compare(&a, &b) {
+            Ok(ordering) => ordering,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
@@ -0,0 +1,92 @@
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+
+use anyhow::{anyhow, Result};
+use futures_util::FutureExt;
+use jni::objects::{GlobalRef, JObject, JValue};
+use jni::{JNIEnv, JavaVM};
+
+use crate::error::{self, NekotonError};
+use crate::runtime;
+
+/// A Kotlin object whose methods each take a single JSON string,
+/// callable from any thread
+pub struct JavaCallback {
+    vm: JavaVM,
+    callback: GlobalRef,
+}
+
+impl JavaCallback {
+    pub fn new(env: &mut JNIEnv, callback: JObject) -> Result<Self> {
+        Ok(Self {
+            vm: env.get_java_vm()?,
+            callback: env.new_global_ref(callback)?,
+        })
+    }
+
+    /// Failures are only logged, there is nobody to report them to
+    pub fn notify(&self, method: &str, payload: String) {
+        self.call(method, |env| {
+            let payload = env.new_string(payload)?;
+            env.call_method(
+                &self.callback,
+                method,
+                "(Ljava/lang/String;)V",
+                &[JValue::Object(&payload)],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Completes a Kotlin `NativeCallback` through `onSuccess(String)`, or
+    /// `onError(Throwable)` with the exception a blocking call would have thrown
+    pub fn complete(&self, kind: NekotonError, result: Result<String>) {
+        match result {
+            Ok(payload) => self.notify("onSuccess", payload),
+            Err(e) => self.call("onError", |env| {
+                let exception = error::new_exception(env, kind, &e)?;
+                env.call_method(
+                    &self.callback,
+                    "onError",
+                    "(Ljava/lang/Throwable;)V",
+                    &[JValue::Object(&exception)],
+                )?;
+                Ok(())
+            }),
+        }
+    }
+
+    fn call<F>(&self, method: &str, f: F)
+    where
+        F: FnOnce(&mut JNIEnv) -> jni::errors::Result<()>,
+    {
+        // Already attached threads are left attached when the guard drops
+        let result = self.vm.attach_current_thread().and_then(|mut env| {
+            let result = f(&mut env);
+            // A throwing callback must not leave an exception pending for the next one
+            if env.exception_check()? {
+                env.exception_describe()?;
+                env.exception_clear()?;
+            }
+            result
+        });
+        if let Err(e) = result {
+            log::error!("Callback `{method}` failed: {e}");
+        }
+    }
+}
+
+/// Runs `future` on the shared runtime and completes `callback` with its result.
+/// A panic is reported too, so the Kotlin side never waits forever
+pub fn spawn_completing<F>(callback: JavaCallback, kind: NekotonError, future: F)
+where
+    F: Future<Output = Result<String>> + Send + 'static,
+{
+    runtime::spawn(async move {
+        let result = match AssertUnwindSafe(future).catch_unwind().await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Native panic in async call")),
+        };
+        callback.complete(kind, result);
+    });
+}
@@ -0,0 +1,939 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JByteBuffer, JClass, JLongArray, JString};
+use jni::sys::{jboolean, jbyteArray, jint, jintArray, jlong, jlongArray, jsize};
+use jni::JNIEnv;
+use num_bigint::{BigInt, BigUint, Sign};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ton_block::{MsgAddressIntOrNone, Serializable};
+use ton_types::{BagOfCells, BocSerialiseMode, BuilderData, Cell, MAX_DATA_BITS};
+
+use crate::registry::Registry;
+use crate::{error, slice};
+
+static CELLS: Lazy<Registry<Cell>> = Lazy::new(Registry::new);
+static BUILDERS: Lazy<Registry<Mutex<BuilderData>>> = Lazy::new(Registry::new);
+
+pub fn insert_cell(cell: Cell) -> jlong {
+    CELLS.insert(Arc::new(cell))
+}
+
+pub fn get_cell(handle: jlong) -> Result<Cell> {
+    Ok(CELLS.get(handle)?.as_ref().clone())
+}
+
+pub fn parse_boc(boc: &[u8]) -> Result<Cell> {
+    ton_types::deserialize_tree_of_cells(&mut &boc[..])
+}
+
+pub fn serialize_boc(cell: &Cell) -> Result<Vec<u8>> {
+    ton_types::serialize_toc(cell)
+}
+
+/// Every root of the BOC, in order. Both the indexed (v1) and generic (v2)
+/// formats are read, and the CRC is checked whenever the BOC carries one
+fn parse_boc_roots(boc: &[u8]) -> Result<Vec<Cell>> {
+    ton_types::deserialize_cells_tree(&mut &boc[..])
+}
+
+fn serialize_boc_roots(roots: &[Cell], include_index: bool, include_crc: bool) -> Result<Vec<u8>> {
+    if roots.is_empty() {
+        return Err(anyhow!("A BOC needs at least one root"));
+    }
+    let mode = BocSerialiseMode::Generic {
+        index: include_index,
+        crc: include_crc,
+        cache_bits: false,
+        flags: 0,
+    };
+    let mut boc = Vec::new();
+    BagOfCells::with_roots(roots.iter().collect()).write_to_ex(&mut boc, mode, None, None)?;
+    Ok(boc)
+}
+
+fn cells_to_boc(handles: &[jlong], include_index: bool, include_crc: bool) -> Result<Vec<u8>> {
+    let roots = handles
+        .iter()
+        .map(|handle| get_cell(*handle))
+        .collect::<Result<Vec<_>>>()?;
+    serialize_boc_roots(&roots, include_index, include_crc)
+}
+
+/// Runs `f` on the first `length` bytes of a direct `ByteBuffer`, read in place
+/// instead of being copied out of the Java heap
+pub fn with_direct_buffer<T>(
+    env: &JNIEnv,
+    buffer: &JByteBuffer,
+    length: jint,
+    f: impl FnOnce(&[u8]) -> Result<T>,
+) -> Result<T> {
+    let address = env
+        .get_direct_buffer_address(buffer)
+        .map_err(|_| anyhow!("Buffer must be a direct ByteBuffer"))?;
+    let capacity = env.get_direct_buffer_capacity(buffer)?;
+    // The buffer outlives this call, and Java must not touch it until the call returns
+    f(unsafe { buffer_prefix(address, capacity, length)? })
+}
+
+/// First `length` bytes of the `capacity` bytes at `address`.
+///
+/// # Safety
+///
+/// `address` must point to `capacity` readable bytes that stay untouched for `'a`
+unsafe fn buffer_prefix<'a>(address: *const u8, capacity: usize, length: jint) -> Result<&'a [u8]> {
+    let length = usize::try_from(length)
+        .ok()
+        .filter(|length| *length <= capacity)
+        .ok_or_else(|| anyhow!("Length {length} does not fit a buffer of {capacity} bytes"))?;
+    Ok(std::slice::from_raw_parts(address, length))
+}
+
+/// Copies `data` to the start of a direct `ByteBuffer` and returns its length.
+/// Nothing is written when `data` exceeds the capacity, so the caller can retry
+/// with a buffer of the returned size
+fn write_direct_buffer(env: &JNIEnv, buffer: &JByteBuffer, data: &[u8]) -> Result<jint> {
+    let address = env
+        .get_direct_buffer_address(buffer)
+        .map_err(|_| anyhow!("Buffer must be a direct ByteBuffer"))?;
+    let capacity = env.get_direct_buffer_capacity(buffer)?;
+    // Same contract as `with_direct_buffer`
+    unsafe { write_buffer_prefix(address, capacity, data) }
+}
+
+/// # Safety
+///
+/// `address` must point to `capacity` writable bytes that nothing else accesses meanwhile
+unsafe fn write_buffer_prefix(address: *mut u8, capacity: usize, data: &[u8]) -> Result<jint> {
+    let length = jint::try_from(data.len()).context("Data is too large for a ByteBuffer")?;
+    if data.len() <= capacity {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), address, data.len());
+    }
+    Ok(length)
+}
+
+/// New Java `long[]` holding `handles`
+pub fn new_handle_array(env: &mut JNIEnv, handles: &[jlong]) -> jlongArray {
+    let array = match env.new_long_array(handles.len() as jsize) {
+        Ok(array) => array,
+        Err(e) => {
+            error::throw(env, e.into());
+            return std::ptr::null_mut();
+        }
+    };
+    match env.set_long_array_region(&array, 0, handles) {
+        Ok(()) => array.into_raw(),
+        Err(e) => {
+            error::throw(env, e.into());
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn ensure_bits_free(builder: &BuilderData, bits: usize) -> Result<()> {
+    if builder.bits_free() < bits {
+        return Err(anyhow!(
+            "{bits} bits do not fit into the builder ({} of {MAX_DATA_BITS} bits free)",
+            builder.bits_free()
+        ));
+    }
+    Ok(())
+}
+
+fn cell_builder_store_bytes(builder_handle: jlong, data: &[u8]) -> Result<()> {
+    let builder = BUILDERS.get(builder_handle)?;
+    let mut builder = builder.lock();
+
+    let bits = data.len() * 8;
+    ensure_bits_free(&builder, bits)?;
+    builder.append_raw(data, bits)?;
+    Ok(())
+}
+
+pub fn parse_bit_width(bits: jint) -> Result<usize> {
+    match usize::try_from(bits) {
+        Ok(bits @ 1..=256) => Ok(bits),
+        _ => Err(anyhow!("Bit width must be between 1 and 256, got {bits}")),
+    }
+}
+
+fn store_bits(builder_handle: jlong, value: &BigUint, bits: usize) -> Result<()> {
+    let builder = BUILDERS.get(builder_handle)?;
+    let mut builder = builder.lock();
+    ensure_bits_free(&builder, bits)?;
+    append_bits(&mut builder, value, bits)
+}
+
+/// Appends the lowest `bits` bits of `value`, which must already fit into them
+pub fn append_bits(builder: &mut BuilderData, value: &BigUint, bits: usize) -> Result<()> {
+    // `append_raw` takes the leading bits, so the value is left-aligned in whole bytes
+    let len = bits.div_ceil(8);
+    let aligned = (value << (len * 8 - bits)).to_bytes_be();
+    let mut data = vec![0u8; len - aligned.len()];
+    data.extend_from_slice(&aligned);
+    builder.append_raw(&data, bits)?;
+    Ok(())
+}
+
+fn cell_builder_store_uint(builder_handle: jlong, value: &str, bits: jint) -> Result<()> {
+    let bits = parse_bit_width(bits)?;
+    let value: BigUint = value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid unsigned integer: {value}"))?;
+    if value.bits() > bits as u64 {
+        return Err(anyhow!("{value} does not fit into {bits} unsigned bits"));
+    }
+    store_bits(builder_handle, &value, bits)
+}
+
+fn cell_builder_store_int(builder_handle: jlong, value: &str, bits: jint) -> Result<()> {
+    let bits = parse_bit_width(bits)?;
+    let value: BigInt = value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid integer: {value}"))?;
+
+    let bound = BigInt::from(1) << (bits - 1);
+    if value < -&bound || value >= bound {
+        return Err(anyhow!("{value} does not fit into {bits} signed bits"));
+    }
+    // Two's complement of a negative value is its offset from 2^bits
+    let value = match value.sign() {
+        Sign::Minus => ((BigInt::from(1) << bits) + value).magnitude().clone(),
+        _ => value.magnitude().clone(),
+    };
+    store_bits(builder_handle, &value, bits)
+}
+
+fn cell_builder_store_bit(builder_handle: jlong, bit: bool) -> Result<()> {
+    store_bits(builder_handle, &BigUint::from(bit as u8), 1)
+}
+
+/// Stored as `VarUInteger 16`: the byte length in 4 bits followed by the value
+fn cell_builder_store_coins(builder_handle: jlong, value: &str) -> Result<()> {
+    let value: u128 = value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid amount of coins: {value}"))?;
+    let len = (128 - value.leading_zeros() as usize).div_ceil(8);
+    if len > 15 {
+        return Err(anyhow!("{value} does not fit into 120 bits of coins"));
+    }
+
+    let builder = BUILDERS.get(builder_handle)?;
+    let mut builder = builder.lock();
+    ensure_bits_free(&builder, 4 + len * 8)?;
+    append_bits(&mut builder, &BigUint::from(len), 4)?;
+    if len > 0 {
+        append_bits(&mut builder, &BigUint::from(value), len * 8)?;
+    }
+    Ok(())
+}
+
+/// Empty `address` stores `addr_none`
+fn cell_builder_store_address(builder_handle: jlong, address: &str) -> Result<()> {
+    let address = match address.is_empty() {
+        true => MsgAddressIntOrNone::None,
+        false => MsgAddressIntOrNone::Some(nekoton_utils::repack_address(address)?),
+    };
+    let mut data = BuilderData::new();
+    address.write_to(&mut data)?;
+
+    let builder = BUILDERS.get(builder_handle)?;
+    let mut builder = builder.lock();
+    ensure_bits_free(&builder, data.length_in_bits())?;
+    builder.append_builder(&data)?;
+    Ok(())
+}
+
+fn cell_builder_bits_free(builder_handle: jlong) -> Result<jint> {
+    Ok(BUILDERS.get(builder_handle)?.lock().bits_free() as jint)
+}
+
+fn cell_builder_refs_free(builder_handle: jlong) -> Result<jint> {
+    Ok(BUILDERS.get(builder_handle)?.lock().references_free() as jint)
+}
+
+/// Level 0 up to the cell's own level; for an exotic cell such as a pruned
+/// branch the lower levels hash the pruned contents. Levels above the cell's
+/// level give the representation hash
+fn get_cell_hash_at_level(cell_handle: jlong, level: jint) -> Result<Vec<u8>> {
+    let cell = get_cell(cell_handle)?;
+    let level = match u8::try_from(level) {
+        Ok(level @ 0..=3) => level,
+        _ => return Err(anyhow!("Level must be between 0 and 3, got {level}")),
+    };
+    Ok(cell.hash(level as usize).as_slice().to_vec())
+}
+
+/// Equal representation hashes mean equal trees, however the cells were built
+fn cells_equal(a_handle: jlong, b_handle: jlong) -> Result<bool> {
+    Ok(get_cell(a_handle)?.repr_hash() == get_cell(b_handle)?.repr_hash())
+}
+
+fn cell_builder_store_ref(builder_handle: jlong, cell_handle: jlong) -> Result<()> {
+    let builder = BUILDERS.get(builder_handle)?;
+    let cell = get_cell(cell_handle)?;
+    let mut builder = builder.lock();
+
+    if builder.references_free() == 0 {
+        return Err(anyhow!(
+            "Builder already holds the maximum number of references"
+        ));
+    }
+    builder.checked_append_reference(cell)?;
+    Ok(())
+}
+
+fn cell_builder_store_slice(builder_handle: jlong, slice_handle: jlong) -> Result<()> {
+    let builder = BUILDERS.get(builder_handle)?;
+    let slice = slice::get_slice(slice_handle)?;
+    let slice = slice.lock();
+    let mut builder = builder.lock();
+
+    if builder.bits_free() < slice.remaining_bits()
+        || builder.references_free() < slice.remaining_references()
+    {
+        return Err(anyhow!(
+            "Slice of {} bits and {} refs does not fit into the builder ({} bits and {} refs free)",
+            slice.remaining_bits(),
+            slice.remaining_references(),
+            builder.bits_free(),
+            builder.references_free()
+        ));
+    }
+    builder.checked_append_references_and_data(&slice)?;
+    Ok(())
+}
+
+fn cell_builder_build(builder_handle: jlong) -> Result<jlong> {
+    let builder = BUILDERS.get(builder_handle)?;
+    let cell = builder.lock().clone().into_cell()?;
+    Ok(insert_cell(cell))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellFromBoc(
+    mut env: JNIEnv,
+    _class: JClass,
+    boc_bytes: JByteArray,
+) -> jlong {
+    error::guard!(env, {
+        let boc = match env.convert_byte_array(boc_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match parse_boc(&boc) {
+            Ok(cell) => insert_cell(cell),
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellToBoc(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let boc = match get_cell(cell_handle).and_then(|cell| serialize_boc(&cell)) {
+            Ok(boc) => boc,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&boc) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `cellFromBoc` reading the first `length` bytes of a direct `ByteBuffer`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellFromBocBuffer(
+    mut env: JNIEnv,
+    _class: JClass,
+    boc_buffer: JByteBuffer,
+    length: jint,
+) -> jlong {
+    error::guard!(env, {
+        match with_direct_buffer(&env, &boc_buffer, length, parse_boc) {
+            Ok(cell) => insert_cell(cell),
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+/// `cellToBoc` writing into a direct `ByteBuffer`, see `write_direct_buffer`
+/// for the returned size
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellToBocBuffer(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+    boc_buffer: JByteBuffer,
+) -> jint {
+    error::guard!(env, {
+        let written = get_cell(cell_handle)
+            .and_then(|cell| serialize_boc(&cell))
+            .and_then(|boc| write_direct_buffer(&env, &boc_buffer, &boc));
+        match written {
+            Ok(length) => length,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+/// Returns one cell handle per root
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellsFromBoc(
+    mut env: JNIEnv,
+    _class: JClass,
+    boc_bytes: JByteArray,
+) -> jlongArray {
+    error::guard!(env, {
+        let boc = match env.convert_byte_array(boc_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let handles = match parse_boc_roots(&boc) {
+            Ok(roots) => roots.into_iter().map(insert_cell).collect::<Vec<_>>(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        new_handle_array(&mut env, &handles)
+    })
+}
+
+/// Serializes the cells as the roots of one BOC, in the given order.
+/// `cellToBoc` is the same with a single root, no index and a CRC
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellsToBoc(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handles: JLongArray,
+    include_index: jboolean,
+    include_crc: jboolean,
+) -> jbyteArray {
+    error::guard!(env, {
+        let len = match env.get_array_length(&cell_handles) {
+            Ok(len) => len as usize,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let mut handles = vec![0; len];
+        if env
+            .get_long_array_region(&cell_handles, 0, &mut handles)
+            .is_err()
+        {
+            return std::ptr::null_mut();
+        }
+
+        let boc = match cells_to_boc(&handles, include_index != 0, include_crc != 0) {
+            Ok(boc) => boc,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&boc) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellHash(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let hash = match get_cell(cell_handle) {
+            Ok(cell) => cell.repr_hash(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(hash.as_slice()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellHashAtLevel(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+    level: jint,
+) -> jbyteArray {
+    error::guard!(env, {
+        let hash = match get_cell_hash_at_level(cell_handle, level) {
+            Ok(hash) => hash,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&hash) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// 0 for ordinary cells, up to 3 for exotic ones
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellLevel(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match get_cell(cell_handle) {
+            Ok(cell) => jint::from(cell.level()),
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+/// Longest path from the cell down to a leaf, 0 for a cell without refs
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellDepth(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match get_cell(cell_handle) {
+            Ok(cell) => jint::from(cell.repr_depth()),
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+/// `[bits, refs]` of the cell's own data, without its children
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellBitsAndRefs(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jintArray {
+    error::guard!(env, {
+        let cell = match get_cell(cell_handle) {
+            Ok(cell) => cell,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let values = [cell.bit_length() as jint, cell.references_count() as jint];
+        let array = match env.new_int_array(values.len() as jsize) {
+            Ok(array) => array,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        match env.set_int_array_region(&array, 0, &values) {
+            Ok(()) => array.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellsEqual(
+    mut env: JNIEnv,
+    _class: JClass,
+    a_handle: jlong,
+    b_handle: jlong,
+) -> jboolean {
+    error::guard!(env, {
+        match cells_equal(a_handle, b_handle) {
+            Ok(equal) => equal as jboolean,
+            Err(e) => {
+                error::throw(&mut env, e);
+                false as jboolean
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createCellBuilder(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jlong {
+    error::guard!(env, {
+        BUILDERS.insert(Arc::new(Mutex::new(BuilderData::new())))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreBytes(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    data: JByteArray,
+) -> jboolean {
+    error::guard!(env, {
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        match cell_builder_store_bytes(builder_handle, &data) {
+            Ok(()) => true as jboolean,
+            Err(e) => {
+                error::throw(&mut env, e);
+                false as jboolean
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreUint(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    value: JString,
+    bits: jint,
+) {
+    error::guard!(env, {
+        let value: String = match env.get_string(&value) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = cell_builder_store_uint(builder_handle, &value, bits) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreInt(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    value: JString,
+    bits: jint,
+) {
+    error::guard!(env, {
+        let value: String = match env.get_string(&value) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = cell_builder_store_int(builder_handle, &value, bits) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreBit(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    bit: jboolean,
+) {
+    error::guard!(env, {
+        if let Err(e) = cell_builder_store_bit(builder_handle, bit != 0) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreCoins(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    value: JString,
+) {
+    error::guard!(env, {
+        let value: String = match env.get_string(&value) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = cell_builder_store_coins(builder_handle, &value) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    address: JString,
+) {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = cell_builder_store_address(builder_handle, &address) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderBitsFree(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match cell_builder_bits_free(builder_handle) {
+            Ok(bits) => bits,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderRefsFree(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match cell_builder_refs_free(builder_handle) {
+            Ok(refs) => refs,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreRef(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    cell_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = cell_builder_store_ref(builder_handle, cell_handle) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreSlice(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+    slice_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = cell_builder_store_slice(builder_handle, slice_handle) {
+            error::throw(&mut env, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderBuild(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match cell_builder_build(builder_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupCell(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = CELLS.remove(cell_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupCellBuilder(
+    mut env: JNIEnv,
+    _class: JClass,
+    builder_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = BUILDERS.remove(builder_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_builder() -> jlong {
+        BUILDERS.insert(Arc::new(Mutex::new(BuilderData::new())))
+    }
+
+    fn cell_of(data: &[u8], references: &[Cell]) -> Cell {
+        let mut builder = BuilderData::new();
+        builder.append_raw(data, data.len() * 8).unwrap();
+        for reference in references {
+            builder.checked_append_reference(reference.clone()).unwrap();
+        }
+        builder.into_cell().unwrap()
+    }
+
+    #[test]
+    fn stored_slice_keeps_only_its_remaining_bits_and_refs() {
+        let first = cell_of(&[0x01], &[]);
+        let second = cell_of(&[0x02], &[]);
+        let source = insert_cell(cell_of(&[0xAB, 0xCD, 0xEF], &[first, second.clone()]));
+
+        let slice = slice::insert_slice(SliceData::load_cell(get_cell(source).unwrap()).unwrap());
+        {
+            let slice = slice::get_slice(slice).unwrap();
+            let mut slice = slice.lock();
+            slice.get_next_bits(8).unwrap();
+            slice.checked_drain_reference().unwrap();
+        }
+
+        let builder = new_builder();
+        cell_builder_store_bytes(builder, &[0x12]).unwrap();
+        cell_builder_store_slice(builder, slice).unwrap();
+        let built = get_cell(cell_builder_build(builder).unwrap()).unwrap();
+
+        assert_eq!(
+            built.repr_hash(),
+            cell_of(&[0x12, 0xCD, 0xEF], &[second]).repr_hash()
+        );
+        // The slice is copied, not consumed
+        assert_eq!(slice::get_slice(slice).unwrap().lock().remaining_bits(), 16);
+    }
+
+    #[test]
+    fn slice_that_does_not_fit_leaves_the_builder_untouched() {
+        let bits = slice::insert_slice(SliceData::load_cell(cell_of(&[0xFF; 100], &[])).unwrap());
+        let builder = new_builder();
+        cell_builder_store_bytes(builder, &[0; 28]).unwrap();
+        assert!(cell_builder_store_slice(builder, bits).is_err());
+        assert_eq!(cell_builder_bits_free(builder).unwrap(), 1023 - 28 * 8);
+
+        let reference = cell_of(&[], &[]);
+        let refs =
+            slice::insert_slice(SliceData::load_cell(cell_of(&[], &[reference.clone()])).unwrap());
+        let builder = new_builder();
+        for _ in 0..4 {
+            cell_builder_store_ref(builder, insert_cell(reference.clone())).unwrap();
+        }
+        assert!(cell_builder_store_slice(builder, refs).is_err());
+        assert_eq!(cell_builder_refs_free(builder).unwrap(), 0);
+    }
+
+    #[test]
+    fn buffer_prefix_stays_within_the_capacity() {
+        let buffer = [1u8, 2, 3, 4];
+        let prefix = |length| unsafe { buffer_prefix(buffer.as_ptr(), buffer.len(), length) };
+        assert_eq!(prefix(2).unwrap(), [1, 2]);
+        assert_eq!(prefix(4).unwrap(), buffer);
+        assert!(prefix(0).unwrap().is_empty());
+        assert!(prefix(5).is_err());
+        assert!(prefix(-1).is_err());
+    }
+
+    #[test]
+    fn too_small_buffer_is_left_untouched_but_sized() {
+        let mut buffer = [0u8; 4];
+        let written = unsafe { write_buffer_prefix(buffer.as_mut_ptr(), buffer.len(), &[7; 3]) };
+        assert_eq!(written.unwrap(), 3);
+        assert_eq!(buffer, [7, 7, 7, 0]);
+
+        let mut buffer = [0u8; 2];
+        let written = unsafe { write_buffer_prefix(buffer.as_mut_ptr(), buffer.len(), &[7; 3]) };
+        assert_eq!(written.unwrap(), 3);
+        assert_eq!(buffer, [0, 0]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use jni::objects::JClass;
+use jni::sys::jlong;
+use jni::JNIEnv;
+use nekoton::transport::models::RawContractState;
+use nekoton_abi::GenTimings;
+use nekoton_utils::{Clock, ClockWithOffset};
+use once_cell::sync::Lazy;
+
+use crate::error::{self, NekotonError};
+use crate::{runtime, transport};
+
+/// Every message header and executor run reads time from here, so a single
+/// `setClockOffset` corrects all of them on devices with a skewed clock
+pub static CLOCK: Lazy<Arc<ClockWithOffset>> = Lazy::new(Default::default);
+
+// The config contract exists on every network and is cheap to fetch
+const CONFIG_ADDRESS: &str = "-1:5555555555555555555555555555555555555555555555555555555555555555";
+
+/// Milliseconds to add to the local clock to match the node, from the
+/// generation time of the state it returns. Only accurate to about a second
+fn get_server_time_delta(transport_handle: jlong) -> Result<jlong> {
+    let transport = transport::get_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(CONFIG_ADDRESS)?;
+    let timings = match runtime::block_on(transport.get_contract_state(&address))? {
+        RawContractState::Exists(contract) => contract.timings,
+        RawContractState::NotExists { timings } => timings,
+    };
+    match timings {
+        GenTimings::Known { gen_utime, .. } => {
+            let local_ms = nekoton_utils::SimpleClock.now_ms_u64() as i64;
+            Ok(i64::from(gen_utime) * 1000 - local_ms)
+        }
+        GenTimings::Unknown => Err(anyhow!("Transport does not report generation time")),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setClockOffset(
+    mut env: JNIEnv,
+    _class: JClass,
+    offset_ms: jlong,
+) {
+    error::guard!(env, {
+        CLOCK.update_offset(offset_ms);
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getServerTimeDelta(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match get_server_time_delta(transport_handle) {
+            Ok(delta) => delta,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
@@ -0,0 +1,589 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::transport::models::RawContractState;
+use nekoton_abi::{ExecutionOutput, FunctionExt};
+use nekoton_utils::Clock;
+use serde_json::{json, Value};
+use ton_abi::{Contract, Function, Token, TokenValue};
+use ton_block::{
+    Account, AccountState, AccountStuff, Deserializable, ExternalInboundMessageHeader, Message,
+    MsgAddressInt, Serializable,
+};
+use ton_types::SliceData;
+use zeroize::Zeroize;
+
+use crate::error::{self, ErrorCode, NekotonError};
+use crate::{abi, cell, clock, crypto, executor, models, runtime, transport};
+
+struct CallParams<'a> {
+    address: &'a str,
+    function_name: &'a str,
+    inputs: &'a str,
+    secret: &'a [u8],
+    headers: &'a str,
+    expire_sec: jint,
+    signature_id: jlong,
+    wait_timeout_ms: jlong,
+}
+
+/// Unsigned body of an external call along with the hash to sign and its expiration.
+/// `headers` holds the values of any header the ABI declares; `pubkey`, `time`
+/// and `expire` default to `public_key`, now and `expire_sec` from now
+pub fn encode_call(
+    function: &Function,
+    address: &MsgAddressInt,
+    headers: &str,
+    inputs: &str,
+    public_key: Option<ed25519_dalek::PublicKey>,
+    expire_sec: jint,
+) -> Result<(ton_types::BuilderData, Vec<u8>, u32)> {
+    let expire_sec = u32::try_from(expire_sec).context("Invalid expiration")?;
+    let now_ms = clock::CLOCK.now_ms_u64();
+
+    let mut header = abi::parse_headers(&function.header, headers)?;
+    for param in &function.header {
+        let value = match param.name.as_str() {
+            "pubkey" => TokenValue::PublicKey(public_key),
+            "time" => TokenValue::Time(now_ms),
+            "expire" => TokenValue::Expire((now_ms / 1000) as u32 + expire_sec),
+            _ => continue,
+        };
+        header.entry(param.name.clone()).or_insert(value);
+    }
+    let expire_at = match header.get("expire") {
+        Some(TokenValue::Expire(expire_at)) => *expire_at,
+        _ => (now_ms / 1000) as u32 + expire_sec,
+    };
+
+    let tokens = abi::parse_tokens(&function.inputs, inputs)?;
+    let (body, hash) =
+        function.create_unsigned_call(&header, &tokens, false, true, Some(address.clone()))?;
+    Ok((body, hash, expire_at))
+}
+
+/// Encodes, signs and sends an external call, then waits for the transaction
+/// it produced and decodes the function output from its external out messages
+fn call_contract(transport_handle: jlong, abi_handle: jlong, params: CallParams) -> Result<String> {
+    let transport = transport::get_transport(transport_handle)?;
+    let contract = abi::get_abi(abi_handle)?;
+
+    let (function, address, keypair, (body, hash, _)) = (|| {
+        let function = contract.function(params.function_name)?;
+        let address = nekoton_utils::repack_address(params.address)?;
+        let keypair = crypto::keypair_from_secret(params.secret)?;
+        let unsigned = encode_call(
+            function,
+            &address,
+            params.headers,
+            params.inputs,
+            Some(keypair.public),
+            params.expire_sec,
+        )?;
+        Ok((function, address, keypair, unsigned))
+    })()
+    .context(ErrorCode::Encode)?;
+
+    let body = (|| {
+        let signature_id = crypto::parse_signature_id(params.signature_id, transport_handle)?;
+        let signature = crypto::sign(&keypair, &hash, signature_id);
+        Function::fill_sign(
+            &contract.abi_version,
+            Some(&signature),
+            Some(keypair.public.as_bytes()),
+            body,
+        )
+    })()
+    .context(ErrorCode::Sign)?;
+
+    let mut message = Message::with_ext_in_header(ExternalInboundMessageHeader {
+        dst: address,
+        ..Default::default()
+    });
+    message.set_body(SliceData::load_builder(body).context(ErrorCode::Encode)?);
+    let message_hash = message.serialize().context(ErrorCode::Encode)?.repr_hash();
+
+    runtime::block_on(transport.send_message(&message)).context(ErrorCode::Send)?;
+
+    let wait_timeout = u64::try_from(params.wait_timeout_ms)
+        .map(Duration::from_millis)
+        .context(ErrorCode::Timeout)?;
+    let transaction = runtime::block_on(transport::wait_for_transaction(
+        transport.as_ref(),
+        &message_hash,
+        wait_timeout,
+    ))
+    .and_then(|transaction| {
+        transaction.ok_or_else(|| {
+            anyhow!(
+                "No transaction for message {} within {} ms",
+                message_hash.to_hex_string(),
+                params.wait_timeout_ms
+            )
+        })
+    })
+    .context(ErrorCode::Timeout)?;
+
+    let description = transaction.data.read_description()?;
+    if description.is_aborted() {
+        return Err(anyhow!(executor::failure_reason(&description))).context(ErrorCode::Revert);
+    }
+
+    let mut output = Value::Null;
+    transaction.data.out_msgs.iterate_slices(|slice| {
+        let message = Message::construct_from_cell(slice.reference(0)?)?;
+        if let (true, Some(body)) = (message.is_outbound_external(), message.body()) {
+            if function.is_my_output_message(body.clone(), false)? {
+                let tokens = function.decode_output(body, false)?;
+                output = nekoton_abi::make_abi_tokens(&tokens)?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    })?;
+
+    Ok(json!({
+        "messageHash": message_hash.to_hex_string(),
+        "transaction": models::transaction_to_json(&transaction.hash, &transaction.data)?,
+        "output": output,
+    })
+    .to_string())
+}
+
+fn getter<'a>(contract: &'a Contract, function_name: &str) -> Result<&'a Function> {
+    let function = contract.function(function_name)?;
+    if function.outputs.is_empty() {
+        return Err(anyhow!(
+            "Function `{function_name}` has no outputs and is not a getter"
+        ));
+    }
+    Ok(function)
+}
+
+fn run_getter(
+    function: &Function,
+    account: AccountStuff,
+    inputs: &[Token],
+    responsible: bool,
+) -> Result<ExecutionOutput> {
+    match responsible {
+        true => function.run_local_responsible(clock::CLOCK.as_ref(), account, inputs),
+        false => function.run_local(clock::CLOCK.as_ref(), account, inputs),
+    }
+}
+
+/// Runs a getter against a serialized account without touching the network.
+/// A failed execution is reported through `exitCode` with a null `output`
+fn run_local_on_account(
+    abi_handle: jlong,
+    function_name: &str,
+    account_boc: &[u8],
+    inputs: &str,
+    responsible: bool,
+) -> Result<String> {
+    let contract = abi::get_abi(abi_handle)?;
+    let function = getter(&contract, function_name)?;
+    let inputs = abi::parse_tokens(&function.inputs, inputs)?;
+
+    let account = match Account::construct_from_cell(cell::parse_boc(account_boc)?)? {
+        Account::Account(account) => account,
+        Account::AccountNone => return Err(anyhow!("Account does not exist")),
+    };
+    if !matches!(account.storage.state, AccountState::AccountActive { .. }) {
+        return Err(anyhow!("Account is not deployed"));
+    }
+
+    let output = run_getter(function, account, &inputs, responsible)?;
+    let tokens = match (output.result_code, output.tokens) {
+        (0, Some(tokens)) => nekoton_abi::make_abi_tokens(&tokens)?,
+        _ => Value::Null,
+    };
+    Ok(json!({
+        "exitCode": output.result_code,
+        "output": tokens,
+    })
+    .to_string())
+}
+
+/// Latest state of a deployed account
+pub fn get_active_account(
+    transport_handle: jlong,
+    address: &MsgAddressInt,
+) -> Result<AccountStuff> {
+    let transport = transport::get_transport(transport_handle)?;
+    let state = runtime::block_on(transport.get_contract_state(address))
+        .context(NekotonError::Transport)?;
+    let account = match state {
+        RawContractState::Exists(contract) => contract.account,
+        RawContractState::NotExists { .. } => {
+            return Err(anyhow!("Account {address} does not exist"))
+        }
+    };
+    if !matches!(account.storage.state, AccountState::AccountActive { .. }) {
+        return Err(anyhow!("Account {address} is not deployed"));
+    }
+    Ok(account)
+}
+
+/// Output of a getter that must succeed. Responsible getters are recognized
+/// by their leading `answerId` input
+pub fn call_getter(
+    function: &Function,
+    account: AccountStuff,
+    inputs: &[Token],
+) -> Result<Vec<Token>> {
+    let responsible = matches!(function.inputs.first(), Some(param) if param.name == "answerId");
+    let output = run_getter(function, account, inputs, responsible)?;
+    if output.result_code != 0 {
+        return Err(anyhow!(
+            "Getter `{}` failed with exit code {}",
+            function.name,
+            output.result_code
+        ));
+    }
+    output
+        .tokens
+        .ok_or_else(|| anyhow!("Getter `{}` produced no output", function.name))
+}
+
+/// Runs a getter against the latest account state without sending anything
+fn run_local(
+    transport_handle: jlong,
+    abi_handle: jlong,
+    function_name: &str,
+    address: &str,
+    inputs: &str,
+) -> Result<String> {
+    let contract = abi::get_abi(abi_handle)?;
+    let function = getter(&contract, function_name)?;
+    let inputs = abi::parse_tokens(&function.inputs, inputs)?;
+
+    let address = nekoton_utils::repack_address(address)?;
+    let account = get_active_account(transport_handle, &address)?;
+    let tokens = call_getter(function, account, &inputs)?;
+    Ok(nekoton_abi::make_abi_tokens(&tokens)?.to_string())
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_callContract(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    abi_handle: jlong,
+    function_name: JString,
+    inputs_json: JString,
+    secret_bytes: JByteArray,
+    headers_json: JString,
+    expire_sec: jint,
+    signature_id: jlong,
+    wait_timeout_ms: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let headers: String = match env.get_string(&headers_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let params = CallParams {
+            address: &address,
+            function_name: &function_name,
+            inputs: &inputs,
+            secret: &secret,
+            headers: &headers,
+            expire_sec,
+            signature_id,
+            wait_timeout_ms,
+        };
+        let result = call_contract(transport_handle, abi_handle, params);
+        secret.zeroize();
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_runLocal(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    abi_handle: jlong,
+    function_name: JString,
+    address: JString,
+    inputs_json: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match run_local(
+            transport_handle,
+            abi_handle,
+            &function_name,
+            &address,
+            &inputs,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `runLocal` against a serialized account instead of the network state
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_runLocalOnAccount(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    account_state_boc: JByteArray,
+    inputs_json: JString,
+    responsible: jboolean,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let account_boc = match env.convert_byte_array(account_state_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match run_local_on_account(
+            abi_handle,
+            &function_name,
+            &account_boc,
+            &inputs,
+            responsible != 0,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_block::{
+        AccountStatus, ExtOutMessageHeader, MsgAddressExt, TrComputePhase, TrComputePhaseVm,
+        Transaction, TransactionDescr, TransactionDescrOrdinary,
+    };
+
+    use super::*;
+    use crate::transport::mock;
+
+    const ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    const COUNTER_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["time", "expire"],
+        "functions": [
+            {
+                "name": "setValue",
+                "inputs": [{"name": "value", "type": "uint32"}],
+                "outputs": [{"name": "previous", "type": "uint32"}]
+            }
+        ],
+        "data": [],
+        "events": []
+    }"#;
+
+    fn reply(compute_ph: TrComputePhase, aborted: bool, output: Option<Message>) -> String {
+        let address = nekoton_utils::repack_address(ADDRESS).unwrap();
+        let mut transaction =
+            Transaction::with_address_and_status(address.address(), AccountStatus::AccStateActive);
+        transaction.set_logical_time(1);
+        transaction
+            .write_description(&TransactionDescr::Ordinary(TransactionDescrOrdinary {
+                aborted,
+                compute_ph,
+                ..Default::default()
+            }))
+            .unwrap();
+        if let Some(output) = output {
+            transaction.add_out_message(&output).unwrap();
+        }
+        base64::encode(cell::serialize_boc(&transaction.serialize().unwrap()).unwrap())
+    }
+
+    fn succeeded() -> TrComputePhase {
+        TrComputePhase::Vm(TrComputePhaseVm {
+            success: true,
+            ..Default::default()
+        })
+    }
+
+    fn call(fixtures: Value, inputs: Value, wait_timeout_ms: jlong) -> Result<Value> {
+        let transport = mock::create_mock_transport(&fixtures.to_string()).unwrap();
+        let abi = abi::parse_abi(COUNTER_ABI).unwrap();
+        let params = CallParams {
+            address: ADDRESS,
+            function_name: "setValue",
+            inputs: &inputs.to_string(),
+            secret: &[1; 32],
+            headers: "",
+            expire_sec: 60,
+            signature_id: -1,
+            wait_timeout_ms,
+        };
+        let result = call_contract(transport, abi, params)?;
+        Ok(serde_json::from_str(&result).unwrap())
+    }
+
+    fn failed_stage(result: Result<Value>) -> Option<ErrorCode> {
+        result.unwrap_err().downcast_ref::<ErrorCode>().copied()
+    }
+
+    #[test]
+    fn call_decodes_output_of_the_reply() {
+        let contract = Contract::load(COUNTER_ABI.as_bytes()).unwrap();
+        let function = contract.function("setValue").unwrap();
+        let previous = [Token::new(
+            "previous",
+            TokenValue::Uint(ton_abi::Uint::new(7, 32)),
+        )];
+        let body = function
+            .encode_internal_output(function.get_output_id(), &previous)
+            .unwrap();
+        let mut output = Message::with_ext_out_header(ExtOutMessageHeader::with_addresses(
+            nekoton_utils::repack_address(ADDRESS).unwrap(),
+            MsgAddressExt::AddrNone,
+        ));
+        output.set_body(SliceData::load_builder(body).unwrap());
+
+        let fixtures = json!({ "replies": { ADDRESS: reply(succeeded(), false, Some(output)) } });
+        let result = call(fixtures, json!({ "value": 8 }), 0).unwrap();
+        assert_eq!(result["output"]["previous"], "7");
+        assert_eq!(result["transaction"]["aborted"], false);
+    }
+
+    #[test]
+    fn each_failed_stage_is_reported_with_its_code() {
+        let inputs = json!({ "value": 8 });
+
+        let unknown_input = call(json!({}), json!({ "value": 8, "extra": 1 }), 0);
+        assert_eq!(failed_stage(unknown_input), Some(ErrorCode::Encode));
+
+        let node_down = json!({ "failures": { "sendMessage": "Node is down" } });
+        assert_eq!(
+            failed_stage(call(node_down, inputs.clone(), 0)),
+            Some(ErrorCode::Send)
+        );
+
+        assert_eq!(
+            failed_stage(call(json!({}), inputs.clone(), 0)),
+            Some(ErrorCode::Timeout)
+        );
+
+        let reverted = TrComputePhase::Vm(TrComputePhaseVm {
+            success: false,
+            exit_code: 100,
+            ..Default::default()
+        });
+        let fixtures = json!({ "replies": { ADDRESS: reply(reverted, true, None) } });
+        let error = call(fixtures, inputs, 0).unwrap_err();
+        assert_eq!(error.downcast_ref::<ErrorCode>(), Some(&ErrorCode::Revert));
+        assert!(format!("{error:#}").contains("exit code 100"));
+    }
+}
@@ -0,0 +1,602 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use jni::objects::{JByteArray, JClass};
+use jni::sys::{jboolean, jbyteArray, jlong, jstring};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use serde_json::json;
+use zeroize::Zeroize;
+
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+use crate::transport;
+
+/// Passed as `signature_id` to use the id of the network behind the
+/// `transport_handle` passed along with it
+pub const NETWORK_SIGNATURE_ID: jlong = jlong::MIN;
+
+// The secret half of a keypair is wiped by ed25519-dalek once the last reference is dropped
+static SIGNERS: Lazy<Registry<Keypair>> = Lazy::new(Registry::new);
+
+pub fn keypair_from_secret(secret: &[u8]) -> Result<Keypair> {
+    if secret.len() != ed25519_dalek::SECRET_KEY_LENGTH {
+        return Err(anyhow!(
+            "Secret key must be {} bytes, got {}",
+            ed25519_dalek::SECRET_KEY_LENGTH,
+            secret.len()
+        )
+        .context(NekotonError::Crypto));
+    }
+    let secret = SecretKey::from_bytes(secret).context(NekotonError::Crypto)?;
+    let public = PublicKey::from(&secret);
+    Ok(Keypair { secret, public })
+}
+
+/// 32-byte secret followed by the 32-byte public key
+fn generate_key_pair() -> Result<Vec<u8>> {
+    let mut secret = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+    rand::rngs::OsRng.try_fill_bytes(&mut secret)?;
+    let keypair = keypair_from_secret(&secret);
+    secret.zeroize();
+    Ok(keypair?.to_bytes().to_vec())
+}
+
+fn public_key_from_secret(secret: &[u8]) -> Result<[u8; 32]> {
+    Ok(keypair_from_secret(secret)?.public.to_bytes())
+}
+
+/// Any other negative `signature_id` means the network has no signature id capability.
+/// `transport_handle` is only looked at for `NETWORK_SIGNATURE_ID`
+pub fn parse_signature_id(signature_id: jlong, transport_handle: jlong) -> Result<Option<i32>> {
+    if signature_id == NETWORK_SIGNATURE_ID {
+        return transport::network_signature_id(transport_handle);
+    }
+    if signature_id < 0 {
+        return Ok(None);
+    }
+    i32::try_from(signature_id)
+        .map(Some)
+        .map_err(|_| anyhow!("Signature id out of range: {signature_id}"))
+}
+
+/// Same preimage as nekoton: the big-endian signature id followed by the data
+pub fn extend_with_signature_id(data: &[u8], signature_id: Option<i32>) -> Cow<'_, [u8]> {
+    match signature_id {
+        Some(signature_id) => {
+            let mut extended = Vec::with_capacity(4 + data.len());
+            extended.extend_from_slice(&signature_id.to_be_bytes());
+            extended.extend_from_slice(data);
+            Cow::Owned(extended)
+        }
+        None => Cow::Borrowed(data),
+    }
+}
+
+pub fn sign(keypair: &Keypair, data: &[u8], signature_id: Option<i32>) -> [u8; 64] {
+    keypair
+        .sign(&extend_with_signature_id(data, signature_id))
+        .to_bytes()
+}
+
+fn sign_data(
+    secret: &[u8],
+    data: &[u8],
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> Result<[u8; 64]> {
+    let keypair = keypair_from_secret(secret)?;
+    Ok(sign(
+        &keypair,
+        data,
+        parse_signature_id(signature_id, transport_handle)?,
+    ))
+}
+
+/// Any malformed input is reported as an invalid signature
+fn verify_signature(
+    public_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> bool {
+    let (Ok(public_key), Ok(signature), Ok(signature_id)) = (
+        PublicKey::from_bytes(public_key),
+        Signature::try_from(signature),
+        parse_signature_id(signature_id, transport_handle),
+    ) else {
+        return false;
+    };
+    public_key
+        .verify(&extend_with_signature_id(data, signature_id), &signature)
+        .is_ok()
+}
+
+fn create_signer(mut secret: Vec<u8>) -> Result<jlong> {
+    let keypair = keypair_from_secret(&secret);
+    secret.zeroize();
+    Ok(SIGNERS.insert(Arc::new(keypair?)))
+}
+
+fn signer_sign(
+    signer_handle: jlong,
+    data: &[u8],
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> Result<[u8; 64]> {
+    let keypair = SIGNERS.get(signer_handle)?;
+    Ok(sign(
+        &keypair,
+        data,
+        parse_signature_id(signature_id, transport_handle)?,
+    ))
+}
+
+fn signer_public_key(signer_handle: jlong) -> Result<[u8; 32]> {
+    Ok(SIGNERS.get(signer_handle)?.public.to_bytes())
+}
+
+/// `signedHash` is the exact bytes passed to ed25519, i.e. the data with the
+/// signature id prefix if any
+fn sign_detached(
+    secret: &[u8],
+    data: &[u8],
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> Result<String> {
+    let keypair = keypair_from_secret(secret)?;
+    let signature_id = parse_signature_id(signature_id, transport_handle)?;
+    let signed = extend_with_signature_id(data, signature_id);
+    let signature = keypair.sign(&signed);
+
+    Ok(json!({
+        "signature": hex::encode(signature.to_bytes()),
+        "publicKey": hex::encode(keypair.public.as_bytes()),
+        "signedHash": hex::encode(&signed),
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_generateKeyPair(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jbyteArray {
+    error::guard!(env, {
+        let mut keypair = match generate_key_pair() {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&keypair) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        keypair.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_publicKeyFromSecret(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = public_key_from_secret(&secret);
+        secret.zeroize();
+
+        let public_key = match public_key {
+            Ok(public_key) => public_key,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&public_key) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signData(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+    data: JByteArray,
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                secret.zeroize();
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let signature = sign_data(&secret, &data, signature_id, transport_handle);
+        secret.zeroize();
+
+        let signature = match signature {
+            Ok(signature) => signature,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&signature) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_verifySignature(
+    mut env: JNIEnv,
+    _class: JClass,
+    public_bytes: JByteArray,
+    data: JByteArray,
+    signature_bytes: JByteArray,
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> jboolean {
+    error::guard!(env, {
+        let public_key = match env.convert_byte_array(public_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        let signature = match env.convert_byte_array(signature_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        verify_signature(
+            &public_key,
+            &data,
+            &signature,
+            signature_id,
+            transport_handle,
+        ) as jboolean
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signDetached(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+    data: JByteArray,
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                secret.zeroize();
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let result = sign_detached(&secret, &data, signature_id, transport_handle);
+        secret.zeroize();
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createSigner(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+) -> jlong {
+    error::guard!(env, {
+        let secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_signer(secret) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signerSign(
+    mut env: JNIEnv,
+    _class: JClass,
+    signer_handle: jlong,
+    data: JByteArray,
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let signature = match signer_sign(signer_handle, &data, signature_id, transport_handle) {
+            Ok(signature) => signature,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&signature) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signerPublicKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    signer_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let public_key = match signer_public_key(signer_handle) {
+            Ok(public_key) => public_key,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&public_key) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_destroySigner(
+    mut env: JNIEnv,
+    _class: JClass,
+    signer_handle: jlong,
+) {
+    error::guard!(env, {
+        // Destroying twice is harmless; only using a destroyed signer throws
+        let _ = SIGNERS.remove(signer_handle);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [7; 32];
+
+    #[test]
+    fn signer_signs_like_sign_data() {
+        let handle = create_signer(SECRET.to_vec()).unwrap();
+        for signature_id in [-1, 42] {
+            assert_eq!(
+                signer_sign(handle, b"payload", signature_id, 0).unwrap(),
+                sign_data(&SECRET, b"payload", signature_id, 0).unwrap()
+            );
+        }
+        assert_eq!(
+            signer_public_key(handle).unwrap(),
+            public_key_from_secret(&SECRET).unwrap()
+        );
+    }
+
+    #[test]
+    fn detached_signature_verifies_over_its_signed_hash() {
+        let public_key = public_key_from_secret(&SECRET).unwrap();
+        for signature_id in [-1, 42] {
+            let detached = sign_detached(&SECRET, b"payload", signature_id, 0).unwrap();
+            let detached: serde_json::Value = serde_json::from_str(&detached).unwrap();
+            let signature = hex::decode(detached["signature"].as_str().unwrap()).unwrap();
+            let signed = hex::decode(detached["signedHash"].as_str().unwrap()).unwrap();
+
+            assert_eq!(detached["publicKey"], hex::encode(public_key));
+            assert_eq!(
+                signed,
+                extend_with_signature_id(b"payload", parse_signature_id(signature_id, 0).unwrap())
+                    .as_ref()
+            );
+            assert!(verify_signature(
+                &public_key,
+                b"payload",
+                &signature,
+                signature_id,
+                0
+            ));
+            // The preimage verifies on its own, without a signature id
+            assert!(verify_signature(&public_key, &signed, &signature, -1, 0));
+        }
+    }
+
+    #[test]
+    fn destroyed_signer_throws_on_use_only() {
+        let handle = create_signer(SECRET.to_vec()).unwrap();
+        assert!(SIGNERS.remove(handle).is_ok());
+        assert!(SIGNERS.remove(handle).is_err());
+        assert!(signer_sign(handle, b"payload", -1, 0).is_err());
+        assert!(signer_public_key(handle).is_err());
+    }
+
+    // RFC 8032, test 1
+    const RFC_SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const RFC_PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+    const RFC_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+    #[test]
+    fn signatures_match_the_rfc_vector() {
+        let secret = hex::decode(RFC_SECRET).unwrap();
+        assert_eq!(
+            hex::encode(public_key_from_secret(&secret).unwrap()),
+            RFC_PUBLIC
+        );
+        let signature = sign_data(&secret, b"", -1, 0).unwrap();
+        assert_eq!(hex::encode(signature), RFC_SIGNATURE);
+        assert!(verify_signature(
+            &hex::decode(RFC_PUBLIC).unwrap(),
+            b"",
+            &signature,
+            -1,
+            0
+        ));
+    }
+
+    #[test]
+    fn generated_key_pair_is_consistent_and_random() {
+        let first = generate_key_pair().unwrap();
+        let second = generate_key_pair().unwrap();
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, second);
+        assert_eq!(public_key_from_secret(&first[..32]).unwrap(), first[32..]);
+    }
+
+    #[test]
+    fn signature_id_is_part_of_what_is_verified() {
+        let public_key = public_key_from_secret(&SECRET).unwrap();
+        let signature = sign_data(&SECRET, b"payload", 42, 0).unwrap();
+
+        assert!(verify_signature(&public_key, b"payload", &signature, 42, 0));
+        assert!(!verify_signature(
+            &public_key,
+            b"payload",
+            &signature,
+            43,
+            0
+        ));
+        assert!(!verify_signature(
+            &public_key,
+            b"payload",
+            &signature,
+            -1,
+            0
+        ));
+        assert!(!verify_signature(
+            &public_key,
+            b"tampered",
+            &signature,
+            42,
+            0
+        ));
+        // Same preimage as nekoton: the big-endian id in front of the data
+        assert_eq!(
+            signature,
+            sign_data(
+                &SECRET,
+                &[&42i32.to_be_bytes()[..], &b"payload"[..]].concat(),
+                -1,
+                0
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_keys_and_signatures_are_rejected() {
+        let public_key = public_key_from_secret(&SECRET).unwrap();
+        let signature = sign_data(&SECRET, b"payload", -1, 0).unwrap();
+
+        assert!(sign_data(&SECRET[..31], b"payload", -1, 0).is_err());
+        assert!(public_key_from_secret(&[0; 33]).is_err());
+        assert!(!verify_signature(
+            &public_key[..31],
+            b"payload",
+            &signature,
+            -1,
+            0
+        ));
+        assert!(!verify_signature(
+            &public_key,
+            b"payload",
+            &signature[..63],
+            -1,
+            0
+        ));
+        assert!(sign_data(&SECRET, b"payload", i64::from(i32::MAX) + 1, 0).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jlong, jstring};
+use jni::JNIEnv;
+use nekoton::transport::models::RawContractState;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use ton_abi::{Contract, Token, TokenValue};
+use ton_block::{AccountState, AccountStuff, Deserializable, MsgAddressInt};
+
+use crate::error::{self, NekotonError};
+use crate::{abi, contract, runtime, transport};
+
+/// Record of a domain certificate that holds the address it points to
+const TARGET_ADDRESS_RECORD: u32 = 0;
+
+const DENS_ROOT_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "resolve",
+            "inputs": [
+                {"name": "answerId", "type": "uint32"},
+                {"name": "path", "type": "string"}
+            ],
+            "outputs": [{"name": "certificate", "type": "address"}]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+const DENS_DOMAIN_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "resolve",
+            "inputs": [
+                {"name": "answerId", "type": "uint32"},
+                {"name": "key", "type": "uint32"}
+            ],
+            "outputs": [{"name": "value", "type": "optional(cell)"}]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+static DENS_ROOT: Lazy<Contract> =
+    Lazy::new(|| Contract::load(DENS_ROOT_ABI.as_bytes()).expect("Invalid DeNS root ABI"));
+
+static DENS_DOMAIN: Lazy<Contract> =
+    Lazy::new(|| Contract::load(DENS_DOMAIN_ABI.as_bytes()).expect("Invalid DeNS domain ABI"));
+
+fn call(
+    contract: &Contract,
+    account: &AccountStuff,
+    name: &str,
+    inputs: Value,
+) -> Result<Vec<Token>> {
+    let function = contract.function(name)?;
+    let inputs = abi::parse_tokens(&function.inputs, &inputs.to_string())?;
+    contract::call_getter(function, account.clone(), &inputs)
+}
+
+/// The certificate of an unregistered or expired domain is never deployed
+fn find_certificate(
+    transport_handle: jlong,
+    address: &MsgAddressInt,
+) -> Result<Option<AccountStuff>> {
+    let transport = transport::get_transport(transport_handle)?;
+    let state = runtime::block_on(transport.get_contract_state(address))
+        .context(NekotonError::Transport)?;
+    Ok(match state {
+        RawContractState::Exists(contract)
+            if matches!(
+                contract.account.storage.state,
+                AccountState::AccountActive { .. }
+            ) =>
+        {
+            Some(contract.account)
+        }
+        _ => None,
+    })
+}
+
+/// Resolves `alice.ever` through the DeNS root at `root`, which differs per
+/// network: the root computes the domain's certificate address and the
+/// certificate holds the target address record. DeNS keeps no reverse records,
+/// so only forward resolution exists. `None` for an unregistered domain or one
+/// without a target
+fn resolve_domain(transport_handle: jlong, root: &str, domain: &str) -> Result<Option<String>> {
+    let root = nekoton_utils::repack_address(root)?;
+    let path = domain.trim().trim_end_matches('.').to_lowercase();
+    if path.is_empty() {
+        return Err(anyhow!("Domain name must not be empty"));
+    }
+
+    let root_account = contract::get_active_account(transport_handle, &root)?;
+    let inputs = json!({ "answerId": 0, "path": path });
+    let certificate = match call(&DENS_ROOT, &root_account, "resolve", inputs)?.pop() {
+        Some(Token {
+            value: TokenValue::Address(address),
+            ..
+        }) => nekoton_utils::repack_address(&address.to_string())?,
+        _ => return Err(anyhow!("Getter `resolve` must return an address")),
+    };
+    let certificate = match find_certificate(transport_handle, &certificate)? {
+        Some(certificate) => certificate,
+        None => return Ok(None),
+    };
+
+    let inputs = json!({ "answerId": 0, "key": TARGET_ADDRESS_RECORD });
+    match call(&DENS_DOMAIN, &certificate, "resolve", inputs)?.pop() {
+        Some(Token {
+            value: TokenValue::Optional(_, value),
+            ..
+        }) => match value.map(|value| *value) {
+            Some(TokenValue::Cell(cell)) => {
+                let target = MsgAddressInt::construct_from_cell(cell)
+                    .context("Invalid target address record")?;
+                Ok(Some(target.to_string()))
+            }
+            Some(_) => Err(anyhow!("Target address record must be a cell")),
+            None => Ok(None),
+        },
+        _ => Err(anyhow!("Getter `resolve` must return an optional cell")),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_resolveDomain(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    root_address: JString,
+    domain_name: JString,
+) -> jstring {
+    error::guard!(env, {
+        let root_address: String = match env.get_string(&root_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let domain_name: String = match env.get_string(&domain_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let target = match resolve_domain(transport_handle, &root_address, &domain_name) {
+            Ok(Some(target)) => target,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(target) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
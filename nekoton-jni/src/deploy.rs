@@ -0,0 +1,668 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton_utils::Clock;
+use serde_json::json;
+use ton_abi::Contract;
+use ton_block::{
+    Account, CurrencyCollection, Deserializable, ExternalInboundMessageHeader, Message, MsgAddrStd,
+    MsgAddressInt, Serializable, StateInit, Transaction,
+};
+use ton_executor::BlockchainConfig;
+use ton_types::{Cell, SliceData};
+
+use crate::error::{self, NekotonError};
+use crate::{abi, address, cell, clock, executor, runtime, transport};
+
+/// How long the deployed contract should be able to pay for its storage
+const STORAGE_PREPAYMENT_PERIOD: u32 = 30 * 24 * 60 * 60;
+
+pub fn compute_address(workchain: i8, state_init: &StateInit) -> Result<MsgAddressInt> {
+    let hash = state_init.serialize()?.repr_hash();
+    Ok(MsgAddressInt::AddrStd(MsgAddrStd {
+        anycast: None,
+        workchain_id: workchain,
+        address: hash.into(),
+    }))
+}
+
+fn make_state_init(code: Cell, data: Cell) -> StateInit {
+    StateInit {
+        code: Some(code),
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+fn parse_workchain(workchain: jint) -> Result<i8> {
+    i8::try_from(workchain).map_err(|_| anyhow!("Invalid workchain"))
+}
+
+fn compute_state_init(code_boc: &[u8], data_boc: &[u8]) -> Result<Vec<u8>> {
+    let state_init = make_state_init(cell::parse_boc(code_boc)?, cell::parse_boc(data_boc)?);
+    cell::serialize_boc(&state_init.serialize()?)
+}
+
+/// The address is the representation hash of the StateInit cell, the same one
+/// the network derives when the contract is deployed
+fn compute_state_init_address(workchain: jint, state_init_boc: &[u8]) -> Result<Vec<u8>> {
+    let workchain = parse_workchain(workchain)?;
+    let state_init = StateInit::construct_from_cell(cell::parse_boc(state_init_boc)?)?;
+    address::address_to_bytes(&compute_address(workchain, &state_init)?)
+}
+
+/// A TVC is the StateInit BOC produced by the compiler; its data keeps every
+/// value that isn't overwritten by `init_data` or the public key
+fn encode_state_init(
+    abi_handle: jlong,
+    tvc: &[u8],
+    init_data: &str,
+    public_key: &[u8],
+) -> Result<Vec<u8>> {
+    let contract = abi::get_abi(abi_handle)?;
+    let public_key = abi::parse_public_key(public_key)?;
+    let mut state_init = StateInit::construct_from_cell(cell::parse_boc(tvc)?)?;
+    if state_init.code.is_none() {
+        return Err(anyhow!("TVC has no code"));
+    }
+
+    let data = match state_init.data.take() {
+        Some(data) => abi::merge_init_data(&contract, data, public_key, init_data)?,
+        None => abi::encode_init_data(&contract, public_key, init_data)?,
+    };
+    state_init.data = Some(data);
+    cell::serialize_boc(&state_init.serialize()?)
+}
+
+fn expected_address(state_init_boc: &[u8], workchain: jint) -> Result<String> {
+    let workchain = parse_workchain(workchain)?;
+    let state_init = StateInit::construct_from_cell(cell::parse_boc(state_init_boc)?)?;
+    Ok(compute_address(workchain, &state_init)?.to_string())
+}
+
+fn prepare_generic_deploy(
+    abi_handle: jlong,
+    code_boc: &[u8],
+    constructor_inputs: &str,
+    public_key: &[u8],
+    workchain: jint,
+    init_data: &str,
+) -> Result<String> {
+    let contract = abi::get_abi(abi_handle)?;
+    let workchain = parse_workchain(workchain)?;
+    let public_key = abi::parse_public_key(public_key)?;
+
+    let code = cell::parse_boc(code_boc)?;
+    let data = abi::encode_init_data(&contract, public_key, init_data)?;
+    let state_init = make_state_init(code, data);
+    let address = compute_address(workchain, &state_init)?;
+
+    let constructor = contract.function("constructor")?;
+    let inputs = abi::parse_tokens(&constructor.inputs, constructor_inputs)?;
+    let body = constructor.encode_internal_input(&inputs)?.into_cell()?;
+
+    Ok(json!({
+        "address": address.to_string(),
+        "stateInitBoc": base64::encode(cell::serialize_boc(&state_init.serialize()?)?),
+        "constructorBodyHandle": cell::insert_cell(body),
+    })
+    .to_string())
+}
+
+/// Runs `message` against an uninit account at `address`. A nonexistent account
+/// can't pay for an external message, so the account is funded beforehand
+fn simulate_deploy(
+    config: &BlockchainConfig,
+    address: &MsgAddressInt,
+    message: &Message,
+    now: u32,
+) -> Result<(Transaction, Cell)> {
+    let account = Account::uninit(
+        address.clone(),
+        0,
+        now,
+        CurrencyCollection::with_grams(executor::SIMULATED_BALANCE),
+    );
+    let mut account_root = account.serialize()?;
+    let transaction =
+        executor::execute_message(config.clone(), &mut account_root, message, now, 0)?;
+    Ok((transaction, account_root))
+}
+
+/// An external message carrying only the StateInit, for contracts that are
+/// configured by a separate call after being deployed. Contracts compiled from
+/// an ABI reject externals without a body, so the message is run through the
+/// executor first and only returned if the contract's code accepts it
+fn prepare_deploy_only(
+    transport_handle: jlong,
+    state_init_boc: &[u8],
+    workchain: jint,
+    public_key: &[u8],
+) -> Result<String> {
+    let transport = transport::get_transport(transport_handle)?;
+    let workchain = parse_workchain(workchain)?;
+    let state_init = StateInit::construct_from_cell(cell::parse_boc(state_init_boc)?)?;
+    if state_init.code.is_none() {
+        return Err(anyhow!("StateInit has no code to deploy"));
+    }
+
+    // The key the contract will accept messages from must be the one baked into its data
+    if let Some(public_key) = abi::parse_public_key(public_key)? {
+        let data = state_init
+            .data
+            .clone()
+            .ok_or_else(|| anyhow!("StateInit has no data"))?;
+        let stored = Contract::get_pubkey(&SliceData::load_cell(data)?)?;
+        if stored.as_deref() != Some(public_key) {
+            return Err(anyhow!("Public key does not match the StateInit data"));
+        }
+    }
+
+    let address = compute_address(workchain, &state_init)?;
+    let mut message = Message::with_ext_in_header(ExternalInboundMessageHeader {
+        dst: address.clone(),
+        ..Default::default()
+    });
+    message.set_state_init(state_init);
+
+    let config = runtime::block_on(transport.get_blockchain_config(clock::CLOCK.as_ref(), false))
+        .context(NekotonError::Transport)?;
+    let now = clock::CLOCK.now_sec_u64() as u32;
+    let (transaction, _) = simulate_deploy(&config, &address, &message, now)
+        .context("The contract does not accept a deploy without a call")?;
+    let description = transaction.read_description()?;
+    if description.is_aborted() {
+        return Err(anyhow!(
+            "The contract does not accept a deploy without a call: {}",
+            executor::failure_reason(&description)
+        ));
+    }
+
+    Ok(json!({
+        "address": address.to_string(),
+        "messageBoc": base64::encode(cell::serialize_boc(&message.serialize()?)?),
+    })
+    .to_string())
+}
+
+fn estimate_deploy_cost(
+    transport_handle: jlong,
+    state_init_boc: &[u8],
+    constructor_message_boc: &[u8],
+) -> Result<String> {
+    let transport = transport::get_transport(transport_handle)?;
+    let state_init = StateInit::construct_from_cell(cell::parse_boc(state_init_boc)?)?;
+    let mut message = Message::construct_from_cell(cell::parse_boc(constructor_message_boc)?)?;
+
+    let address = message
+        .dst()
+        .ok_or_else(|| anyhow!("Constructor message has no destination"))?;
+    let workchain = parse_workchain(address.workchain_id())?;
+    if address != compute_address(workchain, &state_init)? {
+        return Err(anyhow!(
+            "Constructor message destination does not match the StateInit address"
+        ));
+    }
+    if message.state_init().is_none() {
+        message.set_state_init(state_init);
+    }
+
+    let config = runtime::block_on(transport.get_blockchain_config(clock::CLOCK.as_ref(), false))
+        .context(NekotonError::Transport)?;
+    let now = clock::CLOCK.now_sec_u64() as u32;
+
+    let (transaction, account_root) = simulate_deploy(&config, &address, &message, now)?;
+    let description = transaction.read_description()?;
+    if description.is_aborted() {
+        return Err(anyhow!(
+            "Deploy would fail: {}",
+            executor::failure_reason(&description)
+        ));
+    }
+
+    // Counted from the transaction rather than the balance difference, which an
+    // internal constructor message carrying value would push below zero
+    let mut sent = 0u128;
+    transaction.iterate_out_msgs(|message| {
+        if let Some(header) = message.int_header() {
+            sent = sent.saturating_add(header.value.grams.as_u128());
+        }
+        Ok(true)
+    })?;
+    let spent = transaction
+        .total_fees()
+        .grams
+        .as_u128()
+        .checked_add(sent)
+        .ok_or_else(|| anyhow!("Deploy cost overflow"))?;
+
+    let account = Account::construct_from_cell(account_root)?;
+    let storage = account
+        .storage_info()
+        .ok_or_else(|| anyhow!("Deploy did not create the account"))?;
+    let storage_prepayment =
+        config.calc_storage_fee(storage, workchain == -1, now + STORAGE_PREPAYMENT_PERIOD)?;
+
+    Ok(json!({
+        "address": address.to_string(),
+        "totalFees": transaction.total_fees().grams.as_u128().to_string(),
+        "spent": spent.to_string(),
+        "storagePrepayment": storage_prepayment.to_string(),
+        "storagePeriodSec": STORAGE_PREPAYMENT_PERIOD,
+        "requiredBalance": (spent + storage_prepayment).to_string(),
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_computeStateInit(
+    mut env: JNIEnv,
+    _class: JClass,
+    code_boc: JByteArray,
+    data_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let code_boc = match env.convert_byte_array(code_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let data_boc = match env.convert_byte_array(data_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let state_init = match compute_state_init(&code_boc, &data_boc) {
+            Ok(state_init) => state_init,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&state_init) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_computeAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    workchain: jint,
+    state_init_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let state_init_boc = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let address = match compute_state_init_address(workchain, &state_init_boc) {
+            Ok(address) => address,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&address) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeStateInit(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    tvc_bytes: JByteArray,
+    init_data_json: JString,
+    public_key: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let tvc = match env.convert_byte_array(tvc_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let init_data: String = match env.get_string(&init_data_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let state_init = match encode_state_init(abi_handle, &tvc, &init_data, &public_key) {
+            Ok(state_init) => state_init,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&state_init) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_expectedAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    state_init_boc: JByteArray,
+    workchain: jint,
+) -> jstring {
+    error::guard!(env, {
+        let state_init_boc = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let address = match expected_address(&state_init_boc, workchain) {
+            Ok(address) => address,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(address) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_prepareGenericDeploy(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    code_boc: JByteArray,
+    constructor_inputs_json: JString,
+    public_key: JByteArray,
+    workchain: jint,
+    init_data_json: JString,
+) -> jstring {
+    error::guard!(env, {
+        let code_boc = match env.convert_byte_array(code_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let constructor_inputs: String = match env.get_string(&constructor_inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let init_data: String = match env.get_string(&init_data_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match prepare_generic_deploy(
+            abi_handle,
+            &code_boc,
+            &constructor_inputs,
+            &public_key,
+            workchain,
+            &init_data,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_prepareDeployOnly(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    state_init_boc: JByteArray,
+    workchain: jint,
+    public_key: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let state_init_boc = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result =
+            match prepare_deploy_only(transport_handle, &state_init_boc, workchain, &public_key) {
+                Ok(result) => result,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Abi, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_estimateDeployCost(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    state_init_boc: JByteArray,
+    constructor_message_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let state_init_boc = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let constructor_message_boc = match env.convert_byte_array(constructor_message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result =
+            match estimate_deploy_cost(transport_handle, &state_init_boc, &constructor_message_boc)
+            {
+                Ok(result) => result,
+                Err(e) => {
+                    error::throw(&mut env, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use ton_block::AccountState;
+    use ton_types::BuilderData;
+
+    use super::*;
+
+    const OWNER: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+    const PUBLIC_KEY: [u8; 32] = [0x11; 32];
+
+    const COUNTER_ABI: &str = r#"{
+        "ABI version": 2,
+        "version": "2.2",
+        "header": ["pubkey", "time", "expire"],
+        "functions": [
+            {
+                "name": "constructor",
+                "inputs": [{"name": "owner", "type": "address"}],
+                "outputs": []
+            }
+        ],
+        "data": [{"key": 1, "name": "nonce", "type": "uint32"}],
+        "events": []
+    }"#;
+
+    /// A contract whose code is a single ACCEPT, so it pays for any message
+    fn accepting_code() -> Cell {
+        let mut code = BuilderData::new();
+        code.append_raw(&[0xF8, 0x00], 16).unwrap();
+        code.into_cell().unwrap()
+    }
+
+    #[test]
+    fn generic_deploy_runs_at_the_computed_address() {
+        let abi = abi::parse_abi(COUNTER_ABI).unwrap();
+        let code = accepting_code();
+        let inputs = json!({ "owner": OWNER }).to_string();
+        let deploy = prepare_generic_deploy(
+            abi,
+            &cell::serialize_boc(&code).unwrap(),
+            &inputs,
+            &PUBLIC_KEY,
+            0,
+            r#"{"nonce": 7}"#,
+        )
+        .unwrap();
+        let deploy: Value = serde_json::from_str(&deploy).unwrap();
+
+        let address = nekoton_utils::repack_address(deploy["address"].as_str().unwrap()).unwrap();
+        let state_init = base64::decode(deploy["stateInitBoc"].as_str().unwrap()).unwrap();
+        let state_init =
+            StateInit::construct_from_cell(cell::parse_boc(&state_init).unwrap()).unwrap();
+        let body = cell::get_cell(deploy["constructorBodyHandle"].as_i64().unwrap()).unwrap();
+
+        // The constructor body carries the inputs, the data the key and the static variables
+        let contract = abi::get_abi(abi).unwrap();
+        let constructor = contract.function("constructor").unwrap();
+        let decoded = constructor
+            .decode_input(SliceData::load_cell(body.clone()).unwrap(), true, false)
+            .unwrap();
+        assert_eq!(
+            nekoton_abi::make_abi_tokens(&decoded).unwrap()["owner"],
+            OWNER
+        );
+        let data = SliceData::load_cell(state_init.data.clone().unwrap()).unwrap();
+        assert_eq!(
+            Contract::get_pubkey(&data).unwrap().as_deref(),
+            Some(PUBLIC_KEY.as_slice())
+        );
+
+        // The network only deploys a StateInit whose hash is the destination address
+        let mut message = Message::with_ext_in_header(ExternalInboundMessageHeader {
+            dst: address.clone(),
+            ..Default::default()
+        });
+        message.set_state_init(state_init.clone());
+        message.set_body(SliceData::load_cell(body).unwrap());
+        let now = clock::CLOCK.now_sec_u64() as u32;
+        let (transaction, account) =
+            simulate_deploy(&BlockchainConfig::default(), &address, &message, now).unwrap();
+
+        let description = transaction.read_description().unwrap();
+        assert!(
+            !description.is_aborted(),
+            "{}",
+            executor::failure_reason(&description)
+        );
+        let account = match Account::construct_from_cell(account).unwrap() {
+            Account::Account(account) => account,
+            Account::AccountNone => panic!("Deploy left no account"),
+        };
+        assert_eq!(account.addr, address);
+        match account.storage.state {
+            AccountState::AccountActive {
+                state_init: deployed,
+            } => {
+                assert_eq!(deployed.code, Some(code));
+                assert_eq!(deployed.data, state_init.data);
+            }
+            state => panic!("Account is {}", crate::models::account_state_to_str(&state)),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jint, jlong, jstring};
+use jni::JNIEnv;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use ton_abi::{Param, TokenValue};
+use ton_types::{BuilderData, HashmapE, HashmapType, SliceData};
+
+use crate::{abi, cell, error, slice};
+
+/// Unsigned keys only; 1 to 256 bits like the builder stores
+fn parse_key_bits(key_bits: jint) -> Result<usize> {
+    cell::parse_bit_width(key_bits).context("Invalid dictionary key width")
+}
+
+fn encode_key(key: &str, key_bits: usize) -> Result<SliceData> {
+    let value: BigUint = key
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid dictionary key: {key}"))?;
+    if value.bits() > key_bits as u64 {
+        return Err(anyhow!("Key {value} does not fit into {key_bits} bits"));
+    }
+    let mut builder = BuilderData::new();
+    cell::append_bits(&mut builder, &value, key_bits)?;
+    SliceData::load_builder(builder)
+}
+
+fn decode_key(mut key: SliceData, key_bits: usize) -> Result<String> {
+    Ok(slice::read_bits(&mut key, key_bits)?.to_string())
+}
+
+/// Values are stored as a whole slice, so they are handed out as cells of their own
+fn value_to_cell_handle(value: &SliceData) -> Result<jlong> {
+    let mut builder = BuilderData::new();
+    builder.checked_append_references_and_data(value)?;
+    Ok(cell::insert_cell(builder.into_cell()?))
+}
+
+fn load_dict(cell_handle: jlong, key_bits: usize) -> Result<HashmapE> {
+    let root = cell::get_cell(cell_handle)?;
+    Ok(HashmapE::with_hashmap(key_bits, Some(root)))
+}
+
+/// Name every value is decoded under, whatever the parameter is called
+const VALUE_PARAM: &str = "value";
+
+/// Either a type signature such as `uint128`, or a full ABI parameter for tuples
+fn parse_value_type(value_type: &str) -> Result<Param> {
+    let value: Value = match value_type.trim_start().starts_with('{') {
+        true => serde_json::from_str(value_type).context("Invalid value type JSON")?,
+        false => json!({ "name": VALUE_PARAM, "type": value_type.trim() }),
+    };
+    let mut param: Param = serde_json::from_value(value).context("Invalid value type")?;
+    param.name = VALUE_PARAM.to_owned();
+    Ok(param)
+}
+
+/// `entries` is an object of decimal keys to value cell handles.
+/// The result is the root cell of the dictionary, as referenced by `HashmapE`
+fn dict_build(key_bits: jint, entries: &str) -> Result<jlong> {
+    let key_bits = parse_key_bits(key_bits)?;
+    let entries: serde_json::Map<String, Value> =
+        serde_json::from_str(entries).context("Invalid dictionary entries JSON")?;
+
+    let mut dict = HashmapE::with_bit_len(key_bits);
+    for (key, value) in &entries {
+        let handle = value
+            .as_i64()
+            .ok_or_else(|| anyhow!("Value of key {key} must be a cell handle"))?;
+        let mut value = BuilderData::new();
+        value
+            .checked_append_references_and_data(&SliceData::load_cell(cell::get_cell(handle)?)?)?;
+        dict.set_builder(encode_key(key, key_bits)?, &value)?;
+    }
+
+    let root = dict
+        .data()
+        .cloned()
+        .ok_or_else(|| anyhow!("An empty dictionary has no root cell"))?;
+    Ok(cell::insert_cell(root))
+}
+
+/// An object of decimal keys to new cell handles holding each value
+fn dict_entries(cell_handle: jlong, key_bits: jint) -> Result<String> {
+    let key_bits = parse_key_bits(key_bits)?;
+    let dict = load_dict(cell_handle, key_bits)?;
+
+    let mut entries = serde_json::Map::new();
+    dict.iterate_slices(|key, value| {
+        entries.insert(
+            decode_key(key, key_bits)?,
+            json!(value_to_cell_handle(&value)?),
+        );
+        Ok(true)
+    })?;
+    Ok(Value::Object(entries).to_string())
+}
+
+/// Like `dict_entries`, with every value decoded as `value_type` by the rules of
+/// `abi_version`, the version of the ABI the dictionary belongs to
+fn dict_entries_decoded(
+    cell_handle: jlong,
+    key_bits: jint,
+    value_type: &str,
+    abi_version: &str,
+) -> Result<String> {
+    let key_bits = parse_key_bits(key_bits)?;
+    let dict = load_dict(cell_handle, key_bits)?;
+    let params = [parse_value_type(value_type)?];
+    let abi_version = abi::parse_abi_version(abi_version)?;
+
+    let mut entries = serde_json::Map::new();
+    dict.iterate_slices(|key, value| {
+        let tokens = TokenValue::decode_params(&params, value, &abi_version, false)?;
+        let decoded = nekoton_abi::make_abi_tokens(&tokens)?;
+        entries.insert(decode_key(key, key_bits)?, decoded[VALUE_PARAM].clone());
+        Ok(true)
+    })?;
+    Ok(Value::Object(entries).to_string())
+}
+
+/// `0` when the key is absent
+fn dict_get(cell_handle: jlong, key_bits: jint, key: &str) -> Result<jlong> {
+    let key_bits = parse_key_bits(key_bits)?;
+    let dict = load_dict(cell_handle, key_bits)?;
+    match dict.get(encode_key(key, key_bits)?)? {
+        Some(value) => value_to_cell_handle(&value),
+        None => Ok(0),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_dictBuild(
+    mut env: JNIEnv,
+    _class: JClass,
+    key_bits: jint,
+    entries_json: JString,
+) -> jlong {
+    error::guard!(env, {
+        let entries: String = match env.get_string(&entries_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match dict_build(key_bits, &entries) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_dictEntries(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+    key_bits: jint,
+) -> jstring {
+    error::guard!(env, {
+        let entries = match dict_entries(cell_handle, key_bits) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(entries) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_dictEntriesDecoded(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+    key_bits: jint,
+    value_type: JString,
+    abi_version: JString,
+) -> jstring {
+    error::guard!(env, {
+        let value_type: String = match env.get_string(&value_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let abi_version: String = match env.get_string(&abi_version) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let entries = match dict_entries_decoded(cell_handle, key_bits, &value_type, &abi_version) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(entries) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_dictGet(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+    key_bits: jint,
+    key: JString,
+) -> jlong {
+    error::guard!(env, {
+        let key: String = match env.get_string(&key) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match dict_get(cell_handle, key_bits, &key) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
@@ -0,0 +1,243 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use curve25519_dalek_ng::edwards::CompressedEdwardsY;
+use curve25519_dalek_ng::montgomery::MontgomeryPoint;
+use curve25519_dalek_ng::scalar::Scalar;
+use jni::objects::{JByteArray, JClass};
+use jni::sys::jbyteArray;
+use jni::JNIEnv;
+use sha2::{Digest, Sha512};
+use zeroize::Zeroize;
+
+use crate::crypto;
+use crate::error::{self, NekotonError};
+
+const NONCE_LENGTH: usize = 12;
+
+/// The clamped scalar ed25519 derives from the first half of the secret's hash
+fn ed25519_secret_to_x25519(secret: &[u8]) -> Result<[u8; 32]> {
+    let keypair = crypto::keypair_from_secret(secret)?;
+    let mut hash = Sha512::digest(keypair.secret.as_bytes());
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    hash.as_mut_slice().zeroize();
+
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    Ok(scalar)
+}
+
+fn ed25519_public_to_x25519(public_key: &[u8]) -> Result<[u8; 32]> {
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| anyhow!("Public key must be 32 bytes, got {}", public_key.len()))?;
+    let point = CompressedEdwardsY(public_key)
+        .decompress()
+        .ok_or_else(|| anyhow!("Public key is not a valid curve point"))?;
+    Ok(point.to_montgomery().to_bytes())
+}
+
+/// X25519 shared secret of our ed25519 secret and their ed25519 public key,
+/// the same for both sides of the conversation
+fn shared_secret(secret: &[u8], their_public_key: &[u8]) -> Result<Key> {
+    let mut scalar = ed25519_secret_to_x25519(secret)?;
+    let point = MontgomeryPoint(ed25519_public_to_x25519(their_public_key)?);
+    let shared = Scalar::from_bits(scalar) * point;
+    scalar.zeroize();
+    Ok(Key::clone_from_slice(shared.as_bytes()))
+}
+
+fn parse_nonce(nonce: &[u8]) -> Result<&Nonce> {
+    if nonce.len() != NONCE_LENGTH {
+        return Err(anyhow!(
+            "Nonce must be {NONCE_LENGTH} bytes, got {}",
+            nonce.len()
+        ));
+    }
+    Ok(Nonce::from_slice(nonce))
+}
+
+fn box_encrypt(
+    secret: &[u8],
+    their_public_key: &[u8],
+    nonce: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&shared_secret(secret, their_public_key)?);
+    cipher
+        .encrypt(parse_nonce(nonce)?, data)
+        .map_err(|_| anyhow!("Failed to encrypt data"))
+}
+
+fn box_decrypt(
+    secret: &[u8],
+    their_public_key: &[u8],
+    nonce: &[u8],
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(&shared_secret(secret, their_public_key)?);
+    cipher
+        .decrypt(parse_nonce(nonce)?, data)
+        .map_err(|_| anyhow!("Failed to decrypt data: wrong key or corrupted data"))
+}
+
+fn read_box_args(
+    env: &mut JNIEnv,
+    secret: JByteArray,
+    their_public_key: JByteArray,
+    nonce: JByteArray,
+    data: JByteArray,
+) -> Option<(Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>)> {
+    Some((
+        env.convert_byte_array(secret).ok()?,
+        env.convert_byte_array(their_public_key).ok()?,
+        env.convert_byte_array(nonce).ok()?,
+        env.convert_byte_array(data).ok()?,
+    ))
+}
+
+/// Encrypts with X25519 + ChaCha20-Poly1305 under the 12-byte `nonce`, the scheme
+/// EVER Wallet uses for encrypted comments rather than NaCl's XSalsa20.
+/// Keys are ed25519 and converted on the fly
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_naclBoxEncrypt(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+    their_public_key: JByteArray,
+    nonce: JByteArray,
+    data: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let (mut secret, their_public_key, nonce, data) =
+            match read_box_args(&mut env, secret_bytes, their_public_key, nonce, data) {
+                Some(args) => args,
+                None => return std::ptr::null_mut(),
+            };
+        let encrypted = box_encrypt(&secret, &their_public_key, &nonce, &data);
+        secret.zeroize();
+
+        let encrypted = match encrypted {
+            Ok(encrypted) => encrypted,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&encrypted) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Reverse of `naclBoxEncrypt`, with the sender's public key as `theirPublicKey`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_naclBoxDecrypt(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+    their_public_key: JByteArray,
+    nonce: JByteArray,
+    data: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let (mut secret, their_public_key, nonce, data) =
+            match read_box_args(&mut env, secret_bytes, their_public_key, nonce, data) {
+                Some(args) => args,
+                None => return std::ptr::null_mut(),
+            };
+        let decrypted = box_decrypt(&secret, &their_public_key, &nonce, &data);
+        secret.zeroize();
+
+        let mut decrypted = match decrypted {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&decrypted) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        decrypted.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_ed25519SecretToX25519(
+    mut env: JNIEnv,
+    _class: JClass,
+    secret_bytes: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let x25519_secret = ed25519_secret_to_x25519(&secret);
+        secret.zeroize();
+
+        let mut x25519_secret = match x25519_secret {
+            Ok(x25519_secret) => x25519_secret,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&x25519_secret) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        x25519_secret.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_ed25519PublicToX25519(
+    mut env: JNIEnv,
+    _class: JClass,
+    public_key: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let x25519_public_key = match ed25519_public_to_x25519(&public_key) {
+            Ok(x25519_public_key) => x25519_public_key,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&x25519_public_key) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,215 @@
+use std::any::Any;
+use std::fmt;
+
+use jni::objects::{JThrowable, JValue};
+use jni::sys::{jboolean, jint, jlong};
+use jni::JNIEnv;
+
+use crate::registry::InvalidHandle;
+use crate::transport::{QuorumNotReached, ResponseTooLarge};
+
+/// Base of the exception hierarchy, thrown when no more specific kind applies
+pub const NEKOTON_EXCEPTION: &str = "com/mazekine/nekoton/NekotonException";
+pub const TRANSPORT_ERROR: &str = "com/mazekine/nekoton/NekotonException$TransportError";
+pub const ABI_ERROR: &str = "com/mazekine/nekoton/NekotonException$AbiError";
+pub const CRYPTO_ERROR: &str = "com/mazekine/nekoton/NekotonException$CryptoError";
+/// Replaces the `IllegalStateException` that unknown and released handles threw
+/// before the exception hierarchy existed, so callers catching that must catch
+/// this instead. Like every subclass it extends `NekotonException`
+pub const INVALID_HANDLE: &str = "com/mazekine/nekoton/NekotonException$InvalidHandle";
+/// Constructed with the message and the JSON array of the results the inner
+/// transports produced, so callers can see where they disagreed
+pub const QUORUM_NOT_REACHED: &str = "com/mazekine/nekoton/NekotonException$QuorumNotReached";
+/// Constructed with the message and the `ErrorCode` of the failed stage, such
+/// as `SEND_FAILED`, so callers can tell the stages apart without the message
+pub const STAGE_ERROR: &str = "com/mazekine/nekoton/NekotonException$StageError";
+/// Constructed with the message and the limit in bytes that the HTTP body or
+/// WebSocket message exceeded. Not retried, since every endpoint answers alike
+pub const RESPONSE_TOO_LARGE: &str = "com/mazekine/nekoton/NekotonException$ResponseTooLarge";
+
+/// Failed stage of a composite operation, attached as context and thrown as
+/// `NekotonException.StageError` carrying the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    Encode,
+    Sign,
+    Send,
+    Timeout,
+    Revert,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Encode => "ENCODE_FAILED",
+            Self::Sign => "SIGN_FAILED",
+            Self::Send => "SEND_FAILED",
+            Self::Timeout => "TIMEOUT",
+            Self::Revert => "REVERTED",
+        })
+    }
+}
+
+/// Kind of failure, picking the subclass of `NekotonException` to throw.
+/// Attached as context where a failure originates, or passed to [`throw_as`]
+/// by handlers that only deal with one kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NekotonError {
+    Transport,
+    Abi,
+    Crypto,
+}
+
+impl fmt::Display for NekotonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Transport => "Transport error",
+            Self::Abi => "ABI error",
+            Self::Crypto => "Crypto error",
+        })
+    }
+}
+
+/// Class to throw, with the argument its constructor takes after the message
+/// for subclasses that carry one
+fn exception_class(
+    error: &anyhow::Error,
+    default: Option<NekotonError>,
+) -> (&'static str, Option<String>) {
+    // Using a released or never issued handle is a programming error on the Kotlin side
+    if error.chain().any(|cause| cause.is::<InvalidHandle>()) {
+        return (INVALID_HANDLE, None);
+    }
+    if let Some(quorum) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<QuorumNotReached>())
+    {
+        return (QUORUM_NOT_REACHED, Some(quorum.results.to_string()));
+    }
+    if let Some(too_large) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<ResponseTooLarge>())
+    {
+        return (RESPONSE_TOO_LARGE, Some(too_large.limit.to_string()));
+    }
+    if let Some(stage) = error.downcast_ref::<ErrorCode>() {
+        return (STAGE_ERROR, Some(stage.to_string()));
+    }
+    let kind = error.downcast_ref::<NekotonError>().copied();
+    let class = match kind.or(default) {
+        Some(NekotonError::Transport) => TRANSPORT_ERROR,
+        Some(NekotonError::Abi) => ABI_ERROR,
+        Some(NekotonError::Crypto) => CRYPTO_ERROR,
+        None => NEKOTON_EXCEPTION,
+    };
+    (class, None)
+}
+
+fn exception<'local>(
+    env: &mut JNIEnv<'local>,
+    error: &anyhow::Error,
+    default: Option<NekotonError>,
+) -> jni::errors::Result<JThrowable<'local>> {
+    let (class, detail) = exception_class(error, default);
+    let message = env.new_string(format!("{error:#}"))?;
+    let exception = match detail {
+        Some(detail) => {
+            let detail = env.new_string(detail)?;
+            env.new_object(
+                class,
+                "(Ljava/lang/String;Ljava/lang/String;)V",
+                &[JValue::Object(&message), JValue::Object(&detail)],
+            )?
+        }
+        None => env.new_object(class, "(Ljava/lang/String;)V", &[JValue::Object(&message)])?,
+    };
+    Ok(JThrowable::from(exception))
+}
+
+fn throw_with(env: &mut JNIEnv, default: Option<NekotonError>, error: anyhow::Error) {
+    // Keep the original exception if the failure came from a JNI call
+    if env.exception_check().unwrap_or(false) {
+        return;
+    }
+    if let Ok(exception) = exception(env, &error, default) {
+        let _ = env.throw(exception);
+    }
+}
+
+pub fn throw(env: &mut JNIEnv, error: anyhow::Error) {
+    throw_with(env, None, error);
+}
+
+/// Like [`throw`], falling back to `kind` when the error carries none
+pub fn throw_as(env: &mut JNIEnv, kind: NekotonError, error: anyhow::Error) {
+    throw_with(env, Some(kind), error);
+}
+
+/// The exception [`throw_as`] would throw, for errors reported through callbacks
+pub fn new_exception<'local>(
+    env: &mut JNIEnv<'local>,
+    kind: NekotonError,
+    error: &anyhow::Error,
+) -> jni::errors::Result<JThrowable<'local>> {
+    exception(env, error, Some(kind))
+}
+
+pub fn throw_panic(env: &mut JNIEnv, panic: Box<dyn Any + Send>) {
+    let reason = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(reason), _) => reason.to_string(),
+        (_, Some(reason)) => reason.clone(),
+        _ => "unknown reason".to_owned(),
+    };
+    // A panic may have left a pending exception that is no longer meaningful
+    let _ = env.exception_clear();
+    let _ = env.throw_new(NEKOTON_EXCEPTION, format!("Native panic: {reason}"));
+}
+
+/// Value a handler returns to Java after throwing
+pub trait Sentinel {
+    fn sentinel() -> Self;
+}
+
+impl Sentinel for () {
+    fn sentinel() -> Self {}
+}
+
+impl Sentinel for jboolean {
+    fn sentinel() -> Self {
+        false as jboolean
+    }
+}
+
+impl Sentinel for jint {
+    fn sentinel() -> Self {
+        0
+    }
+}
+
+impl Sentinel for jlong {
+    fn sentinel() -> Self {
+        0
+    }
+}
+
+impl<T> Sentinel for *mut T {
+    fn sentinel() -> Self {
+        std::ptr::null_mut()
+    }
+}
+
+/// Wraps the body of every JNI handler so a panic is thrown to Java as a
+/// `NekotonException` instead of unwinding into the JVM
+macro_rules! guard {
+    ($env:ident, $body:block) => {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| $body)) {
+            Ok(result) => result,
+            Err(panic) => {
+                $crate::error::throw_panic(&mut $env, panic);
+                $crate::error::Sentinel::sentinel()
+            }
+        }
+    };
+}
+
+pub(crate) use guard;
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::transport::models::RawContractState;
+use nekoton_utils::Clock;
+use parking_lot::Mutex;
+use serde_json::json;
+use ton_abi::Function;
+use ton_block::{
+    Account, ConfigParams, CurrencyCollection, Deserializable, ExternalInboundMessageHeader,
+    Message, Serializable, TrComputePhase, Transaction, TransactionDescr,
+};
+use ton_executor::{
+    BlockchainConfig, ExecuteParams, OrdinaryTransactionExecutor, TransactionExecutor,
+};
+use ton_types::{Cell, SliceData};
+use ton_vm::executor::{BehaviorModifiers, Engine, EngineTraceInfo, EngineTraceInfoType};
+
+use crate::error::{self, NekotonError};
+use crate::{abi, address, cell, clock, contract, models, runtime, transport};
+
+/// Balance of a simulated account, large enough to never limit gas
+pub const SIMULATED_BALANCE: u64 = 1_000_000_000_000_000;
+
+/// Lifetime of the message a profiled call is wrapped into
+const PROFILE_EXPIRATION_SEC: jint = 60;
+
+/// Runs `message` against the serialized account in `account`, which is
+/// replaced with the resulting account state
+pub fn execute_message(
+    config: BlockchainConfig,
+    account: &mut Cell,
+    message: &Message,
+    utime: u32,
+    last_trans_lt: u64,
+) -> Result<Transaction> {
+    let executor = OrdinaryTransactionExecutor::new(config);
+    let params = ExecuteParams {
+        block_unixtime: utime,
+        block_lt: last_trans_lt + 1,
+        last_tr_lt: Arc::new(AtomicU64::new(last_trans_lt)),
+        ..Default::default()
+    };
+    executor.execute_with_libs_and_params(Some(message), account, params)
+}
+
+/// Re-executes `transaction` on `account`, the state right before it, which is
+/// replaced with the state right after it. Fails unless both states have the
+/// hashes the transaction recorded, which a replay misses when the config has
+/// changed since or the contract read the block's random seed
+pub fn replay_transaction(
+    config: &BlockchainConfig,
+    account: &mut Cell,
+    transaction: &Transaction,
+) -> Result<()> {
+    let state_update = transaction.read_state_update()?;
+    if account.repr_hash() != state_update.old_hash {
+        return Err(anyhow!(
+            "Account state doesn't match the one before lt {}",
+            transaction.lt
+        ));
+    }
+    let message = transaction.read_in_msg()?.ok_or_else(|| {
+        anyhow!(
+            "Transaction at lt {} has no inbound message, only ordinary ones can be replayed",
+            transaction.lt
+        )
+    })?;
+
+    let executor = OrdinaryTransactionExecutor::new(config.clone());
+    let params = ExecuteParams {
+        block_unixtime: transaction.now,
+        block_lt: transaction.lt,
+        last_tr_lt: Arc::new(AtomicU64::new(transaction.prev_trans_lt)),
+        ..Default::default()
+    };
+    executor.execute_with_libs_and_params(Some(&message), account, params)?;
+    if account.repr_hash() != state_update.new_hash {
+        return Err(anyhow!(
+            "Replaying the transaction at lt {} didn't reproduce its resulting state",
+            transaction.lt
+        ));
+    }
+    Ok(())
+}
+
+/// Human-readable reason why an aborted transaction failed
+pub fn failure_reason(description: &TransactionDescr) -> String {
+    let (compute, action) = match description {
+        TransactionDescr::Ordinary(description) => (&description.compute_ph, &description.action),
+        TransactionDescr::TickTock(description) => (&description.compute_ph, &description.action),
+        _ => return "transaction aborted".to_owned(),
+    };
+
+    match compute {
+        TrComputePhase::Skipped(skipped) => {
+            return format!("compute phase skipped: {:?}", skipped.reason)
+        }
+        TrComputePhase::Vm(vm) if !vm.success => {
+            return format!("compute phase failed with exit code {}", vm.exit_code)
+        }
+        TrComputePhase::Vm(_) => {}
+    }
+    match action {
+        Some(action) if !action.success => {
+            format!(
+                "action phase failed with result code {}",
+                action.result_code
+            )
+        }
+        _ => "transaction aborted".to_owned(),
+    }
+}
+
+/// Executes the message against the current state of `address` without sending it.
+/// A message deploying a new account runs against an uninit account funded
+/// beforehand, since a nonexistent one can't pay for it
+fn estimate_fees(transport_handle: jlong, address: &str, message_boc: &[u8]) -> Result<String> {
+    let transport = transport::get_transport(transport_handle)?;
+    let address = address::parse_address(address)?;
+    let message = Message::construct_from_cell(cell::parse_boc(message_boc)?)?;
+    if message.dst().as_ref() != Some(&address) {
+        return Err(anyhow!("Message is not addressed to {address}"));
+    }
+
+    let config = runtime::block_on(transport.get_blockchain_config(clock::CLOCK.as_ref(), false))
+        .context(NekotonError::Transport)?;
+    let now = clock::CLOCK.now_sec_u64() as u32;
+
+    let state = runtime::block_on(transport.get_contract_state(&address))
+        .context(NekotonError::Transport)?;
+    let (account, last_trans_lt) = match state {
+        RawContractState::Exists(contract) => {
+            let last_trans_lt = contract.account.storage.last_trans_lt;
+            (Account::Account(contract.account), last_trans_lt)
+        }
+        RawContractState::NotExists { .. } if message.state_init().is_some() => {
+            let account = Account::uninit(
+                address.clone(),
+                0,
+                now,
+                CurrencyCollection::with_grams(SIMULATED_BALANCE),
+            );
+            (account, 0)
+        }
+        RawContractState::NotExists { .. } => {
+            return Err(anyhow!(
+                "Account {address} does not exist and the message has no StateInit"
+            ))
+        }
+    };
+
+    let mut account_root = account.serialize()?;
+    let transaction = execute_message(config, &mut account_root, &message, now, last_trans_lt)?;
+    let description = transaction.read_description()?;
+    if description.is_aborted() {
+        return Err(anyhow!(
+            "Message would fail: {}",
+            failure_reason(&description)
+        ));
+    }
+
+    let (storage, gas, forward, action) = match &description {
+        TransactionDescr::Ordinary(description) => (
+            description
+                .storage_ph
+                .as_ref()
+                .map(|storage| storage.storage_fees_collected.as_u128())
+                .unwrap_or_default(),
+            match &description.compute_ph {
+                TrComputePhase::Vm(vm) => vm.gas_fees.as_u128(),
+                TrComputePhase::Skipped(_) => 0,
+            },
+            description
+                .action
+                .as_ref()
+                .and_then(|action| action.total_fwd_fees.as_ref())
+                .map(|fees| fees.as_u128())
+                .unwrap_or_default(),
+            description
+                .action
+                .as_ref()
+                .and_then(|action| action.total_action_fees.as_ref())
+                .map(|fees| fees.as_u128())
+                .unwrap_or_default(),
+        ),
+        _ => return Err(anyhow!("Unexpected transaction type")),
+    };
+
+    Ok(json!({
+        "storage": storage.to_string(),
+        "gas": gas.to_string(),
+        "forward": forward.to_string(),
+        "action": action.to_string(),
+        "total": transaction.total_fees().grams.as_u128().to_string(),
+    })
+    .to_string())
+}
+
+/// Runs the executor on the given state alone, with nothing fetched from the network.
+/// `config_boc` is the `configBoc` of `getBlockchainConfig` and `global_id` its
+/// `globalId`. An empty `account_boc` means a nonexistent account, a zero `utime`
+/// means now and a zero `lt` the account's own last transaction lt.
+/// An aborted transaction is returned like any other, see its `compute.exitCode`
+fn simulate_transaction(
+    config_boc: &[u8],
+    global_id: jint,
+    account_boc: &[u8],
+    message_boc: &[u8],
+    utime: jlong,
+    lt: jlong,
+) -> Result<String> {
+    let config = ConfigParams::construct_from_cell(cell::parse_boc(config_boc)?)
+        .context("Invalid config BOC")?;
+    let config = BlockchainConfig::with_config(config, global_id)?;
+    let account = match account_boc.is_empty() {
+        true => Account::AccountNone,
+        false => Account::construct_from_cell(cell::parse_boc(account_boc)?)
+            .context("Invalid account BOC")?,
+    };
+    let message = Message::construct_from_cell(cell::parse_boc(message_boc)?)
+        .context("Invalid message BOC")?;
+
+    let utime = match utime {
+        0 => clock::CLOCK.now_sec_u64() as u32,
+        utime => u32::try_from(utime).map_err(|_| anyhow!("Invalid utime: {utime}"))?,
+    };
+    let last_trans_lt = match lt {
+        0 => account.last_tr_time().unwrap_or_default(),
+        lt => u64::try_from(lt).map_err(|_| anyhow!("Invalid lt: {lt}"))?,
+    };
+
+    let mut account_root = account.serialize()?;
+    let transaction = execute_message(config, &mut account_root, &message, utime, last_trans_lt)?;
+    let transaction_cell = transaction.serialize()?;
+
+    let mut out_messages = Vec::new();
+    transaction.out_msgs.iterate_slices(|slice| {
+        let message = slice.reference(0)?;
+        out_messages.push(base64::encode(cell::serialize_boc(&message)?));
+        Ok(true)
+    })?;
+
+    Ok(json!({
+        "transaction": models::transaction_to_json(&transaction_cell.repr_hash(), &transaction)?,
+        "transactionBoc": base64::encode(cell::serialize_boc(&transaction_cell)?),
+        "accountBoc": base64::encode(cell::serialize_boc(&account_root)?),
+        "outMessageBocs": out_messages,
+    })
+    .to_string())
+}
+
+/// Runs `function` as an external call on the given account with the TVM tracing
+/// every instruction, at the fees of the current network config. Signature checks
+/// always pass, so any function can be profiled without the owner's key; the
+/// account pays for gas as usual, so the function still has to `accept`.
+/// Instructions are grouped by mnemonic, most expensive first
+fn profile_execution(
+    transport_handle: jlong,
+    abi_handle: jlong,
+    function_name: &str,
+    account_boc: &[u8],
+    inputs: &str,
+) -> Result<String> {
+    let transport = transport::get_transport(transport_handle)?;
+    let contract = abi::get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let account = Account::construct_from_cell(cell::parse_boc(account_boc)?)
+        .context("Invalid account BOC")?;
+    let address = account
+        .get_addr()
+        .cloned()
+        .ok_or_else(|| anyhow!("Account does not exist"))?;
+    let last_trans_lt = account.last_tr_time().unwrap_or_default();
+
+    let (body, _, _) =
+        contract::encode_call(function, &address, "", inputs, None, PROFILE_EXPIRATION_SEC)?;
+    let body = Function::fill_sign(&contract.abi_version, Some(&[0u8; 64]), None, body)?;
+    let mut message = Message::with_ext_in_header(ExternalInboundMessageHeader {
+        dst: address,
+        ..Default::default()
+    });
+    message.set_body(SliceData::load_builder(body)?);
+
+    let config = runtime::block_on(transport.get_blockchain_config(clock::CLOCK.as_ref(), false))
+        .context(NekotonError::Transport)?;
+    let now = clock::CLOCK.now_sec_u64() as u32;
+
+    // Mnemonic to instruction count and gas
+    let profile = Arc::new(Mutex::new(HashMap::<String, (u64, i64)>::new()));
+    let trace = profile.clone();
+    let params = ExecuteParams {
+        block_unixtime: now,
+        block_lt: last_trans_lt + 1,
+        last_tr_lt: Arc::new(AtomicU64::new(last_trans_lt)),
+        trace_callback: Some(Arc::new(move |_: &Engine, info: &EngineTraceInfo| {
+            if !matches!(
+                info.info_type,
+                EngineTraceInfoType::Normal | EngineTraceInfoType::Implicit
+            ) {
+                return;
+            }
+            let mnemonic = info.cmd_str.split_whitespace().next().unwrap_or_default();
+            let mut profile = trace.lock();
+            let (count, gas) = profile.entry(mnemonic.to_owned()).or_default();
+            *count += 1;
+            *gas += info.gas_cmd;
+        })),
+        behavior_modifiers: Some(BehaviorModifiers {
+            chksig_always_succeed: true,
+        }),
+        ..Default::default()
+    };
+
+    let mut account_root = account.serialize()?;
+    let transaction = OrdinaryTransactionExecutor::new(config).execute_with_libs_and_params(
+        Some(&message),
+        &mut account_root,
+        params,
+    )?;
+    let description = transaction.read_description()?;
+
+    let mut instructions = profile.lock().drain().collect::<Vec<_>>();
+    instructions.sort_by(|(a_name, (_, a_gas)), (b_name, (_, b_gas))| {
+        b_gas.cmp(a_gas).then_with(|| a_name.cmp(b_name))
+    });
+    let instructions = instructions
+        .into_iter()
+        .map(|(name, (count, gas))| json!({ "name": name, "count": count, "gas": gas }))
+        .collect::<Vec<_>>();
+
+    Ok(json!({
+        "aborted": description.is_aborted(),
+        "compute": models::compute_phase_to_json(&description),
+        "totalFees": transaction.total_fees().grams.as_u128().to_string(),
+        "instructions": instructions,
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_estimateFees(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    message_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let message_boc = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match estimate_fees(transport_handle, &address, &message_boc) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_simulateTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    config_boc: JByteArray,
+    global_id: jint,
+    account_boc: JByteArray,
+    message_boc: JByteArray,
+    utime: jlong,
+    lt: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let config_boc = match env.convert_byte_array(config_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let account_boc = match env.convert_byte_array(account_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let message_boc = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match simulate_transaction(
+            &config_boc,
+            global_id,
+            &account_boc,
+            &message_boc,
+            utime,
+            lt,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_profileExecution(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    abi_handle: jlong,
+    function_name: JString,
+    account_boc: JByteArray,
+    inputs_json: JString,
+) -> jstring {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let account_boc = match env.convert_byte_array(account_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match profile_execution(
+            transport_handle,
+            abi_handle,
+            &function_name,
+            &account_boc,
+            &inputs,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use ed25519_dalek::PublicKey;
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jbyteArray, jint, jlong};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+use ton_abi::contract::AbiVersion;
+use ton_abi::Function;
+use ton_block::{
+    Deserializable, ExternalInboundMessageHeader, Message, MsgAddressInt, Serializable, StateInit,
+};
+use ton_types::{BuilderData, SliceData};
+
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+use crate::{abi, cell, contract};
+
+static UNSIGNED_MESSAGES: Lazy<Registry<dyn Signable>> = Lazy::new(Registry::new);
+
+/// An external message encoded up to the signature, so it can be signed elsewhere
+pub trait Signable: Send + Sync {
+    /// What the signer signs, without any signature id prefix
+    fn hash(&self) -> &[u8];
+    /// Unix time in seconds after which the message is rejected
+    fn expire_at(&self) -> u32;
+    /// BOC of the complete message
+    fn sign(&self, signature: &[u8; 64]) -> Result<Vec<u8>>;
+}
+
+pub fn insert_unsigned(message: Arc<dyn Signable>) -> jlong {
+    UNSIGNED_MESSAGES.insert(message)
+}
+
+/// An external call to any ABI function
+struct UnsignedMessage {
+    abi_version: AbiVersion,
+    dst: MsgAddressInt,
+    state_init: Option<StateInit>,
+    public_key: Option<PublicKey>,
+    body: BuilderData,
+    hash: Vec<u8>,
+    expire_at: u32,
+}
+
+impl Signable for UnsignedMessage {
+    fn hash(&self) -> &[u8] {
+        &self.hash
+    }
+
+    fn expire_at(&self) -> u32 {
+        self.expire_at
+    }
+
+    fn sign(&self, signature: &[u8; 64]) -> Result<Vec<u8>> {
+        let body = Function::fill_sign(
+            &self.abi_version,
+            Some(signature),
+            self.public_key.as_ref().map(PublicKey::as_bytes),
+            self.body.clone(),
+        )?;
+
+        let mut message = Message::with_ext_in_header(ExternalInboundMessageHeader {
+            dst: self.dst.clone(),
+            ..Default::default()
+        });
+        if let Some(state_init) = &self.state_init {
+            message.set_state_init(state_init.clone());
+        }
+        message.set_body(SliceData::load_builder(body)?);
+        cell::serialize_boc(&message.serialize()?)
+    }
+}
+
+/// `public_key` fills the `pubkey` header and may be empty when the contract
+/// checks against its own stored key
+#[allow(clippy::too_many_arguments)]
+fn create_external_message(
+    abi_handle: jlong,
+    function_name: &str,
+    inputs: &str,
+    dst: &str,
+    state_init_boc: &[u8],
+    expire_sec: jint,
+    public_key: &[u8],
+) -> Result<jlong> {
+    let contract = abi::get_abi(abi_handle)?;
+    let function = contract.function(function_name)?;
+    let dst = nekoton_utils::repack_address(dst)?;
+    let public_key = abi::parse_public_key(public_key)?
+        .map(PublicKey::from_bytes)
+        .transpose()?;
+    let state_init = match state_init_boc.is_empty() {
+        true => None,
+        false => Some(StateInit::construct_from_cell(cell::parse_boc(
+            state_init_boc,
+        )?)?),
+    };
+
+    let (body, hash, expire_at) =
+        contract::encode_call(function, &dst, "", inputs, public_key, expire_sec)?;
+    Ok(insert_unsigned(Arc::new(UnsignedMessage {
+        abi_version: contract.abi_version,
+        dst,
+        state_init,
+        public_key,
+        body,
+        hash,
+        expire_at,
+    })))
+}
+
+fn sign_message(handle: jlong, signature: &[u8]) -> Result<Vec<u8>> {
+    let message = UNSIGNED_MESSAGES.get(handle)?;
+    let signature = <[u8; 64]>::try_from(signature)
+        .map_err(|_| anyhow!("Signature must be 64 bytes, got {}", signature.len()))?;
+    message.sign(&signature)
+}
+
+/// Same layout as a real signature, for running the message through an executor
+fn sign_message_with_fake_signature(handle: jlong) -> Result<Vec<u8>> {
+    UNSIGNED_MESSAGES.get(handle)?.sign(&[0u8; 64])
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createExternalMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    abi_handle: jlong,
+    function_name: JString,
+    inputs_json: JString,
+    dst_address: JString,
+    state_init_boc: JByteArray,
+    expire_sec: jint,
+    public_key: JByteArray,
+) -> jlong {
+    error::guard!(env, {
+        let function_name: String = match env.get_string(&function_name) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let inputs: String = match env.get_string(&inputs_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let dst: String = match env.get_string(&dst_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let state_init = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+
+        match create_external_message(
+            abi_handle,
+            &function_name,
+            &inputs,
+            &dst,
+            &state_init,
+            expire_sec,
+            &public_key,
+        ) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                0
+            }
+        }
+    })
+}
+
+/// The hash a remote signer has to sign, without any signature id prefix.
+/// Works for every unsigned message handle, whichever call created it
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_unsignedMessageHash(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let message = match UNSIGNED_MESSAGES.get(handle) {
+            Ok(message) => message,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(message.hash()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Unix time in seconds after which the message is rejected
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_unsignedMessageExpireAt(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match UNSIGNED_MESSAGES.get(handle) {
+            Ok(message) => jlong::from(message.expire_at()),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    signature_bytes: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let signature = match env.convert_byte_array(signature_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let message = match sign_message(handle, &signature) {
+            Ok(message) => message,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&message) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_signMessageWithFakeSignature(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let message = match sign_message_with_fake_signature(handle) {
+            Ok(message) => message,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&message) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupUnsignedMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = UNSIGNED_MESSAGES.remove(handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
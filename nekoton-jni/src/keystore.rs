@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::crypto::MnemonicType;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rand::RngCore;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+use crate::crypto;
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+
+const PBKDF2_ROUNDS: u32 = 100_000;
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+static KEYSTORES: Lazy<Registry<KeyStore>> = Lazy::new(Registry::new);
+
+/// Secrets are only ever held encrypted with a key derived from their password;
+/// the plaintext exists in memory just for the duration of a single signature
+#[derive(Default)]
+struct KeyStore {
+    keys: Mutex<HashMap<String, EncryptedKey>>,
+}
+
+#[derive(Clone)]
+struct EncryptedKey {
+    public_key: [u8; 32],
+    salt: [u8; SALT_LENGTH],
+    nonce: [u8; NONCE_LENGTH],
+    encrypted_secret: Vec<u8>,
+}
+
+impl EncryptedKey {
+    fn encrypt(secret: &[u8], password: &str) -> Result<Self> {
+        let public_key = crypto::keypair_from_secret(secret)?.public.to_bytes();
+
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::rngs::OsRng.try_fill_bytes(&mut salt)?;
+        rand::rngs::OsRng.try_fill_bytes(&mut nonce)?;
+
+        let encrypted_secret = cipher(password, &salt)
+            .encrypt(Nonce::from_slice(&nonce), secret)
+            .map_err(|_| anyhow!("Failed to encrypt the secret"))?;
+        Ok(Self {
+            public_key,
+            salt,
+            nonce,
+            encrypted_secret,
+        })
+    }
+
+    /// A wrong password fails the authentication tag check
+    fn decrypt(&self, password: &str) -> Result<Vec<u8>> {
+        cipher(password, &self.salt)
+            .decrypt(
+                Nonce::from_slice(&self.nonce),
+                self.encrypted_secret.as_slice(),
+            )
+            .map_err(|_| anyhow!("Invalid password"))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "publicKey": hex::encode(self.public_key),
+            "salt": hex::encode(self.salt),
+            "nonce": hex::encode(self.nonce),
+            "encryptedSecret": hex::encode(&self.encrypted_secret),
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let field = |name: &str| -> Result<Vec<u8>> {
+            let value = value[name]
+                .as_str()
+                .ok_or_else(|| anyhow!("Missing `{name}` in stored key"))?;
+            hex::decode(value).with_context(|| format!("Invalid `{name}` in stored key"))
+        };
+        Ok(Self {
+            public_key: fixed("publicKey", field("publicKey")?)?,
+            salt: fixed("salt", field("salt")?)?,
+            nonce: fixed("nonce", field("nonce")?)?,
+            encrypted_secret: field("encryptedSecret")?,
+        })
+    }
+}
+
+fn fixed<const N: usize>(name: &str, bytes: Vec<u8>) -> Result<[u8; N]> {
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| anyhow!("Invalid `{name}` length in stored key: {len}"))
+}
+
+fn cipher(password: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+    key.zeroize();
+    cipher
+}
+
+/// `stored_keys` is a JSON array of keys from `keystoreExportKey`, or empty
+fn keystore_create(stored_keys: &str) -> Result<jlong> {
+    let keystore = KeyStore::default();
+    if !stored_keys.trim().is_empty() {
+        let stored: Vec<Value> =
+            serde_json::from_str(stored_keys).context("Invalid stored keys JSON")?;
+        let mut keys = keystore.keys.lock();
+        for value in &stored {
+            let key = EncryptedKey::from_json(value)?;
+            keys.insert(hex::encode(key.public_key), key);
+        }
+    }
+    Ok(KEYSTORES.insert(Arc::new(keystore)))
+}
+
+/// Returns the key id, which is the hex public key
+fn keystore_add_key(keystore_handle: jlong, secret: &[u8], password: &str) -> Result<String> {
+    let keystore = KEYSTORES.get(keystore_handle)?;
+    if password.is_empty() {
+        return Err(anyhow!("Password must not be empty"));
+    }
+    let key = EncryptedKey::encrypt(secret, password)?;
+    let key_id = hex::encode(key.public_key);
+    keystore.keys.lock().insert(key_id.clone(), key);
+    Ok(key_id)
+}
+
+fn get_key(keystore: &KeyStore, key_id: &str) -> Result<EncryptedKey> {
+    keystore
+        .keys
+        .lock()
+        .get(key_id)
+        .cloned()
+        .ok_or_else(|| anyhow!("Unknown key: {key_id}"))
+}
+
+/// The encrypted form, for persisting the keystore outside of the process
+fn keystore_export_key(keystore_handle: jlong, key_id: &str) -> Result<String> {
+    let keystore = KEYSTORES.get(keystore_handle)?;
+    Ok(get_key(&keystore, key_id)?.to_json().to_string())
+}
+
+fn keystore_sign(
+    keystore_handle: jlong,
+    key_id: &str,
+    password: &str,
+    data: &[u8],
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> Result<[u8; 64]> {
+    let keystore = KEYSTORES.get(keystore_handle)?;
+    let signature_id = crypto::parse_signature_id(signature_id, transport_handle)?;
+    let key = get_key(&keystore, key_id)?;
+
+    let mut secret = key.decrypt(password)?;
+    let keypair = crypto::keypair_from_secret(&secret);
+    secret.zeroize();
+    Ok(crypto::sign(&keypair?, data, signature_id))
+}
+
+/// `Legacy` phrases have no account id, `Bip39` ones derive the key of
+/// `account_id` the way EVER Wallet's `Labs` accounts do
+fn parse_account_type(mnemonic_type: &str, account_id: jint) -> Result<MnemonicType> {
+    match (mnemonic_type, account_id) {
+        ("Legacy", 0) => Ok(MnemonicType::Legacy),
+        ("Legacy", _) => Err(anyhow!("Legacy phrases have no account id")),
+        ("Bip39", account_id) => u16::try_from(account_id)
+            .map(MnemonicType::Labs)
+            .map_err(|_| anyhow!("Invalid account id: {account_id}")),
+        _ => Err(anyhow!("Unsupported mnemonic type: {mnemonic_type}")),
+    }
+}
+
+/// The JSON container of nekoton's `EncryptedKey`, which EVER Wallet stores its
+/// keys in. The secret is derived from `phrase` the way the account type says,
+/// so the wallet restores the same key from either encrypted part
+fn export_encrypted_key(
+    phrase: &str,
+    mnemonic_type: &str,
+    account_id: jint,
+    password: &str,
+) -> Result<String> {
+    if password.is_empty() {
+        return Err(anyhow!("Password must not be empty"));
+    }
+    let phrase = phrase.trim();
+    let account_type = parse_account_type(mnemonic_type, account_id)?;
+    let keypair = nekoton::crypto::derive_from_phrase(phrase, account_type)?;
+    let public_key = keypair.public.to_bytes();
+    let mut secret = keypair.secret.to_bytes();
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut private_key_nonce = [0u8; NONCE_LENGTH];
+    let mut seed_phrase_nonce = [0u8; NONCE_LENGTH];
+    rand::rngs::OsRng.try_fill_bytes(&mut salt)?;
+    rand::rngs::OsRng.try_fill_bytes(&mut private_key_nonce)?;
+    rand::rngs::OsRng.try_fill_bytes(&mut seed_phrase_nonce)?;
+
+    let cipher = cipher(password, &salt);
+    let enc_private_key = cipher
+        .encrypt(Nonce::from_slice(&private_key_nonce), secret.as_slice())
+        .map_err(|_| anyhow!("Failed to encrypt the secret"));
+    secret.zeroize();
+    let enc_private_key = enc_private_key?;
+    let enc_seed_phrase = cipher
+        .encrypt(Nonce::from_slice(&seed_phrase_nonce), phrase.as_bytes())
+        .map_err(|_| anyhow!("Failed to encrypt the seed phrase"))?;
+
+    Ok(json!({
+        "pubkey": hex::encode(public_key),
+        "name": "",
+        "account_type": serde_json::to_value(account_type)?,
+        "salt": hex::encode(salt),
+        "enc_private_key": hex::encode(enc_private_key),
+        "private_key_nonce": hex::encode(private_key_nonce),
+        "enc_seed_phrase": hex::encode(enc_seed_phrase),
+        "seed_phrase_nonce": hex::encode(seed_phrase_nonce),
+    })
+    .to_string())
+}
+
+/// 32-byte secret followed by the 32-byte public key, checked against `pubkey`
+fn import_encrypted_key(key_json: &str, password: &str) -> Result<Vec<u8>> {
+    let value: Value = serde_json::from_str(key_json).context("Invalid encrypted key JSON")?;
+    let field = |name: &str| -> Result<Vec<u8>> {
+        let value = value[name]
+            .as_str()
+            .ok_or_else(|| anyhow!("Missing `{name}` in encrypted key"))?;
+        hex::decode(value).with_context(|| format!("Invalid `{name}` in encrypted key"))
+    };
+    let public_key: [u8; 32] = fixed("pubkey", field("pubkey")?)?;
+    let salt: [u8; SALT_LENGTH] = fixed("salt", field("salt")?)?;
+    let nonce: [u8; NONCE_LENGTH] = fixed("private_key_nonce", field("private_key_nonce")?)?;
+
+    let mut secret = cipher(password, &salt)
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            field("enc_private_key")?.as_slice(),
+        )
+        .map_err(|_| anyhow!("Invalid password"))?;
+    let keypair = crypto::keypair_from_secret(&secret);
+    secret.zeroize();
+    let keypair = keypair?;
+    if keypair.public.to_bytes() != public_key {
+        return Err(anyhow!("Decrypted secret does not match `pubkey`"));
+    }
+    Ok(keypair.to_bytes().to_vec())
+}
+
+fn keystore_remove_key(keystore_handle: jlong, key_id: &str) -> Result<()> {
+    let keystore = KEYSTORES.get(keystore_handle)?;
+    match keystore.keys.lock().remove(key_id) {
+        Some(_) => Ok(()),
+        None => Err(anyhow!("Unknown key: {key_id}")),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_keystoreCreate(
+    mut env: JNIEnv,
+    _class: JClass,
+    stored_keys_json: JString,
+) -> jlong {
+    error::guard!(env, {
+        let stored_keys: String = match env.get_string(&stored_keys_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match keystore_create(&stored_keys) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_keystoreAddKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    keystore_handle: jlong,
+    secret_bytes: JByteArray,
+    password: JString,
+) -> jstring {
+    error::guard!(env, {
+        let mut secret = match env.convert_byte_array(secret_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let password: String = match env.get_string(&password) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                secret.zeroize();
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let key_id = keystore_add_key(keystore_handle, &secret, &password);
+        secret.zeroize();
+
+        let key_id = match key_id {
+            Ok(key_id) => key_id,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(key_id) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_keystoreExportKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    keystore_handle: jlong,
+    key_id: JString,
+) -> jstring {
+    error::guard!(env, {
+        let key_id: String = match env.get_string(&key_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let exported = match keystore_export_key(keystore_handle, &key_id) {
+            Ok(exported) => exported,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(exported) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_keystoreSign(
+    mut env: JNIEnv,
+    _class: JClass,
+    keystore_handle: jlong,
+    key_id: JString,
+    password: JString,
+    data: JByteArray,
+    signature_id: jlong,
+    transport_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let key_id: String = match env.get_string(&key_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let password: String = match env.get_string(&password) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let data = match env.convert_byte_array(data) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let signature = match keystore_sign(
+            keystore_handle,
+            &key_id,
+            &password,
+            &data,
+            signature_id,
+            transport_handle,
+        ) {
+            Ok(signature) => signature,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&signature) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_keystoreRemoveKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    keystore_handle: jlong,
+    key_id: JString,
+) {
+    error::guard!(env, {
+        let key_id: String = match env.get_string(&key_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = keystore_remove_key(keystore_handle, &key_id) {
+            error::throw_as(&mut env, NekotonError::Crypto, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupKeystore(
+    mut env: JNIEnv,
+    _class: JClass,
+    keystore_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = KEYSTORES.remove(keystore_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_exportEncryptedKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    phrase: JString,
+    mnemonic_type: JString,
+    account_id: jint,
+    password: JString,
+) -> jstring {
+    error::guard!(env, {
+        let mut phrase: String = match env.get_string(&phrase) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let mnemonic_type: String = match env.get_string(&mnemonic_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                phrase.zeroize();
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let password: String = match env.get_string(&password) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                phrase.zeroize();
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let key = export_encrypted_key(&phrase, &mnemonic_type, account_id, &password);
+        phrase.zeroize();
+
+        let key = match key {
+            Ok(key) => key,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(key) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_importEncryptedKey(
+    mut env: JNIEnv,
+    _class: JClass,
+    key_json: JString,
+    password: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let key_json: String = match env.get_string(&key_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let password: String = match env.get_string(&password) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let mut keypair = match import_encrypted_key(&key_json, &password) {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&keypair) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        keypair.zeroize();
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phrase(mnemonic_type: MnemonicType) -> String {
+        nekoton::crypto::generate_key(mnemonic_type).words.join(" ")
+    }
+
+    fn decrypt_seed_phrase(key: &Value, password: &str) -> String {
+        let field = |name: &str| hex::decode(key[name].as_str().unwrap()).unwrap();
+        let phrase = cipher(password, &field("salt"))
+            .decrypt(
+                Nonce::from_slice(&field("seed_phrase_nonce")),
+                field("enc_seed_phrase").as_slice(),
+            )
+            .unwrap();
+        String::from_utf8(phrase).unwrap()
+    }
+
+    #[test]
+    fn exported_key_holds_the_phrase_it_was_derived_from() {
+        for (mnemonic_type, account_id, account_type) in [
+            ("Legacy", 0, MnemonicType::Legacy),
+            ("Bip39", 3, MnemonicType::Labs(3)),
+        ] {
+            let phrase = phrase(account_type);
+            let exported =
+                export_encrypted_key(&phrase, mnemonic_type, account_id, "pass").unwrap();
+            let key: Value = serde_json::from_str(&exported).unwrap();
+
+            let expected = nekoton::crypto::derive_from_phrase(&phrase, account_type).unwrap();
+            assert_eq!(
+                import_encrypted_key(&exported, "pass").unwrap(),
+                expected.to_bytes()
+            );
+            assert_eq!(
+                key["account_type"],
+                serde_json::to_value(account_type).unwrap()
+            );
+            assert_eq!(decrypt_seed_phrase(&key, "pass"), phrase);
+        }
+    }
+
+    #[test]
+    fn export_rejects_mismatched_account_types() {
+        let legacy = phrase(MnemonicType::Legacy);
+        assert!(export_encrypted_key(&legacy, "Legacy", 1, "pass").is_err());
+        assert!(export_encrypted_key(&legacy, "Bip39", -1, "pass").is_err());
+        assert!(export_encrypted_key(&legacy, "Electrum", 0, "pass").is_err());
+        assert!(export_encrypted_key(&legacy, "Legacy", 0, "").is_err());
+    }
+}
@@ -1,38 +1,57 @@
 use jni::objects::{JClass, JString, JByteArray};
-use jni::sys::{jlong, jstring, jboolean, jbyteArray, jint};
+use jni::sys::{jlong, jstring, jbyteArray};
 use jni::JNIEnv;
 use nekoton_jetton::{JettonMetaData, META_NAME, META_SYMBOL};
 
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_initialize(
-    _env: JNIEnv,
-    _class: JClass,
-) -> jboolean {
-    #[cfg(target_os = "android")]
-    android_logger::init_once(
-        android_logger::Config::default()
-            .with_min_level(log::Level::Debug)
-            .with_tag("nekoton-jni"),
-    );
-    
-    #[cfg(not(target_os = "android"))]
-    {
-        let _ = env_logger::try_init();
-    }
-    
-    true as jboolean
-}
+mod abi;
+mod account;
+mod address;
+mod amount;
+mod callback;
+mod cell;
+mod clock;
+mod contract;
+mod crypto;
+mod dens;
+mod deploy;
+mod dict;
+mod encryption;
+mod error;
+mod executor;
+mod external;
+mod keystore;
+mod logging;
+mod message;
+mod mnemonic;
+mod models;
+mod nft;
+mod payload;
+mod proof;
+mod registry;
+mod runtime;
+mod slice;
+mod state_init;
+mod subscription;
+mod token;
+mod transaction;
+mod transport;
+mod wallet;
 
 #[no_mangle]
 pub extern "C" fn Java_com_mazekine_nekoton_Native_getVersion(
-    env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
 ) -> jstring {
-    let version = env!("CARGO_PKG_VERSION");
-    match env.new_string(version) {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
+    error::guard!(env, {
+        let version = env!("CARGO_PKG_VERSION");
+        match env.new_string(version) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
 }
 
 #[no_mangle]
@@ -42,358 +61,32 @@ pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanup(
 ) {
 }
 
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_createGqlTransport(
-    _env: JNIEnv,
-    _class: JClass,
-    _endpoint: JString,
-) -> jlong {
-    1 // Return placeholder handle
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_createJrpcTransport(
-    _env: JNIEnv,
-    _class: JClass,
-    _endpoint: JString,
-) -> jlong {
-    2 // Return placeholder handle
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_sendExternalMessage(
-    env: JNIEnv,
-    _class: JClass,
-    _transport_handle: jlong,
-    _message_boc: JByteArray,
-) -> jstring {
-    let success_msg = "Message sent successfully (placeholder)";
-    match env.new_string(success_msg) {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_getContractState(
-    env: JNIEnv,
-    _class: JClass,
-    _transport_handle: jlong,
-    _address: JString,
-) -> jbyteArray {
-    let placeholder_state = r#"{"balance":"0","isDeployed":false}"#;
-    let state_bytes = placeholder_state.as_bytes();
-    
-    match env.byte_array_from_slice(state_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransactions(
-    env: JNIEnv,
-    _class: JClass,
-    _transport_handle: jlong,
-    _address: JString,
-    _from_lt: jlong,
-    _count: jint,
-) -> jbyteArray {
-    let placeholder_transactions = "[]";
-    let tx_bytes = placeholder_transactions.as_bytes();
-    
-    match env.byte_array_from_slice(tx_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupTransport(
-    _env: JNIEnv,
-    _class: JClass,
-    _transport_handle: jlong,
-) {
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_generateKeyPair(
-    env: JNIEnv,
-    _class: JClass,
-) -> jbyteArray {
-    use rand::RngCore;
-    let mut secret_bytes = [0u8; 32];
-    rand::thread_rng().fill_bytes(&mut secret_bytes);
-    
-    match env.byte_array_from_slice(&secret_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_publicKeyFromSecret(
-    env: JNIEnv,
-    _class: JClass,
-    _secret_bytes: JByteArray,
-) -> jbyteArray {
-    let public_bytes = vec![0u8; 32];
-    
-    match env.byte_array_from_slice(&public_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_signData(
-    env: JNIEnv,
-    _class: JClass,
-    _secret_bytes: JByteArray,
-    _data: JByteArray,
-    _signature_id: jlong,
-) -> jbyteArray {
-    let signature_bytes = vec![0u8; 64];
-    
-    match env.byte_array_from_slice(&signature_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_verifySignature(
-    _env: JNIEnv,
-    _class: JClass,
-    _public_bytes: JByteArray,
-    _data: JByteArray,
-    _signature_bytes: JByteArray,
-    _signature_id: jlong,
-) -> jboolean {
-    true as jboolean
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_generateBip39Mnemonic(
-    env: JNIEnv,
-    _class: JClass,
-    word_count: jlong,
-) -> jstring {
-    let phrase = match word_count {
-        12 => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-        15 => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-        18 => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-        21 => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-        24 => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-        _ => "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-    };
-    
-    match env.new_string(phrase) {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_deriveBip39KeyPair(
-    env: JNIEnv,
-    _class: JClass,
-    _phrase: JString,
-    _path: JString,
-) -> jbyteArray {
-    let secret_bytes = vec![0u8; 32];
-    
-    match env.byte_array_from_slice(&secret_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAbi(
-    _env: JNIEnv,
-    _class: JClass,
-    _abi_json: JString,
-) -> jlong {
-    3 // Return placeholder handle
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiVersion(
-    env: JNIEnv,
-    _class: JClass,
-    _abi_handle: jlong,
-) -> jstring {
-    match env.new_string("2") {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_getAbiFunctionNames(
-    env: JNIEnv,
-    _class: JClass,
-    _abi_handle: jlong,
-) -> jbyteArray {
-    let function_names = r#"["constructor","getDetails"]"#;
-    let result = function_names.as_bytes();
-    
-    match env.byte_array_from_slice(result) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeFunctionCall(
-    env: JNIEnv,
-    _class: JClass,
-    _abi_handle: jlong,
-    _function_name: JString,
-    _inputs_json: JString,
-) -> jbyteArray {
-    let result = vec![0u8; 1];
-    
-    match env.byte_array_from_slice(&result) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeFunctionOutput(
-    env: JNIEnv,
-    _class: JClass,
-    _abi_handle: jlong,
-    _function_name: JString,
-    _output_boc: JByteArray,
-) -> jstring {
-    let result = r#"{"result": "placeholder"}"#;
-    
-    match env.new_string(result) {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupAbi(
-    _env: JNIEnv,
-    _class: JClass,
-    _abi_handle: jlong,
-) {
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_parseAddress(
-    env: JNIEnv,
-    _class: JClass,
-    _address_str: JString,
-) -> jbyteArray {
-    let placeholder_bytes = vec![0u8; 32];
-    match env.byte_array_from_slice(&placeholder_bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_formatAddress(
-    env: JNIEnv,
-    _class: JClass,
-    _address_bytes: JByteArray,
-    _user_friendly: jboolean,
-    _url_safe: jboolean,
-    _test_only: jboolean,
-    _bounce: jboolean,
-) -> jstring {
-    match env.new_string("placeholder_address") {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cellFromBoc(
-    _env: JNIEnv,
-    _class: JClass,
-    _boc_bytes: JByteArray,
-) -> jlong {
-    4 // Return placeholder handle
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cellToBoc(
-    env: JNIEnv,
-    _class: JClass,
-    _cell_handle: jlong,
-) -> jbyteArray {
-    let placeholder_boc = vec![0u8; 1];
-    match env.byte_array_from_slice(&placeholder_boc) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_getCellHash(
-    env: JNIEnv,
-    _class: JClass,
-    _cell_handle: jlong,
-) -> jbyteArray {
-    let hash = vec![0u8; 32];
-    match env.byte_array_from_slice(&hash) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_createCellBuilder(
-    _env: JNIEnv,
-    _class: JClass,
-) -> jlong {
-    5 // Return placeholder handle
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderStoreBytes(
-    _env: JNIEnv,
-    _class: JClass,
-    _builder_handle: jlong,
-    _data: JByteArray,
-) -> jboolean {
-    true as jboolean
-}
-
-#[no_mangle]
-pub extern "C" fn Java_com_mazekine_nekoton_Native_cellBuilderBuild(
-    _env: JNIEnv,
-    _class: JClass,
-    _builder_handle: jlong,
-) -> jlong {
-    6 // Return placeholder handle
-}
-
 #[no_mangle]
 pub extern "C" fn Java_com_mazekine_nekoton_Native_getJettonWalletAddress(
-    env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     _root_address: JByteArray,
     owner_address: JByteArray,
 ) -> jbyteArray {
-    // touch jetton metadata symbols to ensure dependency is linked
-    let _ = &*META_NAME;
-
-    let bytes = match env.convert_byte_array(owner_address) {
-        Ok(b) => b,
-        Err(_) => return std::ptr::null_mut(),
-    };
-    match env.byte_array_from_slice(&bytes) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
+    error::guard!(env, {
+        // touch jetton metadata symbols to ensure dependency is linked
+        let _ = &*META_NAME;
+
+        let bytes = match env.convert_byte_array(owner_address) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&bytes) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
 }
 
 #[no_mangle]
@@ -402,17 +95,25 @@ pub extern "C" fn Java_com_mazekine_nekoton_Native_parseJettonWalletState(
     _class: JClass,
     state: JString,
 ) -> jstring {
-    // create empty metadata via nekoton-jetton just to demonstrate usage
-    let _ = JettonMetaData::from(&std::collections::HashMap::new());
-
-    let state_str: String = match env.get_string(&state) {
-        Ok(s) => s.into(),
-        Err(_) => return std::ptr::null_mut(),
-    };
-    match env.new_string(state_str) {
-        Ok(s) => s.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
+    error::guard!(env, {
+        // create empty metadata via nekoton-jetton just to demonstrate usage
+        let _ = JettonMetaData::from(&std::collections::HashMap::new());
+
+        let state_str: String = match env.get_string(&state) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(state_str) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
 }
 
 #[no_mangle]
@@ -423,19 +124,27 @@ pub extern "C" fn Java_com_mazekine_nekoton_Native_buildJettonTransfer(
     to: JByteArray,
     amount: jlong,
 ) -> jbyteArray {
-    let _ = &*META_SYMBOL;
-
-    let mut result = Vec::new();
-    result.extend_from_slice(&amount.to_le_bytes());
-
-    let to_bytes = match env.convert_byte_array(to) {
-        Ok(b) => b,
-        Err(_) => return std::ptr::null_mut(),
-    };
-    result.extend_from_slice(&to_bytes);
-
-    match env.byte_array_from_slice(&result) {
-        Ok(arr) => arr.into_raw(),
-        Err(_) => std::ptr::null_mut(),
-    }
+    error::guard!(env, {
+        let _ = &*META_SYMBOL;
+
+        let mut result = Vec::new();
+        result.extend_from_slice(&amount.to_le_bytes());
+
+        let to_bytes = match env.convert_byte_array(to) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        result.extend_from_slice(&to_bytes);
+
+        match env.byte_array_from_slice(&result) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
 }
@@ -0,0 +1,164 @@
+use std::cell::Cell;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use jni::objects::{JClass, JObject};
+use jni::sys::{jboolean, jint};
+use jni::JNIEnv;
+use log::kv::{self, Key, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde_json::{json, Map, Value};
+
+use crate::callback::JavaCallback;
+use crate::error;
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LISTENER: Lazy<RwLock<Option<Arc<JavaCallback>>>> = Lazy::new(|| RwLock::new(None));
+
+thread_local! {
+    /// Set while a record is handed to Kotlin, so whatever gets logged on the
+    /// way there does not loop back into the listener
+    static FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Writes to the platform backend and forwards to the Kotlin listener, if any
+struct Logger {
+    backend: Box<dyn Log>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.backend.log(record);
+        forward(record);
+    }
+
+    fn flush(&self) {
+        self.backend.flush();
+    }
+}
+
+/// Collects the key-values of a record as strings
+struct Fields(Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0
+            .insert(key.to_string(), Value::String(value.to_string()));
+        Ok(())
+    }
+}
+
+fn forward(record: &Record) {
+    // Cloned out, so the listener may replace itself without a deadlock
+    let listener = match LISTENER.read().clone() {
+        Some(listener) => listener,
+        None => return,
+    };
+    if FORWARDING.with(|forwarding| forwarding.replace(true)) {
+        return;
+    }
+
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+    let event = json!({
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "module": record.module_path(),
+        "file": record.file(),
+        "line": record.line(),
+        "fields": fields.0,
+    });
+    listener.notify("onLog", event.to_string());
+
+    FORWARDING.with(|forwarding| forwarding.set(false));
+}
+
+/// OFF, ERROR, WARN, INFO, DEBUG, TRACE
+fn parse_level(level: jint) -> Result<LevelFilter> {
+    Ok(match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        5 => LevelFilter::Trace,
+        _ => return Err(anyhow!("Unknown log level: {level}")),
+    })
+}
+
+/// The backends let everything through, so the global max level of the `log`
+/// crate is the only filter and can be changed at any time
+fn init() {
+    #[cfg(target_os = "android")]
+    let backend = android_logger::AndroidLogger::new(
+        android_logger::Config::default()
+            .with_max_level(LevelFilter::Trace)
+            .with_tag("nekoton-jni"),
+    );
+
+    #[cfg(not(target_os = "android"))]
+    let backend = env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .build();
+
+    // Only the first call installs the logger
+    let _ = log::set_boxed_logger(Box::new(Logger {
+        backend: Box::new(backend),
+    }));
+    log::set_max_level(DEFAULT_LEVEL);
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_initialize(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    error::guard!(env, {
+        init();
+        true as jboolean
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setLogLevel(
+    mut env: JNIEnv,
+    _class: JClass,
+    level: jint,
+) {
+    error::guard!(env, {
+        match parse_level(level) {
+            Ok(level) => log::set_max_level(level),
+            Err(e) => error::throw(&mut env, e),
+        }
+    })
+}
+
+/// Forwards every record that passes the log level to `onLog(String)` of
+/// `callback` as JSON with level, target, message, source location and the
+/// record's key-value fields, such as `request_id` of transport requests.
+/// A null `callback` removes the listener. Takes effect once `initialize` has run
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setLogCallback(
+    mut env: JNIEnv,
+    _class: JClass,
+    callback: JObject,
+) {
+    error::guard!(env, {
+        if callback.is_null() {
+            *LISTENER.write() = None;
+            return;
+        }
+        match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => *LISTENER.write() = Some(Arc::new(callback)),
+            Err(e) => error::throw(&mut env, e),
+        }
+    })
+}
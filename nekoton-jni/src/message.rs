@@ -0,0 +1,302 @@
+use anyhow::{anyhow, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jbyteArray, jlong, jstring};
+use jni::JNIEnv;
+use ton_block::{
+    Deserializable, InternalMessageHeader, Message, MsgAddressIntOrNone, Serializable, StateInit,
+};
+use ton_types::SliceData;
+
+use crate::{address, cell, error, models};
+
+fn build_transfer(
+    destination: &str,
+    amount: jlong,
+    bounce: bool,
+    extra_currencies: &str,
+    body: &[u8],
+) -> Result<Vec<u8>> {
+    let amount = u64::try_from(amount).map_err(|_| anyhow!("Amount must not be negative"))?;
+    let mut message = Message::with_int_header(InternalMessageHeader {
+        ihr_disabled: true,
+        bounce,
+        dst: nekoton_utils::repack_address(destination)?,
+        value: models::parse_currency_collection(amount, extra_currencies)?,
+        ..Default::default()
+    });
+    if !body.is_empty() {
+        message.set_body(SliceData::load_cell(cell::parse_boc(body)?)?);
+    }
+
+    cell::serialize_boc(&message.serialize()?)
+}
+
+/// Empty `src` leaves the source for the sending contract to fill in.
+/// `extra_currencies` is the same JSON object `buildTransfer` takes
+fn create_internal_message(
+    src: &str,
+    dst: &str,
+    value: jlong,
+    bounce: bool,
+    extra_currencies: &str,
+    body: &[u8],
+    state_init: &[u8],
+) -> Result<Vec<u8>> {
+    let value = u64::try_from(value).map_err(|_| anyhow!("Value must not be negative"))?;
+    let src = match src.is_empty() {
+        true => MsgAddressIntOrNone::None,
+        false => MsgAddressIntOrNone::Some(nekoton_utils::repack_address(src)?),
+    };
+    let mut message = Message::with_int_header(InternalMessageHeader {
+        ihr_disabled: true,
+        bounce,
+        src,
+        dst: nekoton_utils::repack_address(dst)?,
+        value: models::parse_currency_collection(value, extra_currencies)?,
+        ..Default::default()
+    });
+    if !body.is_empty() {
+        message.set_body(SliceData::load_cell(cell::parse_boc(body)?)?);
+    }
+    if !state_init.is_empty() {
+        message.set_state_init(StateInit::construct_from_cell(cell::parse_boc(
+            state_init,
+        )?)?);
+    }
+
+    cell::serialize_boc(&message.serialize()?)
+}
+
+fn parse_message(message_boc: &[u8]) -> Result<String> {
+    Ok(models::message_to_json(cell::parse_boc(message_boc)?)?.to_string())
+}
+
+/// External outbound messages have no internal destination and yield `None`
+fn get_message_destination(message_boc: &[u8]) -> Result<Option<Vec<u8>>> {
+    let message = Message::construct_from_cell(cell::parse_boc(message_boc)?)?;
+    message
+        .dst()
+        .map(|dst| address::address_to_bytes(&dst))
+        .transpose()
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_buildTransfer(
+    mut env: JNIEnv,
+    _class: JClass,
+    destination: JString,
+    amount: jlong,
+    bounce: jboolean,
+    extra_currencies_json: JString,
+    body_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let destination: String = match env.get_string(&destination) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let extra_currencies: String = match env.get_string(&extra_currencies_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let body = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let message =
+            match build_transfer(&destination, amount, bounce != 0, &extra_currencies, &body) {
+                Ok(message) => message,
+                Err(e) => {
+                    error::throw(&mut env, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.byte_array_from_slice(&message) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getMessageDestination(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let message = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let destination = match get_message_destination(&message) {
+            Ok(Some(destination)) => destination,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&destination) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createInternalMessageBoc(
+    mut env: JNIEnv,
+    _class: JClass,
+    src: JString,
+    dst: JString,
+    value: jlong,
+    bounce: jboolean,
+    extra_currencies_json: JString,
+    body_boc: JByteArray,
+    state_init_boc: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let src: String = match env.get_string(&src) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let dst: String = match env.get_string(&dst) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let extra_currencies: String = match env.get_string(&extra_currencies_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let body = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let state_init = match env.convert_byte_array(state_init_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let message = match create_internal_message(
+            &src,
+            &dst,
+            value,
+            bounce != 0,
+            &extra_currencies,
+            &body,
+            &state_init,
+        ) {
+            Ok(message) => message,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&message) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    message_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let message = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let message = match parse_message(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(message) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    #[test]
+    fn internal_messages_carry_extra_currencies() {
+        let extra = r#"{"1": "500", "7": "12345678901234567890"}"#;
+        let boc = create_internal_message("", ADDRESS, 1_000, true, extra, &[], &[]).unwrap();
+        let message = Message::construct_from_cell(cell::parse_boc(&boc).unwrap()).unwrap();
+        let value = message.get_value().unwrap();
+        assert_eq!(
+            value,
+            &models::parse_currency_collection(1_000, extra).unwrap()
+        );
+        assert_eq!(value.grams.as_u128(), 1_000);
+
+        let boc = create_internal_message("", ADDRESS, 1_000, true, "", &[], &[]).unwrap();
+        let message = Message::construct_from_cell(cell::parse_boc(&boc).unwrap()).unwrap();
+        assert_eq!(
+            message.get_value().unwrap(),
+            &ton_block::CurrencyCollection::with_grams(1_000)
+        );
+        assert!(
+            create_internal_message("", ADDRESS, 1_000, true, r#"{"1": "-5"}"#, &[], &[]).is_err()
+        );
+    }
+}
@@ -0,0 +1,502 @@
+use anyhow::{anyhow, Result};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use jni::objects::{JClass, JString};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::crypto::MnemonicType as NekotonMnemonicType;
+use tiny_hderive::bip32::ExtendedPrivKey;
+use zeroize::Zeroize;
+
+use crate::crypto;
+use crate::error::{self, NekotonError};
+
+const DEFAULT_DERIVATION_PATH: &str = "m/44'/396'/0'/0/0";
+const DEFAULT_BASE_PATH: &str = "m/44'/396'/0'/0";
+const MAX_DERIVED_KEYS: u32 = 100;
+
+/// A BIP39 language code: en, es, fr, it, ja, ko, zh-hans or zh-hant.
+/// Empty means English
+fn parse_language(language: &str) -> Result<Language> {
+    match language.trim() {
+        "" => Ok(Language::English),
+        code => Language::from_language_code(code)
+            .ok_or_else(|| anyhow!("Unsupported mnemonic language: {code}")),
+    }
+}
+
+fn generate_bip39_mnemonic(word_count: jlong, language: &str) -> Result<String> {
+    let language = parse_language(language)?;
+    let word_count = match word_count {
+        12 | 15 | 18 | 21 | 24 => word_count as usize,
+        _ => {
+            return Err(anyhow!(
+                "Unsupported word count {word_count}, expected 12, 15, 18, 21 or 24"
+            ))
+        }
+    };
+    let mnemonic = Mnemonic::new(MnemonicType::for_word_count(word_count)?, language);
+    Ok(mnemonic.into_phrase())
+}
+
+/// 32-byte secret followed by the 32-byte public key
+fn derive_bip39_key_pair(phrase: &str, path: &str, language: &str) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::from_phrase(phrase.trim(), parse_language(language)?)?;
+    let path = match path.trim() {
+        "" => DEFAULT_DERIVATION_PATH,
+        path => path,
+    };
+
+    let seed = Seed::new(&mnemonic, "");
+    derive_from_seed(&seed, path)
+}
+
+fn derive_from_seed(seed: &Seed, path: &str) -> Result<Vec<u8>> {
+    let derived = ExtendedPrivKey::derive(seed.as_bytes(), path)
+        .map_err(|e| anyhow!("Invalid derivation path {path:?}: {e:?}"))?;
+    let mut secret = derived.secret();
+    let keypair = crypto::keypair_from_secret(&secret);
+    secret.zeroize();
+    Ok(keypair?.to_bytes().to_vec())
+}
+
+/// Key pairs for `{base_path}/{index}`, concatenated in index order. The seed is
+/// only stretched once for the whole batch
+fn derive_bip39_key_pairs(
+    phrase: &str,
+    base_path: &str,
+    start_index: jint,
+    count: jint,
+    language: &str,
+) -> Result<Vec<u8>> {
+    let start_index = u32::try_from(start_index).map_err(|_| anyhow!("Invalid start index"))?;
+    let count = match u32::try_from(count) {
+        Ok(count @ 1..=MAX_DERIVED_KEYS) => count,
+        _ => {
+            return Err(anyhow!(
+                "Count must be between 1 and {MAX_DERIVED_KEYS}, got {count}"
+            ))
+        }
+    };
+    let end_index = start_index
+        .checked_add(count)
+        .filter(|end| *end <= 1 << 31)
+        .ok_or_else(|| anyhow!("Derivation index out of range"))?;
+
+    let mnemonic = Mnemonic::from_phrase(phrase.trim(), parse_language(language)?)?;
+    let base_path = match base_path.trim().trim_end_matches('/') {
+        "" => DEFAULT_BASE_PATH,
+        path => path,
+    };
+
+    let seed = Seed::new(&mnemonic, "");
+    let mut keypairs = Vec::with_capacity(count as usize * 64);
+    for index in start_index..end_index {
+        let mut keypair = derive_from_seed(&seed, &format!("{base_path}/{index}"))?;
+        keypairs.extend_from_slice(&keypair);
+        keypair.zeroize();
+    }
+    Ok(keypairs)
+}
+
+fn generate_legacy_mnemonic() -> String {
+    nekoton::crypto::generate_key(NekotonMnemonicType::Legacy)
+        .words
+        .join(" ")
+}
+
+/// The legacy scheme derives the key from the phrase alone, without a path
+fn derive_legacy_key_pair(phrase: &str) -> Result<Vec<u8>> {
+    let keypair = nekoton::crypto::derive_from_phrase(phrase.trim(), NekotonMnemonicType::Legacy)?;
+    Ok(keypair.to_bytes().to_vec())
+}
+
+/// Checks word count, words and (for BIP39) the checksum without deriving anything.
+/// Only an unknown mnemonic type or language is an error, `language` applies to BIP39
+fn validate_mnemonic(phrase: &str, mnemonic_type: &str, language: &str) -> Result<bool> {
+    let phrase = phrase.trim();
+    match mnemonic_type {
+        "Bip39" => Ok(Mnemonic::validate(phrase, parse_language(language)?).is_ok()),
+        // The legacy scheme has no checksum, nekoton only checks the word list
+        "Legacy" => {
+            Ok(nekoton::crypto::derive_from_phrase(phrase, NekotonMnemonicType::Legacy).is_ok())
+        }
+        _ => Err(anyhow!("Unsupported mnemonic type: {mnemonic_type}")),
+    }
+}
+
+/// JSON array of the BIP39 words starting with `prefix`, in wordlist order.
+/// An empty prefix matches nothing
+fn suggest_mnemonic_words(prefix: &str, language: &str) -> Result<String> {
+    let language = parse_language(language)?;
+    let prefix = prefix.trim().to_lowercase();
+    let words = match prefix.is_empty() {
+        true => &[][..],
+        false => language.wordlist().get_words_by_prefix(&prefix),
+    };
+    Ok(serde_json::to_string(words)?)
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_generateBip39Mnemonic(
+    mut env: JNIEnv,
+    _class: JClass,
+    word_count: jlong,
+    language: JString,
+) -> jstring {
+    error::guard!(env, {
+        let language: String = match env.get_string(&language) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let phrase = match generate_bip39_mnemonic(word_count, &language) {
+            Ok(phrase) => phrase,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(phrase) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_deriveBip39KeyPair(
+    mut env: JNIEnv,
+    _class: JClass,
+    phrase: JString,
+    path: JString,
+    language: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let phrase: String = match env.get_string(&phrase) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let path: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let language: String = match env.get_string(&language) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let mut keypair = match derive_bip39_key_pair(&phrase, &path, &language) {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&keypair) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        keypair.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_deriveBip39KeyPairs(
+    mut env: JNIEnv,
+    _class: JClass,
+    phrase: JString,
+    base_path: JString,
+    start_index: jint,
+    count: jint,
+    language: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let phrase: String = match env.get_string(&phrase) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let base_path: String = match env.get_string(&base_path) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let language: String = match env.get_string(&language) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let mut keypairs =
+            match derive_bip39_key_pairs(&phrase, &base_path, start_index, count, &language) {
+                Ok(keypairs) => keypairs,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Crypto, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        let result = match env.byte_array_from_slice(&keypairs) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        keypairs.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_generateLegacyMnemonic(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    error::guard!(env, {
+        match env.new_string(generate_legacy_mnemonic()) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_deriveLegacyKeyPair(
+    mut env: JNIEnv,
+    _class: JClass,
+    phrase: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let phrase: String = match env.get_string(&phrase) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let mut keypair = match derive_legacy_key_pair(&phrase) {
+            Ok(keypair) => keypair,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        let result = match env.byte_array_from_slice(&keypair) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        };
+        keypair.zeroize();
+        result
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_validateMnemonic(
+    mut env: JNIEnv,
+    _class: JClass,
+    phrase: JString,
+    mnemonic_type: JString,
+    language: JString,
+) -> jboolean {
+    error::guard!(env, {
+        let phrase: String = match env.get_string(&phrase) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        let mnemonic_type: String = match env.get_string(&mnemonic_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        let language: String = match env.get_string(&language) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+
+        match validate_mnemonic(&phrase, &mnemonic_type, &language) {
+            Ok(valid) => valid as jboolean,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                false as jboolean
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_suggestMnemonicWords(
+    mut env: JNIEnv,
+    _class: JClass,
+    prefix: JString,
+    language: JString,
+) -> jstring {
+    error::guard!(env, {
+        let prefix: String = match env.get_string(&prefix) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let language: String = match env.get_string(&language) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let words = match suggest_mnemonic_words(&prefix, &language) {
+            Ok(words) => words,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Crypto, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(words) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The BIP39 test vector with the empty passphrase
+    const BIP39_PHRASE: &str = "abandon abandon abandon abandon abandon abandon \
+                                abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn bip39_phrase_derives_the_known_key_pair() {
+        let keypair = derive_bip39_key_pair(BIP39_PHRASE, "m/44'/396'/0'/0/0", "").unwrap();
+        assert_eq!(
+            hex::encode(&keypair[..32]),
+            "f3d1b6f23d9d0df2dec233b7ded22662c55b4fb5fd2b3a3ffeb83d6834562456"
+        );
+        assert_eq!(
+            hex::encode(&keypair[32..]),
+            "77c647c114a311fc70d8f6d52d6ffef1ee105e36eba5a8769e3ce4d1a25bde25"
+        );
+        assert_eq!(
+            derive_bip39_key_pair(BIP39_PHRASE, "", "").unwrap(),
+            keypair
+        );
+
+        let keypairs = derive_bip39_key_pairs(BIP39_PHRASE, "", 0, 2, "").unwrap();
+        assert_eq!(keypairs[..64], keypair);
+        assert_eq!(
+            hex::encode(&keypairs[96..]),
+            "e0fcd8cc269fe5e55c332fd1e153fa1e21fc0f9a4948ecda4bb5dd87075e95ca"
+        );
+    }
+
+    #[test]
+    fn bip39_derivation_rejects_invalid_phrases_and_paths() {
+        // Right words, wrong checksum
+        let checksum = BIP39_PHRASE.replace("about", "abandon");
+        assert!(derive_bip39_key_pair(&checksum, "", "").is_err());
+        assert!(!validate_mnemonic(&checksum, "Bip39", "").unwrap());
+        assert!(derive_bip39_key_pair(&BIP39_PHRASE.replace("about", "nekoton"), "", "").is_err());
+        assert!(derive_bip39_key_pair("abandon about", "", "").is_err());
+
+        for path in ["44'/396'/0'/0/0", "m/44'/396'/x/0/0", "m/44''/396'", "m//0"] {
+            assert!(
+                derive_bip39_key_pair(BIP39_PHRASE, path, "").is_err(),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_phrase_derives_the_same_key_pair() {
+        let phrase = generate_legacy_mnemonic();
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert!(validate_mnemonic(&phrase, "Legacy", "").unwrap());
+
+        let keypair = derive_legacy_key_pair(&phrase).unwrap();
+        assert_eq!(keypair.len(), 64);
+        assert_eq!(
+            crypto::keypair_from_secret(&keypair[..32])
+                .unwrap()
+                .public
+                .to_bytes(),
+            keypair[32..]
+        );
+        assert_eq!(
+            derive_legacy_key_pair(&format!("  {phrase}\n")).unwrap(),
+            keypair
+        );
+        assert_ne!(
+            derive_legacy_key_pair(&generate_legacy_mnemonic()).unwrap(),
+            keypair
+        );
+    }
+
+    #[test]
+    fn legacy_phrase_derives_the_known_key_pair() {
+        // TON's scheme: PBKDF2-SHA512 over HMAC-SHA512(phrase), salted with "TON default seed"
+        let phrase = format!("{}art", "abandon ".repeat(23));
+        let keypair = derive_legacy_key_pair(&phrase).unwrap();
+        assert_eq!(
+            hex::encode(&keypair[..32]),
+            "88965e4e6f686bad4be63761f4d8fa1cc682bccf11f8382bd281304d07b76edc"
+        );
+        assert_eq!(
+            hex::encode(&keypair[32..]),
+            "abbd2a1c784a6086850c172bcc7d56208e4dea0a51b9389ba21d174ff864c17a"
+        );
+    }
+
+    #[test]
+    fn legacy_derivation_rejects_other_phrases() {
+        let bip39 = generate_bip39_mnemonic(12, "").unwrap();
+        assert!(derive_legacy_key_pair(&bip39).is_err());
+        assert!(!validate_mnemonic(&bip39, "Legacy", "").unwrap());
+        assert!(derive_legacy_key_pair("").is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use nekoton::transport::models::RawContractState;
+use nekoton_abi::LastTransactionId;
+use serde_json::{json, Map, Value};
+use ton_block::{
+    Account, AccountState, AccountStatus, CommonMsgInfo, CurrencyCollection, Deserializable,
+    ExtraCurrencyCollection, Message, Serializable, TrComputePhase, Transaction, TransactionDescr,
+    VarUInteger32,
+};
+use ton_types::{Cell, UInt256};
+
+/// State JSON of an account that doesn't exist, like before its first transaction
+pub fn nonexistent_state_to_json() -> Value {
+    json!({
+        "balance": "0",
+        "isDeployed": false,
+        "status": account_status_to_str(&AccountStatus::AccStateNonexist),
+        "lastTransactionLt": Value::Null,
+        "lastTransactionHash": Value::Null,
+        "codeHash": Value::Null,
+        "stateInitBoc": Value::Null,
+        "extraCurrencies": {},
+    })
+}
+
+/// Hashes are hex, the StateInit is a base64 BOC; fields that need a deployed
+/// account are null otherwise
+pub fn contract_state_to_json(state: &RawContractState) -> Result<Value> {
+    match state {
+        RawContractState::NotExists { .. } => Ok(nonexistent_state_to_json()),
+        RawContractState::Exists(contract) => {
+            let storage = &contract.account.storage;
+            let (last_transaction_lt, last_transaction_hash) = match contract.last_transaction_id {
+                LastTransactionId::Exact(id) => (id.lt, Some(id.hash.to_hex_string())),
+                LastTransactionId::Inexact { latest_lt } => (latest_lt, None),
+            };
+            let (code_hash, state_init_boc) = match &storage.state {
+                AccountState::AccountActive { state_init } => (
+                    state_init
+                        .code
+                        .as_ref()
+                        .map(|code| code.repr_hash().to_hex_string()),
+                    Some(base64::encode(ton_types::serialize_toc(
+                        &state_init.serialize()?,
+                    )?)),
+                ),
+                _ => (None, None),
+            };
+
+            Ok(json!({
+                "balance": storage.balance.grams.as_u128().to_string(),
+                "isDeployed": matches!(storage.state, AccountState::AccountActive { .. }),
+                "status": account_state_to_str(&storage.state),
+                "lastTransactionLt": last_transaction_lt.to_string(),
+                "lastTransactionHash": last_transaction_hash,
+                "codeHash": code_hash,
+                "stateInitBoc": state_init_boc,
+                "extraCurrencies": extra_currencies_to_json(&storage.balance.other)?,
+            }))
+        }
+    }
+}
+
+/// Everything a serialized account carries. The last transaction hash is only
+/// known when the account comes wrapped in its `ShardAccount`
+pub fn account_to_json(
+    account: &Account,
+    last_transaction_hash: Option<&UInt256>,
+) -> Result<Value> {
+    let account = match account {
+        Account::AccountNone => {
+            return Ok(json!({
+                "address": Value::Null,
+                "balance": "0",
+                "isDeployed": false,
+                "status": account_status_to_str(&AccountStatus::AccStateNonexist),
+                "lastTransactionLt": Value::Null,
+                "lastTransactionHash": Value::Null,
+                "codeHash": Value::Null,
+                "dataHash": Value::Null,
+                "frozenStateHash": Value::Null,
+                "storage": Value::Null,
+                "extraCurrencies": {},
+            }))
+        }
+        Account::Account(account) => account,
+    };
+
+    let storage = &account.storage;
+    let (code_hash, data_hash, frozen_state_hash) = match &storage.state {
+        AccountState::AccountActive { state_init } => (
+            state_init
+                .code
+                .as_ref()
+                .map(|code| code.repr_hash().to_hex_string()),
+            state_init
+                .data
+                .as_ref()
+                .map(|data| data.repr_hash().to_hex_string()),
+            None,
+        ),
+        AccountState::AccountFrozen { state_init_hash } => {
+            (None, None, Some(state_init_hash.to_hex_string()))
+        }
+        AccountState::AccountUninit => (None, None, None),
+    };
+    let stat = &account.storage_stat;
+
+    Ok(json!({
+        "address": account.addr.to_string(),
+        "balance": storage.balance.grams.as_u128().to_string(),
+        "isDeployed": matches!(storage.state, AccountState::AccountActive { .. }),
+        "status": account_state_to_str(&storage.state),
+        "lastTransactionLt": storage.last_trans_lt.to_string(),
+        "lastTransactionHash": last_transaction_hash.map(UInt256::to_hex_string),
+        "codeHash": code_hash,
+        "dataHash": data_hash,
+        "frozenStateHash": frozen_state_hash,
+        "storage": {
+            "usedCells": stat.used.cells().to_string(),
+            "usedBits": stat.used.bits().to_string(),
+            "usedPublicCells": stat.used.public_cells().to_string(),
+            "lastPaid": stat.last_paid,
+            "duePayment": stat
+                .due_payment
+                .as_ref()
+                .map(|due| due.as_u128().to_string()),
+        },
+        "extraCurrencies": extra_currencies_to_json(&storage.balance.other)?,
+    }))
+}
+
+pub fn extra_currencies_to_json(other: &ExtraCurrencyCollection) -> Result<Value> {
+    let mut currencies = Map::new();
+    other.iterate_with_keys(|id: u32, amount: VarUInteger32| {
+        currencies.insert(id.to_string(), Value::String(amount.value().to_string()));
+        Ok(true)
+    })?;
+    Ok(Value::Object(currencies))
+}
+
+/// Builds a currency collection from a nano-token amount and an optional
+/// `{"<currency id>": "<amount>"}` JSON object of extra currencies
+pub fn parse_currency_collection(
+    amount: u64,
+    extra_currencies: &str,
+) -> Result<CurrencyCollection> {
+    let mut value = CurrencyCollection::with_grams(amount);
+    if extra_currencies.trim().is_empty() {
+        return Ok(value);
+    }
+
+    let extra: HashMap<u32, String> =
+        serde_json::from_str(extra_currencies).context("Invalid extra currencies JSON")?;
+    for (id, amount) in extra {
+        let amount: u128 = amount
+            .parse()
+            .with_context(|| format!("Invalid amount for extra currency {id}"))?;
+        value.set_other(id, amount)?;
+    }
+    Ok(value)
+}
+
+pub fn account_status_to_str(status: &AccountStatus) -> &'static str {
+    match status {
+        AccountStatus::AccStateUninit => "uninit",
+        AccountStatus::AccStateFrozen => "frozen",
+        AccountStatus::AccStateActive => "active",
+        AccountStatus::AccStateNonexist => "nonexist",
+    }
+}
+
+pub fn account_state_to_str(state: &AccountState) -> &'static str {
+    account_status_to_str(&match state {
+        AccountState::AccountUninit => AccountStatus::AccStateUninit,
+        AccountState::AccountActive { .. } => AccountStatus::AccStateActive,
+        AccountState::AccountFrozen { .. } => AccountStatus::AccStateFrozen,
+    })
+}
+
+pub fn parse_hash(hash: &str) -> Result<UInt256> {
+    hash.trim()
+        .parse::<UInt256>()
+        .map_err(|e| anyhow!("Invalid hash {hash}: {e}"))
+}
+
+pub fn hash_from_bytes(hash: &[u8]) -> Result<UInt256> {
+    if hash.len() != 32 {
+        return Err(anyhow!("Hash must be 32 bytes, got {}", hash.len()));
+    }
+    Ok(UInt256::from_slice(hash))
+}
+
+pub fn message_to_json(cell: Cell) -> Result<Value> {
+    let hash = cell.repr_hash();
+    let message = Message::construct_from_cell(cell)?;
+
+    let (kind, value, bounce, bounced) = match message.header() {
+        CommonMsgInfo::IntMsgInfo(header) => (
+            "internal",
+            Value::String(header.value.grams.as_u128().to_string()),
+            header.bounce,
+            header.bounced,
+        ),
+        CommonMsgInfo::ExtInMsgInfo(_) => ("externalIn", Value::Null, false, false),
+        CommonMsgInfo::ExtOutMsgInfo(_) => ("externalOut", Value::Null, false, false),
+    };
+    let (body, body_hash) = match message.body() {
+        Some(body) => {
+            let body = body.into_cell();
+            (
+                Value::String(base64::encode(ton_types::serialize_toc(&body)?)),
+                Value::String(body.repr_hash().to_hex_string()),
+            )
+        }
+        None => (Value::Null, Value::Null),
+    };
+
+    Ok(json!({
+        "hash": hash.to_hex_string(),
+        "type": kind,
+        "src": message.src().map(|src| src.to_string()),
+        "dst": message.dst().map(|dst| dst.to_string()),
+        "value": value,
+        "bounce": bounce,
+        "bounced": bounced,
+        "body": body,
+        "bodyHash": body_hash,
+        "hasStateInit": message.state_init().is_some(),
+    }))
+}
+
+pub fn transaction_to_json(hash: &UInt256, transaction: &Transaction) -> Result<Value> {
+    let description = transaction.read_description()?;
+    let in_message = match transaction.in_msg_cell() {
+        Some(cell) => message_to_json(cell)?,
+        None => Value::Null,
+    };
+    let mut out_messages = Vec::new();
+    transaction.out_msgs.iterate_slices(|slice| {
+        out_messages.push(message_to_json(slice.reference(0)?)?);
+        Ok(true)
+    })?;
+
+    Ok(json!({
+        "hash": hash.to_hex_string(),
+        "lt": transaction.lt.to_string(),
+        "prevTransLt": transaction.prev_trans_lt.to_string(),
+        "now": transaction.now,
+        "origStatus": account_status_to_str(&transaction.orig_status),
+        "endStatus": account_status_to_str(&transaction.end_status),
+        "totalFees": transaction.total_fees().grams.as_u128().to_string(),
+        "aborted": description.is_aborted(),
+        "inMessage": in_message,
+        "outMessages": out_messages,
+        "storage": storage_phase_to_json(&description),
+        "compute": compute_phase_to_json(&description),
+        "action": action_phase_to_json(&description),
+    }))
+}
+
+fn storage_phase_to_json(description: &TransactionDescr) -> Value {
+    let storage = match description {
+        TransactionDescr::Ordinary(description) => description.storage_ph.as_ref(),
+        TransactionDescr::TickTock(description) => Some(&description.storage),
+        TransactionDescr::SplitPrepare(description) => description.storage_ph.as_ref(),
+        TransactionDescr::MergeInstall(description) => description.storage_ph.as_ref(),
+        _ => None,
+    };
+
+    match storage {
+        Some(storage) => json!({
+            "feesCollected": storage.storage_fees_collected.as_u128().to_string(),
+            "feesDue": storage
+                .storage_fees_due
+                .as_ref()
+                .map(|fees| fees.as_u128().to_string()),
+            "statusChange": format!("{:?}", storage.status_change),
+        }),
+        None => Value::Null,
+    }
+}
+
+pub fn compute_phase_to_json(description: &TransactionDescr) -> Value {
+    let compute = match description {
+        TransactionDescr::Ordinary(description) => &description.compute_ph,
+        TransactionDescr::TickTock(description) => &description.compute_ph,
+        TransactionDescr::SplitPrepare(description) => &description.compute_ph,
+        TransactionDescr::MergeInstall(description) => &description.compute_ph,
+        _ => return Value::Null,
+    };
+
+    match compute {
+        TrComputePhase::Skipped(skipped) => json!({
+            "skipped": true,
+            "skipReason": format!("{:?}", skipped.reason),
+        }),
+        TrComputePhase::Vm(vm) => json!({
+            "skipped": false,
+            "success": vm.success,
+            "exitCode": vm.exit_code,
+            "exitArg": vm.exit_arg,
+            "gasUsed": vm.gas_used.as_u64().to_string(),
+            "gasLimit": vm.gas_limit.as_u64().to_string(),
+            "gasFees": vm.gas_fees.as_u128().to_string(),
+            "vmSteps": vm.vm_steps,
+        }),
+    }
+}
+
+fn action_phase_to_json(description: &TransactionDescr) -> Value {
+    let action = match description {
+        TransactionDescr::Ordinary(description) => &description.action,
+        TransactionDescr::TickTock(description) => &description.action,
+        TransactionDescr::SplitPrepare(description) => &description.action,
+        TransactionDescr::MergeInstall(description) => &description.action,
+        _ => return Value::Null,
+    };
+
+    match action {
+        Some(action) => json!({
+            "success": action.success,
+            "valid": action.valid,
+            "noFunds": action.no_funds,
+            "resultCode": action.result_code,
+            "resultArg": action.result_arg,
+            "totalActions": action.tot_actions,
+            "specActions": action.spec_actions,
+            "skippedActions": action.skipped_actions,
+            "messagesCreated": action.msgs_created,
+            "totalFwdFees": action
+                .total_fwd_fees
+                .as_ref()
+                .map(|fees| fees.as_u128().to_string()),
+            "totalActionFees": action
+                .total_action_fees
+                .as_ref()
+                .map(|fees| fees.as_u128().to_string()),
+        }),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_block::{
+        ComputeSkipReason, TrActionPhase, TrComputePhaseSkipped, TrComputePhaseVm,
+        TransactionDescrOrdinary,
+    };
+
+    use super::*;
+
+    const ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+
+    fn account_with_balance(balance: &CurrencyCollection) -> Account {
+        let address = nekoton_utils::repack_address(ADDRESS).unwrap();
+        let account = Account::with_address_and_ballance(&address, balance);
+        // As read from a BOC, like every account this module sees
+        Account::construct_from_cell(account.serialize().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn extra_currencies_are_decoded_by_id() {
+        let balance =
+            parse_currency_collection(1_000, r#"{"1": "500", "7": "12345678901234567890"}"#)
+                .unwrap();
+        let account = account_to_json(&account_with_balance(&balance), None).unwrap();
+        assert_eq!(account["balance"], "1000");
+        assert_eq!(
+            account["extraCurrencies"],
+            json!({ "1": "500", "7": "12345678901234567890" })
+        );
+
+        let balance = parse_currency_collection(1_000, "").unwrap();
+        let account = account_to_json(&account_with_balance(&balance), None).unwrap();
+        assert_eq!(account["extraCurrencies"], json!({}));
+    }
+
+    fn transaction_json(description: TransactionDescrOrdinary) -> Value {
+        let address = nekoton_utils::repack_address(ADDRESS).unwrap();
+        let mut transaction =
+            Transaction::with_address_and_status(address.address(), AccountStatus::AccStateActive);
+        transaction
+            .write_description(&TransactionDescr::Ordinary(description))
+            .unwrap();
+        let cell = transaction.serialize().unwrap();
+        let transaction = Transaction::construct_from_cell(cell.clone()).unwrap();
+        transaction_to_json(&cell.repr_hash(), &transaction).unwrap()
+    }
+
+    #[test]
+    fn failed_compute_phase_surfaces_its_exit_code() {
+        let transaction = transaction_json(TransactionDescrOrdinary {
+            aborted: true,
+            compute_ph: TrComputePhase::Vm(TrComputePhaseVm {
+                success: false,
+                exit_code: 52,
+                exit_arg: Some(7),
+                vm_steps: 31,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(transaction["aborted"], true);
+        let compute = &transaction["compute"];
+        assert_eq!(compute["skipped"], false);
+        assert_eq!(compute["success"], false);
+        assert_eq!(compute["exitCode"], 52);
+        assert_eq!(compute["exitArg"], 7);
+        assert_eq!(compute["vmSteps"], 31);
+        assert_eq!(compute["gasUsed"], "0");
+
+        let transaction = transaction_json(TransactionDescrOrdinary {
+            compute_ph: TrComputePhase::Skipped(TrComputePhaseSkipped {
+                reason: ComputeSkipReason::NoState,
+            }),
+            ..Default::default()
+        });
+        assert_eq!(transaction["compute"]["skipped"], true);
+        assert_eq!(transaction["compute"]["skipReason"], "NoState");
+    }
+
+    #[test]
+    fn failed_action_phase_surfaces_its_result_code() {
+        // The compute phase succeeded, but there was not enough balance to send
+        let transaction = transaction_json(TransactionDescrOrdinary {
+            aborted: true,
+            compute_ph: TrComputePhase::Vm(TrComputePhaseVm {
+                success: true,
+                ..Default::default()
+            }),
+            action: Some(TrActionPhase {
+                success: false,
+                valid: true,
+                no_funds: true,
+                result_code: 37,
+                tot_actions: 2,
+                skipped_actions: 0,
+                msgs_created: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(transaction["compute"]["success"], true);
+        let action = &transaction["action"];
+        assert_eq!(action["success"], false);
+        assert_eq!(action["noFunds"], true);
+        assert_eq!(action["resultCode"], 37);
+        assert_eq!(action["totalActions"], 2);
+        assert_eq!(action["skippedActions"], 0);
+        assert_eq!(action["messagesCreated"], 1);
+
+        let transaction = transaction_json(TransactionDescrOrdinary::default());
+        assert!(transaction["action"].is_null());
+    }
+}
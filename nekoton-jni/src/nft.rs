@@ -0,0 +1,538 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jint, jlong, jstring};
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use ton_abi::{Contract, Token, TokenValue};
+use ton_block::{AccountStuff, MsgAddressInt};
+use ton_types::UInt256;
+
+use crate::error::{self, NekotonError};
+use crate::{abi, cell, contract, runtime, transport};
+
+/// Value attached to a transfer on top of the callbacks' own values, the NFT
+/// returns what is left to `sendGasTo`
+const TRANSFER_ATTACHED_AMOUNT: u128 = 1_000_000_000;
+
+const NFT_COLLECTION_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "totalSupply",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "count", "type": "uint128"}]
+        },
+        {
+            "name": "getJson",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "json", "type": "string"}]
+        },
+        {
+            "name": "resolveIndexCodeHash",
+            "inputs": [
+                {"name": "answerId", "type": "uint32"},
+                {"name": "collection", "type": "address"},
+                {"name": "owner", "type": "address"}
+            ],
+            "outputs": [{"name": "codeHash", "type": "uint256"}]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+const NFT_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "getInfo",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [
+                {"name": "id", "type": "uint256"},
+                {"name": "owner", "type": "address"},
+                {"name": "manager", "type": "address"},
+                {"name": "collection", "type": "address"}
+            ]
+        },
+        {
+            "name": "getJson",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "json", "type": "string"}]
+        },
+        {
+            "name": "transfer",
+            "inputs": [
+                {"name": "to", "type": "address"},
+                {"name": "sendGasTo", "type": "address"},
+                {
+                    "name": "callbacks",
+                    "type": "map(address,tuple)",
+                    "components": [
+                        {"name": "value", "type": "uint128"},
+                        {"name": "payload", "type": "cell"}
+                    ]
+                }
+            ],
+            "outputs": []
+        },
+        {
+            "name": "changeOwner",
+            "inputs": [
+                {"name": "newOwner", "type": "address"},
+                {"name": "sendGasTo", "type": "address"},
+                {
+                    "name": "callbacks",
+                    "type": "map(address,tuple)",
+                    "components": [
+                        {"name": "value", "type": "uint128"},
+                        {"name": "payload", "type": "cell"}
+                    ]
+                }
+            ],
+            "outputs": []
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+const NFT_INDEX_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "getInfo",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [
+                {"name": "collection", "type": "address"},
+                {"name": "owner", "type": "address"},
+                {"name": "nft", "type": "address"}
+            ]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+static NFT_COLLECTION: Lazy<Contract> = Lazy::new(|| {
+    Contract::load(NFT_COLLECTION_ABI.as_bytes()).expect("Invalid NFT collection ABI")
+});
+
+static NFT: Lazy<Contract> =
+    Lazy::new(|| Contract::load(NFT_ABI.as_bytes()).expect("Invalid NFT ABI"));
+
+static NFT_INDEX: Lazy<Contract> =
+    Lazy::new(|| Contract::load(NFT_INDEX_ABI.as_bytes()).expect("Invalid NFT index ABI"));
+
+fn call(
+    contract: &Contract,
+    account: &AccountStuff,
+    name: &str,
+    inputs: Value,
+) -> Result<Vec<Token>> {
+    let function = contract.function(name)?;
+    let inputs = abi::parse_tokens(&function.inputs, &inputs.to_string())?;
+    contract::call_getter(function, account.clone(), &inputs)
+}
+
+fn answer_only() -> Value {
+    json!({ "answerId": 0 })
+}
+
+/// TIP-4.2 metadata, `None` for contracts that only implement TIP-4.1
+fn get_json(contract: &Contract, account: &AccountStuff) -> Option<String> {
+    match call(contract, account, "getJson", answer_only())
+        .ok()?
+        .pop()
+    {
+        Some(Token {
+            value: TokenValue::String(json),
+            ..
+        }) => Some(json),
+        _ => None,
+    }
+}
+
+fn get_collection_info(transport_handle: jlong, collection: &str) -> Result<String> {
+    let collection = nekoton_utils::repack_address(collection)?;
+    let account = contract::get_active_account(transport_handle, &collection)?;
+    let total_supply = call(&NFT_COLLECTION, &account, "totalSupply", answer_only())?;
+
+    Ok(json!({
+        "address": collection.to_string(),
+        "totalSupply": nekoton_abi::make_abi_tokens(&total_supply)?["count"],
+        "json": get_json(&NFT_COLLECTION, &account),
+    })
+    .to_string())
+}
+
+/// TIP-4.3 indexes are deployed with code salted by the collection and the
+/// owner, so every index of an owner shares one code hash
+fn get_owner_indexes(
+    transport_handle: jlong,
+    collection: &str,
+    owner: &str,
+    limit: jint,
+    continuation: &str,
+) -> Result<String> {
+    let collection = nekoton_utils::repack_address(collection)?;
+    let owner = nekoton_utils::repack_address(owner)?;
+    let limit = u8::try_from(limit)
+        .ok()
+        .filter(|limit| *limit > 0)
+        .ok_or_else(|| anyhow!("Limit must be between 1 and 255"))?;
+    let continuation = match continuation.trim() {
+        "" => None,
+        address => Some(nekoton_utils::repack_address(address)?),
+    };
+
+    let account = contract::get_active_account(transport_handle, &collection)?;
+    let inputs = json!({
+        "answerId": 0,
+        "collection": collection.to_string(),
+        "owner": owner.to_string(),
+    });
+    let code_hash = match call(&NFT_COLLECTION, &account, "resolveIndexCodeHash", inputs)?.pop() {
+        Some(Token {
+            value: TokenValue::Uint(code_hash),
+            ..
+        }) => {
+            let bytes = code_hash.number.to_bytes_be();
+            let mut hash = [0u8; 32];
+            hash[32 - bytes.len()..].copy_from_slice(&bytes);
+            UInt256::from(hash)
+        }
+        _ => return Err(anyhow!("Getter `resolveIndexCodeHash` must return a hash")),
+    };
+
+    let transport = transport::get_transport(transport_handle)?;
+    let addresses: Vec<MsgAddressInt> =
+        runtime::block_on(transport.get_accounts_by_code_hash(&code_hash, limit, &continuation))
+            .context(NekotonError::Transport)?;
+
+    let indexes = addresses
+        .iter()
+        .map(|address| {
+            let account = contract::get_active_account(transport_handle, address)?;
+            let info = call(&NFT_INDEX, &account, "getInfo", answer_only())?;
+            let mut info = nekoton_abi::make_abi_tokens(&info)?;
+            info["index"] = Value::String(address.to_string());
+            Ok(info)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "codeHash": code_hash.to_hex_string(),
+        "indexes": indexes,
+        // A full page means there may be more, pass the last index to continue
+        "continuation": match addresses.len() == limit as usize {
+            true => addresses.last().map(ToString::to_string),
+            false => None,
+        },
+    })
+    .to_string())
+}
+
+fn get_nft_info(transport_handle: jlong, nft: &str) -> Result<String> {
+    let nft = nekoton_utils::repack_address(nft)?;
+    let account = contract::get_active_account(transport_handle, &nft)?;
+    let mut info = nekoton_abi::make_abi_tokens(&call(&NFT, &account, "getInfo", answer_only())?)?;
+    info["address"] = Value::String(nft.to_string());
+    info["json"] = get_json(&NFT, &account).into();
+    Ok(info.to_string())
+}
+
+/// The internal message the NFT manager's wallet has to send to the NFT.
+/// `callbacks` maps addresses to `{value, payload}` notifications, empty for none
+fn prepare_nft_call(
+    function_name: &str,
+    nft: &str,
+    recipient_param: &str,
+    recipient: &str,
+    send_gas_to: &str,
+    callbacks: &str,
+) -> Result<String> {
+    let nft = nekoton_utils::repack_address(nft)?;
+    let recipient = nekoton_utils::repack_address(recipient)?;
+    let send_gas_to = nekoton_utils::repack_address(send_gas_to)?;
+    let callbacks = match callbacks.trim() {
+        "" => Value::Object(Default::default()),
+        callbacks => serde_json::from_str(callbacks).context("Invalid callbacks JSON")?,
+    };
+    let callbacks_value = match &callbacks {
+        Value::Object(callbacks) => callbacks.values().try_fold(0u128, |total, callback| {
+            let value = match &callback["value"] {
+                Value::String(value) => value.parse().ok(),
+                value => value.as_u64().map(u128::from),
+            }
+            .ok_or_else(|| anyhow!("Callback `value` must be a non-negative integer"))?;
+            total
+                .checked_add(value)
+                .ok_or_else(|| anyhow!("Callback values overflow"))
+        })?,
+        _ => return Err(anyhow!("Callbacks must be a JSON object")),
+    };
+
+    let function = NFT.function(function_name)?;
+    let mut inputs = json!({
+        "sendGasTo": send_gas_to.to_string(),
+        "callbacks": callbacks,
+    });
+    inputs[recipient_param] = Value::String(recipient.to_string());
+    let inputs = abi::parse_tokens(&function.inputs, &inputs.to_string())?;
+    let body = function.encode_internal_input(&inputs)?.into_cell()?;
+
+    Ok(json!({
+        "destination": nft.to_string(),
+        "amount": (TRANSFER_ATTACHED_AMOUNT + callbacks_value).to_string(),
+        "bounce": true,
+        "bodyBoc": base64::encode(cell::serialize_boc(&body)?),
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nftGetCollectionInfo(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    collection: JString,
+) -> jstring {
+    error::guard!(env, {
+        let collection: String = match env.get_string(&collection) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let info = match get_collection_info(transport_handle, &collection) {
+            Ok(info) => info,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(info) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Pages through the owner's indexes; an empty `continuation` starts from the
+/// beginning, the result's `continuation` is null on the last page
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nftGetOwnerIndexes(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    collection: JString,
+    owner: JString,
+    limit: jint,
+    continuation: JString,
+) -> jstring {
+    error::guard!(env, {
+        let collection: String = match env.get_string(&collection) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let owner: String = match env.get_string(&owner) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let continuation: String = match env.get_string(&continuation) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let indexes =
+            match get_owner_indexes(transport_handle, &collection, &owner, limit, &continuation) {
+                Ok(indexes) => indexes,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Abi, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(indexes) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nftGetInfo(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    nft: JString,
+) -> jstring {
+    error::guard!(env, {
+        let nft: String = match env.get_string(&nft) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let info = match get_nft_info(transport_handle, &nft) {
+            Ok(info) => info,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(info) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nftPrepareTransfer(
+    mut env: JNIEnv,
+    _class: JClass,
+    nft: JString,
+    to: JString,
+    send_gas_to: JString,
+    callbacks_json: JString,
+) -> jstring {
+    error::guard!(env, {
+        let nft: String = match env.get_string(&nft) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let to: String = match env.get_string(&to) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let send_gas_to: String = match env.get_string(&send_gas_to) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let callbacks: String = match env.get_string(&callbacks_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let transfer = match prepare_nft_call("transfer", &nft, "to", &to, &send_gas_to, &callbacks)
+        {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(transfer) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Hands the NFT to `newOwner` while the manager stays the same
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_nftPrepareChangeOwner(
+    mut env: JNIEnv,
+    _class: JClass,
+    nft: JString,
+    new_owner: JString,
+    send_gas_to: JString,
+    callbacks_json: JString,
+) -> jstring {
+    error::guard!(env, {
+        let nft: String = match env.get_string(&nft) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let new_owner: String = match env.get_string(&new_owner) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let send_gas_to: String = match env.get_string(&send_gas_to) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let callbacks: String = match env.get_string(&callbacks_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let change_owner = match prepare_nft_call(
+            "changeOwner",
+            &nft,
+            "newOwner",
+            &new_owner,
+            &send_gas_to,
+            &callbacks,
+        ) {
+            Ok(change_owner) => change_owner,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(change_owner) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
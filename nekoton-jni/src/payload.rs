@@ -0,0 +1,200 @@
+use anyhow::Result;
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jint, jlong, jstring};
+use jni::JNIEnv;
+use serde_json::json;
+use ton_types::{BuilderData, IBitstring, SliceData};
+
+use crate::{cell, error, slice};
+
+fn build_op_payload(op_code: jint, body_cell_handle: jlong) -> Result<jlong> {
+    let body = SliceData::load_cell(cell::get_cell(body_cell_handle)?)?;
+    let mut payload = BuilderData::new();
+    payload.append_u32(op_code as u32)?;
+    payload.checked_append_references_and_data(&body)?;
+    Ok(cell::insert_cell(payload.into_cell()?))
+}
+
+/// `Ok(None)` when the body is too short to hold an op-code
+fn parse_op_payload(body_boc: &[u8]) -> Result<Option<String>> {
+    let mut body = SliceData::load_cell(cell::parse_boc(body_boc)?)?;
+    if body.remaining_bits() < 32 {
+        return Ok(None);
+    }
+    // Op-codes travel as jint, so keep the same bit pattern on the way back
+    let op = body.get_next_u32()? as jint;
+    Ok(Some(
+        json!({
+            "op": op,
+            "rest": slice::insert_slice(body),
+        })
+        .to_string(),
+    ))
+}
+
+/// A zero op-code followed by the UTF-8 text, continued in a chain of refs
+/// once it outgrows a cell
+fn encode_comment(text: &str) -> Result<jlong> {
+    let payload = nekoton_abi::create_comment_payload(text)?;
+    Ok(cell::insert_cell(payload.into_cell()))
+}
+
+/// `Ok(None)` when the body is not a text comment
+fn decode_comment(body_boc: &[u8]) -> Result<Option<String>> {
+    let body = SliceData::load_cell(cell::parse_boc(body_boc)?)?;
+    Ok(nekoton_abi::parse_comment_payload(body))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_buildOpPayload(
+    mut env: JNIEnv,
+    _class: JClass,
+    op_code: jint,
+    body_cell_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match build_op_payload(op_code, body_cell_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseOpPayload(
+    mut env: JNIEnv,
+    _class: JClass,
+    body_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let body_boc = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let payload = match parse_op_payload(&body_boc) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(payload) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Returns a cell handle
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_encodeComment(
+    mut env: JNIEnv,
+    _class: JClass,
+    text: JString,
+) -> jlong {
+    error::guard!(env, {
+        let text: String = match env.get_string(&text) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match encode_comment(&text) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeComment(
+    mut env: JNIEnv,
+    _class: JClass,
+    body_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let body_boc = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let comment = match decode_comment(&body_boc) {
+            Ok(Some(comment)) => comment,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(comment) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn cell_of(data: &[u8], bits: usize, references: &[ton_types::Cell]) -> ton_types::Cell {
+        let mut builder = BuilderData::new();
+        builder.append_raw(data, bits).unwrap();
+        for reference in references {
+            builder.checked_append_reference(reference.clone()).unwrap();
+        }
+        builder.into_cell().unwrap()
+    }
+
+    #[test]
+    fn op_payload_round_trips() {
+        let reference = cell_of(&[0x42], 8, &[]);
+        let body = cell_of(&[0xAB, 0xC0], 12, &[reference.clone()]);
+        // Op-codes with the top bit set come back as the same negative jint
+        let op_code = 0xDEAD_BEEF_u32 as jint;
+
+        let payload = build_op_payload(op_code, cell::insert_cell(body)).unwrap();
+        let payload = cell::serialize_boc(&cell::get_cell(payload).unwrap()).unwrap();
+        let parsed: Value =
+            serde_json::from_str(&parse_op_payload(&payload).unwrap().unwrap()).unwrap();
+        assert_eq!(parsed["op"], op_code);
+
+        let rest = slice::get_slice(parsed["rest"].as_i64().unwrap()).unwrap();
+        let mut rest = rest.lock().clone();
+        assert_eq!(rest.remaining_bits(), 12);
+        assert_eq!(rest.get_next_bits(12).unwrap(), [0xAB, 0xC0]);
+        assert_eq!(rest.reference(0).unwrap(), reference);
+    }
+
+    #[test]
+    fn body_shorter_than_an_op_code_gives_none() {
+        let body = cell::serialize_boc(&cell_of(&[0xFF; 4], 31, &[])).unwrap();
+        assert!(parse_op_payload(&body).unwrap().is_none());
+
+        let body = cell::serialize_boc(&cell_of(&[0; 4], 32, &[])).unwrap();
+        assert!(parse_op_payload(&body).unwrap().is_some());
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jlong};
+use jni::JNIEnv;
+use ton_block::{Deserializable, MerkleProof};
+use ton_types::{Cell, CellType};
+
+use crate::{cell, error, models};
+
+fn parse_merkle_proof(proof_boc: &[u8]) -> Result<MerkleProof> {
+    let cell = cell::parse_boc(proof_boc)?;
+    if cell.cell_type() != CellType::MerkleProof {
+        return Err(anyhow!("Not a merkle proof cell: {:?}", cell.cell_type()));
+    }
+    Ok(MerkleProof::construct_from_cell(cell)?)
+}
+
+/// The proof must be internally consistent: pruned branches keep the hashes of
+/// the cells they replace, so the proven tree hashes to the hash the proof claims
+fn verify_merkle_proof(proof_boc: &[u8], expected_root_hash: &[u8]) -> Result<bool> {
+    let expected_root_hash = models::hash_from_bytes(expected_root_hash)?;
+    let proof = parse_merkle_proof(proof_boc)?;
+    Ok(proof.proof.hash(0) == proof.hash && proof.hash == expected_root_hash)
+}
+
+/// `path` is a JSON array of reference indexes from the proven root; the cell
+/// it leads to is returned with the hashes it had in the original tree
+fn extract_from_proof(proof_boc: &[u8], path: &str) -> Result<jlong> {
+    let path: Vec<usize> = match path.trim() {
+        "" => Vec::new(),
+        path => serde_json::from_str(path).context("Path must be a JSON array of indexes")?,
+    };
+    let proof = parse_merkle_proof(proof_boc)?;
+    if proof.proof.hash(0) != proof.hash {
+        return Err(anyhow!("Merkle proof hash mismatch"));
+    }
+
+    let mut cell: Cell = proof.proof.virtualize(1);
+    for (depth, index) in path.into_iter().enumerate() {
+        if cell.cell_type() == CellType::PrunedBranch {
+            return Err(anyhow!("Path is pruned at depth {depth}"));
+        }
+        cell = cell
+            .reference(index)
+            .map_err(|_| anyhow!("No reference {index} at depth {depth}"))?;
+    }
+    if cell.cell_type() == CellType::PrunedBranch {
+        return Err(anyhow!("Cell at the end of the path is pruned"));
+    }
+    Ok(cell::insert_cell(cell))
+}
+
+/// `false` when the proof is well-formed but proves some other root
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_verifyMerkleProof(
+    mut env: JNIEnv,
+    _class: JClass,
+    proof_boc: JByteArray,
+    expected_root_hash: JByteArray,
+) -> jboolean {
+    error::guard!(env, {
+        let proof_boc = match env.convert_byte_array(proof_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+        let expected_root_hash = match env.convert_byte_array(expected_root_hash) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return false as jboolean;
+            }
+        };
+
+        match verify_merkle_proof(&proof_boc, &expected_root_hash) {
+            Ok(valid) => valid as jboolean,
+            Err(e) => {
+                error::throw(&mut env, e);
+                false as jboolean
+            }
+        }
+    })
+}
+
+/// Returns a cell handle
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_extractFromProof(
+    mut env: JNIEnv,
+    _class: JClass,
+    proof_boc: JByteArray,
+    path: JString,
+) -> jlong {
+    error::guard!(env, {
+        let proof_boc = match env.convert_byte_array(proof_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let path: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+
+        match extract_from_proof(&proof_boc, &path) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use jni::sys::jlong;
+use parking_lot::Mutex;
+
+// Shared across all registries so a handle of one kind is never valid for another.
+// Handles are never reused, so a released handle stays invalid forever
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+
+/// Thrown to Java as `NekotonException.InvalidHandle`
+#[derive(Debug)]
+pub struct InvalidHandle {
+    pub handle: jlong,
+}
+
+impl fmt::Display for InvalidHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown or released handle: {}", self.handle)
+    }
+}
+
+impl std::error::Error for InvalidHandle {}
+
+/// Handles may be used from any number of JVM threads at once, so every value
+/// must be `Send + Sync`. Immutable values (cells, ABIs, transports) are shared
+/// as is; mutable ones such as builders and slices carry their own `Mutex`,
+/// which serializes the calls on one handle without blocking other handles
+pub struct Registry<T: ?Sized + Send + Sync> {
+    entries: Mutex<HashMap<jlong, Arc<T>>>,
+}
+
+impl<T: ?Sized + Send + Sync> Registry<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn insert(&self, value: Arc<T>) -> jlong {
+        let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        self.entries.lock().insert(handle, value);
+        handle
+    }
+
+    pub fn get(&self, handle: jlong) -> Result<Arc<T>, InvalidHandle> {
+        self.entries
+            .lock()
+            .get(&handle)
+            .cloned()
+            .ok_or(InvalidHandle { handle })
+    }
+
+    /// The value itself is dropped once the last operation still using it finishes
+    pub fn remove(&self, handle: jlong) -> Result<Arc<T>, InvalidHandle> {
+        self.entries
+            .lock()
+            .remove(&handle)
+            .ok_or(InvalidHandle { handle })
+    }
+}
+
+impl<T: ?Sized + Send + Sync> Default for Registry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,25 @@
+use std::future::Future;
+
+use once_cell::sync::Lazy;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("nekoton-jni")
+        .build()
+        .expect("Failed to create tokio runtime")
+});
+
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    RUNTIME.spawn(future)
+}
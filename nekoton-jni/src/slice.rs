@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use jni::objects::JClass;
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use num_bigint::{BigInt, BigUint};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use ton_block::{Deserializable, MsgAddressIntOrNone};
+use ton_types::SliceData;
+
+use crate::registry::Registry;
+use crate::{cell, error};
+
+static SLICES: Lazy<Registry<Mutex<SliceData>>> = Lazy::new(Registry::new);
+
+pub fn insert_slice(slice: SliceData) -> jlong {
+    SLICES.insert(Arc::new(Mutex::new(slice)))
+}
+
+pub fn get_slice(handle: jlong) -> Result<Arc<Mutex<SliceData>>> {
+    Ok(SLICES.get(handle)?)
+}
+
+/// Hex of the remaining bits; a tail shorter than a nibble is completed with a
+/// single 1 bit and zero padding, and marked with a trailing `_`
+pub fn slice_to_bit_string(slice: &SliceData) -> Result<String> {
+    let mut slice = slice.clone();
+    let bits = slice.remaining_bits();
+    let mut result = String::with_capacity(bits / 4 + 2);
+    for _ in 0..bits / 4 {
+        push_nibble(&mut result, slice.get_next_int(4)? as u8);
+    }
+
+    let tail = bits % 4;
+    if tail > 0 {
+        let nibble = (slice.get_next_int(tail)? as u8) << (4 - tail) | 1 << (3 - tail);
+        push_nibble(&mut result, nibble);
+        result.push('_');
+    }
+    Ok(result)
+}
+
+fn push_nibble(result: &mut String, nibble: u8) {
+    result.push(
+        char::from_digit(u32::from(nibble), 16)
+            .unwrap()
+            .to_ascii_uppercase(),
+    );
+}
+
+fn create_cell_slice(cell_handle: jlong) -> Result<jlong> {
+    let slice = SliceData::load_cell(cell::get_cell(cell_handle)?)?;
+    Ok(insert_slice(slice))
+}
+
+fn slice_bit_string(slice_handle: jlong) -> Result<String> {
+    let slice = get_slice(slice_handle)?;
+    let slice = slice.lock();
+    slice_to_bit_string(&slice)
+}
+
+fn ensure_bits(slice: &SliceData, bits: usize) -> Result<()> {
+    if slice.remaining_bits() < bits {
+        return Err(anyhow!(
+            "Cannot read {bits} bits, only {} remain in the slice",
+            slice.remaining_bits()
+        ));
+    }
+    Ok(())
+}
+
+pub fn read_bits(slice: &mut SliceData, bits: usize) -> Result<BigUint> {
+    ensure_bits(slice, bits)?;
+    // The bits come back left-aligned in whole bytes
+    let data = slice.get_next_bits(bits)?;
+    Ok(BigUint::from_bytes_be(&data) >> (data.len() * 8 - bits))
+}
+
+/// Decimal string, since values can be up to 256 bits wide
+fn slice_load_uint(slice_handle: jlong, bits: jint) -> Result<String> {
+    let bits = cell::parse_bit_width(bits)?;
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    Ok(read_bits(&mut slice, bits)?.to_string())
+}
+
+/// Two's complement, as stored by `cellBuilderStoreInt`
+fn slice_load_int(slice_handle: jlong, bits: jint) -> Result<String> {
+    let bits = cell::parse_bit_width(bits)?;
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    let value = BigInt::from(read_bits(&mut slice, bits)?);
+    let value = match value.bit(bits as u64 - 1) {
+        true => value - (BigInt::from(1) << bits),
+        false => value,
+    };
+    Ok(value.to_string())
+}
+
+fn slice_load_bit(slice_handle: jlong) -> Result<bool> {
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    ensure_bits(&slice, 1)?;
+    Ok(slice.get_next_bit()?)
+}
+
+/// `VarUInteger 16` as a decimal string. Nothing is consumed if it is truncated
+fn slice_load_coins(slice_handle: jlong) -> Result<String> {
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    let mut cursor = slice.clone();
+    ensure_bits(&cursor, 4)?;
+    let len = cursor.get_next_int(4)? as usize;
+    let value = match len {
+        0 => BigUint::default(),
+        len => read_bits(&mut cursor, len * 8)?,
+    };
+    *slice = cursor;
+    Ok(value.to_string())
+}
+
+/// `None` for `addr_none`. Nothing is consumed if the address is malformed
+fn slice_load_address(slice_handle: jlong) -> Result<Option<String>> {
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    let mut cursor = slice.clone();
+    let address = MsgAddressIntOrNone::construct_from(&mut cursor)?;
+    *slice = cursor;
+    Ok(match address {
+        MsgAddressIntOrNone::Some(address) => Some(address.to_string()),
+        MsgAddressIntOrNone::None => None,
+    })
+}
+
+fn slice_load_bytes(slice_handle: jlong, byte_len: jint) -> Result<Vec<u8>> {
+    let byte_len =
+        usize::try_from(byte_len).map_err(|_| anyhow!("Invalid byte length: {byte_len}"))?;
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    ensure_bits(&slice, byte_len * 8)?;
+    slice.get_next_bytes(byte_len)
+}
+
+fn slice_remaining(slice_handle: jlong) -> Result<(jint, jint)> {
+    let slice = get_slice(slice_handle)?;
+    let slice = slice.lock();
+    Ok((
+        slice.remaining_bits() as jint,
+        slice.remaining_references() as jint,
+    ))
+}
+
+fn slice_load_ref(slice_handle: jlong) -> Result<jlong> {
+    let slice = get_slice(slice_handle)?;
+    let mut slice = slice.lock();
+    if slice.remaining_references() == 0 {
+        return Err(anyhow!("No references remain in the slice"));
+    }
+    Ok(cell::insert_cell(slice.checked_drain_reference()?))
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createCellSlice(
+    mut env: JNIEnv,
+    _class: JClass,
+    cell_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match create_cell_slice(cell_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceToBitString(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let bits = match slice_bit_string(slice_handle) {
+            Ok(bits) => bits,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(bits) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadUint(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+    bits: jint,
+) -> jstring {
+    error::guard!(env, {
+        let value = match slice_load_uint(slice_handle, bits) {
+            Ok(value) => value,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(value) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadInt(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+    bits: jint,
+) -> jstring {
+    error::guard!(env, {
+        let value = match slice_load_int(slice_handle, bits) {
+            Ok(value) => value,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(value) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadBit(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jboolean {
+    error::guard!(env, {
+        match slice_load_bit(slice_handle) {
+            Ok(bit) => bit as jboolean,
+            Err(e) => {
+                error::throw(&mut env, e);
+                false as jboolean
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadCoins(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let value = match slice_load_coins(slice_handle) {
+            Ok(value) => value,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(value) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let address = match slice_load_address(slice_handle) {
+            Ok(Some(address)) => address,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(address) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceRemainingBits(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match slice_remaining(slice_handle) {
+            Ok((bits, _)) => bits,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceRemainingRefs(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jint {
+    error::guard!(env, {
+        match slice_remaining(slice_handle) {
+            Ok((_, refs)) => refs,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadBytes(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+    byte_len: jint,
+) -> jbyteArray {
+    error::guard!(env, {
+        let data = match slice_load_bytes(slice_handle, byte_len) {
+            Ok(data) => data,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&data) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sliceLoadRef(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match slice_load_ref(slice_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupSlice(
+    mut env: JNIEnv,
+    _class: JClass,
+    slice_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = SLICES.remove(slice_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ton_types::BuilderData;
+
+    use super::*;
+
+    fn slice_of(data: &[u8], bits: usize) -> SliceData {
+        let mut builder = BuilderData::new();
+        builder.append_raw(data, bits).unwrap();
+        SliceData::load_builder(builder).unwrap()
+    }
+
+    #[test]
+    fn bit_string_tags_a_partial_nibble() {
+        // 1010 1100 0011 1: the last bit is completed with a 1 and padded with zeros
+        let slice = slice_of(&[0b1010_1100, 0b0011_1000], 13);
+        assert_eq!(slice_to_bit_string(&slice).unwrap(), "AC3C_");
+
+        assert_eq!(slice_to_bit_string(&slice_of(&[0xAC], 8)).unwrap(), "AC");
+        assert_eq!(slice_to_bit_string(&slice_of(&[], 0)).unwrap(), "");
+    }
+
+    #[test]
+    fn bit_string_covers_only_the_remaining_bits() {
+        let handle = insert_slice(slice_of(&[0b1010_1100, 0b0011_1000], 13));
+        assert!(slice_load_bit(handle).unwrap());
+        // 0101 1000 0111 is aligned again
+        assert_eq!(slice_bit_string(handle).unwrap(), "587");
+    }
+}
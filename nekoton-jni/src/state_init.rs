@@ -0,0 +1,136 @@
+use anyhow::Result;
+use jni::objects::{JByteArray, JClass};
+use jni::sys::{jbyteArray, jlong, jlongArray};
+use jni::JNIEnv;
+use ton_block::{Deserializable, Serializable, StateInit};
+use ton_types::Cell;
+
+use crate::{cell, error};
+
+/// Cell handle of each part, `0` for a part the StateInit doesn't have
+fn split_state_init(tvc: &[u8]) -> Result<[jlong; 2]> {
+    let state_init = StateInit::construct_from_cell(cell::parse_boc(tvc)?)?;
+    Ok([
+        state_init.code.map(cell::insert_cell).unwrap_or_default(),
+        state_init.data.map(cell::insert_cell).unwrap_or_default(),
+    ])
+}
+
+fn optional_cell(handle: jlong) -> Result<Option<Cell>> {
+    match handle {
+        0 => Ok(None),
+        handle => cell::get_cell(handle).map(Some),
+    }
+}
+
+fn merge_state_init(code_handle: jlong, data_handle: jlong) -> Result<Vec<u8>> {
+    let state_init = StateInit {
+        code: optional_cell(code_handle)?,
+        data: optional_cell(data_handle)?,
+        ..Default::default()
+    };
+    cell::serialize_boc(&state_init.serialize()?)
+}
+
+/// `0` for code without a salt
+fn get_code_salt(code_handle: jlong) -> Result<jlong> {
+    let code = cell::get_cell(code_handle)?;
+    Ok(nekoton_abi::get_code_salt(code)?
+        .map(cell::insert_cell)
+        .unwrap_or_default())
+}
+
+/// Replaces the salt if the code already has one
+fn set_code_salt(code_handle: jlong, salt_handle: jlong) -> Result<jlong> {
+    let code = cell::get_cell(code_handle)?;
+    let salt = cell::get_cell(salt_handle)?;
+    Ok(cell::insert_cell(nekoton_abi::set_code_salt(code, salt)?))
+}
+
+/// Returns `[codeHandle, dataHandle]`, see `mergeStateInit` for the reverse
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_splitStateInit(
+    mut env: JNIEnv,
+    _class: JClass,
+    tvc_bytes: JByteArray,
+) -> jlongArray {
+    error::guard!(env, {
+        let tvc = match env.convert_byte_array(tvc_bytes) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let handles = match split_state_init(&tvc) {
+            Ok(handles) => handles,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        cell::new_handle_array(&mut env, &handles)
+    })
+}
+
+/// Either handle may be `0` to leave that part out
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_mergeStateInit(
+    mut env: JNIEnv,
+    _class: JClass,
+    code_handle: jlong,
+    data_handle: jlong,
+) -> jbyteArray {
+    error::guard!(env, {
+        let tvc = match merge_state_init(code_handle, data_handle) {
+            Ok(tvc) => tvc,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&tvc) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getCodeSalt(
+    mut env: JNIEnv,
+    _class: JClass,
+    code_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match get_code_salt(code_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setCodeSalt(
+    mut env: JNIEnv,
+    _class: JClass,
+    code_handle: jlong,
+    salt_cell_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match set_code_salt(code_handle, salt_cell_handle) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
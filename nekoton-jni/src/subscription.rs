@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use jni::objects::{JByteArray, JClass, JObject, JString};
+use jni::sys::{jlong, jstring};
+use jni::JNIEnv;
+use nekoton::core::generic_contract::{GenericContract, GenericContractSubscriptionHandler};
+use nekoton::core::models::{
+    ContractState, PendingTransaction, Transaction, TransactionsBatchInfo,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::json;
+use ton_block::{Deserializable, Message};
+
+use crate::callback::JavaCallback;
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+use crate::{cell, clock, runtime, transport};
+
+static SUBSCRIPTIONS: Lazy<Registry<Mutex<GenericContract>>> = Lazy::new(Registry::new);
+
+/// Forwards subscription events to a Kotlin object implementing
+/// `onStateChanged`, `onTransactionsFound`, `onMessageSent` and `onMessageExpired`
+struct CallbackHandler(JavaCallback);
+
+impl GenericContractSubscriptionHandler for CallbackHandler {
+    fn on_message_sent(
+        &self,
+        pending_transaction: PendingTransaction,
+        transaction: Option<Transaction>,
+    ) {
+        let payload = json!({
+            "pendingTransaction": pending_transaction,
+            "transaction": transaction,
+        });
+        self.0.notify("onMessageSent", payload.to_string());
+    }
+
+    fn on_message_expired(&self, pending_transaction: PendingTransaction) {
+        let payload = json!({ "pendingTransaction": pending_transaction });
+        self.0.notify("onMessageExpired", payload.to_string());
+    }
+
+    fn on_state_changed(&self, new_state: ContractState) {
+        let payload = json!({ "state": new_state });
+        self.0.notify("onStateChanged", payload.to_string());
+    }
+
+    fn on_transactions_found(
+        &self,
+        transactions: Vec<Transaction>,
+        batch_info: TransactionsBatchInfo,
+    ) {
+        let payload = json!({
+            "transactions": transactions,
+            "batchInfo": batch_info,
+        });
+        self.0.notify("onTransactionsFound", payload.to_string());
+    }
+}
+
+fn subscribe_account(
+    callback: JavaCallback,
+    transport_handle: jlong,
+    address: &str,
+) -> Result<jlong> {
+    let transport = transport::get_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(address)?;
+    let handler = Arc::new(CallbackHandler(callback));
+
+    let contract = runtime::block_on(GenericContract::subscribe(
+        clock::CLOCK.clone(),
+        transport,
+        address,
+        handler,
+        false,
+    ))?;
+    Ok(SUBSCRIPTIONS.insert(Arc::new(Mutex::new(contract))))
+}
+
+/// Polls the account once and fires callbacks for everything that changed.
+/// Callbacks run on the calling thread and must not refresh the same subscription
+fn refresh_subscription(handle: jlong) -> Result<()> {
+    let subscription = SUBSCRIPTIONS.get(handle)?;
+    let mut contract = subscription.lock();
+    runtime::block_on(contract.refresh())
+}
+
+/// Sends the message and tracks it, so a later refresh reports it through
+/// `onMessageSent` or `onMessageExpired`
+fn subscription_send_message(handle: jlong, message_boc: &[u8]) -> Result<String> {
+    let subscription = SUBSCRIPTIONS.get(handle)?;
+    let message = Message::construct_from_cell(cell::parse_boc(message_boc)?)?;
+    let mut contract = subscription.lock();
+    let pending = runtime::block_on(contract.send(&message, false))?;
+    Ok(serde_json::to_string(&pending)?)
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_subscribeAccount(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    callback: JObject,
+) -> jlong {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let callback = match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => callback,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return 0;
+            }
+        };
+
+        match subscribe_account(callback, transport_handle, &address) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_refreshSubscription(
+    mut env: JNIEnv,
+    _class: JClass,
+    subscription_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = refresh_subscription(subscription_handle) {
+            error::throw_as(&mut env, NekotonError::Transport, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_subscriptionSendMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    subscription_handle: jlong,
+    message_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let message_boc = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let pending = match subscription_send_message(subscription_handle, &message_boc) {
+            Ok(pending) => pending,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(pending) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupSubscription(
+    mut env: JNIEnv,
+    _class: JClass,
+    subscription_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = SUBSCRIPTIONS.remove(subscription_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
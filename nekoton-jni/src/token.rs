@@ -0,0 +1,385 @@
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jboolean, jlong, jstring};
+use jni::JNIEnv;
+use num_bigint::BigUint;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use ton_abi::{Contract, Token, TokenValue};
+use ton_block::AccountStuff;
+use ton_types::Cell;
+
+use crate::error::{self, NekotonError};
+use crate::{abi, cell, contract};
+
+/// Value attached to a transfer, the wallet returns what is left to `remainingGasTo`
+const TRANSFER_ATTACHED_AMOUNT: u64 = 500_000_000;
+
+const TOKEN_ROOT_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "name",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "string"}]
+        },
+        {
+            "name": "symbol",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "string"}]
+        },
+        {
+            "name": "decimals",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "uint8"}]
+        },
+        {
+            "name": "totalSupply",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "uint128"}]
+        },
+        {
+            "name": "rootOwner",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "address"}]
+        },
+        {
+            "name": "walletOf",
+            "inputs": [
+                {"name": "answerId", "type": "uint32"},
+                {"name": "walletOwner", "type": "address"}
+            ],
+            "outputs": [{"name": "value0", "type": "address"}]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+const TOKEN_WALLET_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.2",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "balance",
+            "inputs": [{"name": "answerId", "type": "uint32"}],
+            "outputs": [{"name": "value0", "type": "uint128"}]
+        },
+        {
+            "name": "transfer",
+            "inputs": [
+                {"name": "amount", "type": "uint128"},
+                {"name": "recipient", "type": "address"},
+                {"name": "deployWalletValue", "type": "uint128"},
+                {"name": "remainingGasTo", "type": "address"},
+                {"name": "notify", "type": "bool"},
+                {"name": "payload", "type": "cell"}
+            ],
+            "outputs": []
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+static TOKEN_ROOT: Lazy<Contract> =
+    Lazy::new(|| Contract::load(TOKEN_ROOT_ABI.as_bytes()).expect("Invalid token root ABI"));
+
+static TOKEN_WALLET: Lazy<Contract> =
+    Lazy::new(|| Contract::load(TOKEN_WALLET_ABI.as_bytes()).expect("Invalid token wallet ABI"));
+
+/// The single output of a TIP-3.1 getter
+fn get(
+    contract: &Contract,
+    account: &AccountStuff,
+    name: &str,
+    inputs: Value,
+) -> Result<TokenValue> {
+    let function = contract.function(name)?;
+    let inputs = abi::parse_tokens(&function.inputs, &inputs.to_string())?;
+    let mut tokens = contract::call_getter(function, account.clone(), &inputs)?;
+    match tokens.len() {
+        1 => Ok(tokens.remove(0).value),
+        _ => Err(anyhow!("Getter `{name}` must return a single value")),
+    }
+}
+
+fn answer_only() -> Value {
+    json!({ "answerId": 0 })
+}
+
+fn token_string(value: TokenValue) -> Result<String> {
+    match value {
+        TokenValue::String(value) => Ok(value),
+        TokenValue::Uint(value) => Ok(value.number.to_string()),
+        TokenValue::Address(value) => Ok(value.to_string()),
+        value => Err(anyhow!("Unexpected getter output: {value}")),
+    }
+}
+
+fn get_token_root_details(transport_handle: jlong, root_address: &str) -> Result<String> {
+    let root_address = nekoton_utils::repack_address(root_address)?;
+    let account = contract::get_active_account(transport_handle, &root_address)?;
+    let getter = |name| get(&TOKEN_ROOT, &account, name, answer_only()).and_then(token_string);
+
+    let decimals: u8 = getter("decimals")?.parse()?;
+    Ok(json!({
+        "address": root_address.to_string(),
+        "name": getter("name")?,
+        "symbol": getter("symbol")?,
+        "decimals": decimals,
+        "totalSupply": getter("totalSupply")?,
+        "rootOwner": getter("rootOwner")?,
+    })
+    .to_string())
+}
+
+fn get_token_wallet_address(
+    transport_handle: jlong,
+    root_address: &str,
+    owner: &str,
+) -> Result<String> {
+    let root_address = nekoton_utils::repack_address(root_address)?;
+    let owner = nekoton_utils::repack_address(owner)?;
+    let account = contract::get_active_account(transport_handle, &root_address)?;
+    let inputs = json!({ "answerId": 0, "walletOwner": owner.to_string() });
+    token_string(get(&TOKEN_ROOT, &account, "walletOf", inputs)?)
+}
+
+/// Decimal amount in the smallest token units
+fn get_token_wallet_balance(transport_handle: jlong, wallet_address: &str) -> Result<String> {
+    let wallet_address = nekoton_utils::repack_address(wallet_address)?;
+    let account = contract::get_active_account(transport_handle, &wallet_address)?;
+    token_string(get(&TOKEN_WALLET, &account, "balance", answer_only())?)
+}
+
+/// The internal message the owner's wallet has to send to its token wallet.
+/// A positive `deploy_wallet_value` also deploys the recipient's token wallet
+fn prepare_token_transfer(
+    token_wallet: &str,
+    recipient: &str,
+    amount: &str,
+    deploy_wallet_value: jlong,
+    remaining_gas_to: &str,
+    notify: bool,
+    payload_boc: &[u8],
+) -> Result<String> {
+    let token_wallet = nekoton_utils::repack_address(token_wallet)?;
+    let recipient = nekoton_utils::repack_address(recipient)?;
+    let remaining_gas_to = nekoton_utils::repack_address(remaining_gas_to)?;
+    let amount: BigUint = amount
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid token amount: {amount}"))?;
+    let deploy_wallet_value =
+        u64::try_from(deploy_wallet_value).context("Invalid deploy wallet value")?;
+    let payload = match payload_boc.is_empty() {
+        true => Cell::default(),
+        false => cell::parse_boc(payload_boc)?,
+    };
+
+    let function = TOKEN_WALLET.function("transfer")?;
+    let inputs = json!({
+        "amount": amount.to_string(),
+        "recipient": recipient.to_string(),
+        "deployWalletValue": deploy_wallet_value.to_string(),
+        "remainingGasTo": remaining_gas_to.to_string(),
+        "notify": notify,
+        "payload": base64::encode(cell::serialize_boc(&payload)?),
+    });
+    let inputs: Vec<Token> = abi::parse_tokens(&function.inputs, &inputs.to_string())?;
+    let body = function.encode_internal_input(&inputs)?.into_cell()?;
+
+    Ok(json!({
+        "destination": token_wallet.to_string(),
+        "amount": (TRANSFER_ATTACHED_AMOUNT + deploy_wallet_value).to_string(),
+        "bounce": true,
+        "bodyBoc": base64::encode(cell::serialize_boc(&body)?),
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTokenRootDetails(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    root_address: JString,
+) -> jstring {
+    error::guard!(env, {
+        let root_address: String = match env.get_string(&root_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let details = match get_token_root_details(transport_handle, &root_address) {
+            Ok(details) => details,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(details) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTokenWalletAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    root_address: JString,
+    owner: JString,
+) -> jstring {
+    error::guard!(env, {
+        let root_address: String = match env.get_string(&root_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let owner: String = match env.get_string(&owner) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let address = match get_token_wallet_address(transport_handle, &root_address, &owner) {
+            Ok(address) => address,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(address) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTokenWalletBalance(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    wallet_address: JString,
+) -> jstring {
+    error::guard!(env, {
+        let wallet_address: String = match env.get_string(&wallet_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let balance = match get_token_wallet_balance(transport_handle, &wallet_address) {
+            Ok(balance) => balance,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(balance) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_prepareTokenTransfer(
+    mut env: JNIEnv,
+    _class: JClass,
+    token_wallet: JString,
+    recipient: JString,
+    amount: JString,
+    deploy_wallet_value: jlong,
+    remaining_gas_to: JString,
+    notify: jboolean,
+    payload_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let token_wallet: String = match env.get_string(&token_wallet) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let recipient: String = match env.get_string(&recipient) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let amount: String = match env.get_string(&amount) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let remaining_gas_to: String = match env.get_string(&remaining_gas_to) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let payload_boc = match env.convert_byte_array(payload_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let transfer = match prepare_token_transfer(
+            &token_wallet,
+            &recipient,
+            &amount,
+            deploy_wallet_value,
+            &remaining_gas_to,
+            notify != 0,
+            &payload_boc,
+        ) {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Abi, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(transfer) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use jni::objects::{JByteArray, JClass};
+use jni::sys::jstring;
+use jni::JNIEnv;
+use ton_block::{Deserializable, Transaction};
+
+use crate::{cell, error, models};
+
+fn parse_transaction(transaction_boc: &[u8]) -> Result<String> {
+    let cell = cell::parse_boc(transaction_boc)?;
+    let hash = cell.repr_hash();
+    let transaction = Transaction::construct_from_cell(cell)?;
+    Ok(models::transaction_to_json(&hash, &transaction)?.to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_parseTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    transaction_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let transaction_boc = match env.convert_byte_array(transaction_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let transaction = match parse_transaction(&transaction_boc) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(transaction) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jlong, jstring};
+use jni::JNIEnv;
+use nekoton::transport::gql::GqlTransport;
+use serde_json::json;
+
+use super::TRANSPORTS;
+use crate::error::{self, NekotonError};
+use crate::runtime;
+
+fn get_gql_transport(transport_handle: jlong) -> Result<Arc<GqlTransport>> {
+    TRANSPORTS
+        .get(transport_handle)?
+        .gql
+        .clone()
+        .ok_or_else(|| anyhow!("Block queries require a GraphQL transport"))
+}
+
+/// Latest block of the shard `address` belongs to
+fn get_latest_block(transport_handle: jlong, address: &str) -> Result<String> {
+    let transport = get_gql_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(address)?;
+    let block = runtime::block_on(transport.get_latest_block(&address))?;
+    Ok(json!({
+        "id": block.id,
+        "endLt": block.end_lt.to_string(),
+        "genUtime": block.gen_utime,
+    })
+    .to_string())
+}
+
+/// Id of the block following `current_block_id` in the shard of `address`
+fn wait_for_next_block(
+    transport_handle: jlong,
+    current_block_id: &str,
+    address: &str,
+    timeout_ms: jlong,
+) -> Result<String> {
+    let transport = get_gql_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(address)?;
+    let timeout = u64::try_from(timeout_ms)
+        .ok()
+        .filter(|timeout_ms| *timeout_ms > 0)
+        .map(Duration::from_millis)
+        .ok_or_else(|| anyhow!("Timeout must be a positive number of milliseconds"))?;
+    runtime::block_on(transport.wait_for_next_block(current_block_id, &address, timeout))
+}
+
+/// Block header fields, the block contents stay on the server side
+fn get_block(transport_handle: jlong, block_id: &str) -> Result<String> {
+    let transport = get_gql_transport(transport_handle)?;
+    let block = runtime::block_on(transport.get_block(block_id))?;
+    let info = block.read_info().context("Invalid block info")?;
+    let shard = info.shard();
+    Ok(json!({
+        "id": block_id,
+        "globalId": block.global_id,
+        "workchain": shard.workchain_id(),
+        "shard": format!("{:016x}", shard.shard_prefix_with_tag()),
+        "seqno": info.seq_no(),
+        "genUtime": info.gen_utime().as_u32(),
+        "startLt": info.start_lt().to_string(),
+        "endLt": info.end_lt().to_string(),
+        "keyBlock": info.key_block(),
+        "prevKeyBlockSeqno": info.prev_key_block_seqno(),
+        "afterMerge": info.after_merge(),
+        "afterSplit": info.after_split(),
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getLatestBlock(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+) -> jstring {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let block = match get_latest_block(transport_handle, &address) {
+            Ok(block) => block,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(block) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Blocks the calling thread for up to `timeoutMs`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_waitForNextBlock(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    current_block_id: JString,
+    address: JString,
+    timeout_ms: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let current_block_id: String = match env.get_string(&current_block_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let block_id =
+            match wait_for_next_block(transport_handle, &current_block_id, &address, timeout_ms) {
+                Ok(block_id) => block_id,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Transport, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(block_id) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getBlock(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    block_id: JString,
+) -> jstring {
+    error::guard!(env, {
+        let block_id: String = match env.get_string(&block_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let block = match get_block(transport_handle, &block_id) {
+            Ok(block) => block,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(block) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
@@ -0,0 +1,412 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use reqwest::header::{HeaderValue, CONTENT_TYPE, USER_AGENT};
+
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 32 << 20;
+const JSON: &str = "application/json";
+const PROTOBUF: &str = "application/x-protobuf";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Tags the log records of one request, retries included, across all transports
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// One client for all transports, so connections to the same host are pooled
+/// and kept alive between requests regardless of which handle makes them
+static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+
+fn shared_client() -> Result<reqwest::Client> {
+    let client = CLIENT.get_or_try_init(|| {
+        reqwest::Client::builder()
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .tcp_keepalive(TCP_KEEPALIVE)
+            .build()
+    })?;
+    Ok(client.clone())
+}
+
+#[derive(Debug)]
+pub struct ResponseTooLarge {
+    pub limit: usize,
+}
+
+impl fmt::Display for ResponseTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ResponseTooLarge: response exceeds {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for ResponseTooLarge {}
+
+/// How a request picks its endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Stay on the selected endpoint and move to the next one once it fails
+    Failover,
+    /// Spread requests over all endpoints in turn
+    RoundRobin,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Failover => "failover",
+            Self::RoundRobin => "roundRobin",
+        }
+    }
+}
+
+impl std::str::FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "failover" => Ok(Self::Failover),
+            "roundRobin" => Ok(Self::RoundRobin),
+            _ => Err(anyhow!("Unknown endpoint strategy: {s}")),
+        }
+    }
+}
+
+/// Applies to every request of a transport
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// `None` waits as long as the connection stays open
+    pub timeout: Option<Duration>,
+    /// Further attempts after the first one, each on the endpoint the strategy picks
+    pub retries: u32,
+    pub strategy: Strategy,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retries: 0,
+            strategy: Strategy::Failover,
+        }
+    }
+}
+
+/// Sends every request to the currently selected endpoint out of `endpoints`
+pub struct HttpClient {
+    client: reqwest::Client,
+    endpoints: Vec<String>,
+    /// Of the last successful request to each endpoint, `u64::MAX` until there is one
+    latencies_ms: Vec<AtomicU64>,
+    current: AtomicUsize,
+    round_robin: AtomicUsize,
+    policy: RetryPolicy,
+    user_agent: RwLock<HeaderValue>,
+    max_response_bytes: AtomicUsize,
+}
+
+impl HttpClient {
+    pub fn with_endpoints(endpoints: Vec<String>, policy: RetryPolicy) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(anyhow!("At least one endpoint is required"));
+        }
+        for endpoint in &endpoints {
+            reqwest::Url::parse(endpoint)
+                .map_err(|e| anyhow!("Invalid endpoint {endpoint:?}: {e}"))?;
+        }
+        Ok(Self {
+            client: shared_client()?,
+            latencies_ms: endpoints.iter().map(|_| AtomicU64::new(u64::MAX)).collect(),
+            endpoints,
+            current: AtomicUsize::new(0),
+            round_robin: AtomicUsize::new(0),
+            policy,
+            user_agent: RwLock::new(default_user_agent()),
+            max_response_bytes: AtomicUsize::new(DEFAULT_MAX_RESPONSE_BYTES),
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoints[self.current.load(Ordering::Acquire)]
+    }
+
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.current.load(Ordering::Acquire)
+    }
+
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    pub fn latency(&self, index: usize) -> Option<Duration> {
+        match self.latencies_ms[index].load(Ordering::Relaxed) {
+            u64::MAX => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    fn record_latency(&self, index: usize, latency: Duration) {
+        let ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX - 1);
+        self.latencies_ms[index].store(ms, Ordering::Relaxed);
+    }
+
+    /// Sends `probe` to every endpoint at once and selects the one that answered first
+    pub async fn select_fastest(&self, probe: &str) -> Result<()> {
+        if self.endpoints.len() == 1 {
+            return Ok(());
+        }
+
+        let probes = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| async move {
+                let started = Instant::now();
+                match tokio::time::timeout(
+                    PROBE_TIMEOUT,
+                    self.post_to(endpoint, JSON, probe.as_bytes().to_vec()),
+                )
+                .await
+                {
+                    Ok(Ok(_)) => {
+                        let latency = started.elapsed();
+                        self.record_latency(index, latency);
+                        Some((index, latency))
+                    }
+                    _ => None,
+                }
+            });
+        let (index, _) = futures_util::future::join_all(probes)
+            .await
+            .into_iter()
+            .flatten()
+            .min_by_key(|(_, latency)| *latency)
+            .ok_or_else(|| anyhow!("None of the {} endpoints responded", self.endpoints.len()))?;
+
+        self.current.store(index, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn set_user_agent(&self, user_agent: &str) -> Result<()> {
+        if user_agent.is_empty() {
+            return Err(anyhow!("User agent must not be empty"));
+        }
+        if user_agent.chars().any(char::is_control) {
+            return Err(anyhow!("User agent must not contain control characters"));
+        }
+        let user_agent = HeaderValue::from_str(user_agent)
+            .map_err(|_| anyhow!("User agent must contain only visible ASCII characters"))?;
+        *self.user_agent.write() = user_agent;
+        Ok(())
+    }
+
+    pub fn user_agent(&self) -> HeaderValue {
+        self.user_agent.read().clone()
+    }
+
+    pub fn max_response_bytes(&self) -> usize {
+        self.max_response_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_max_response_bytes(&self, bytes: usize) -> Result<()> {
+        if bytes == 0 {
+            return Err(anyhow!("Max response size must be positive"));
+        }
+        self.max_response_bytes.store(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub async fn post_json(&self, data: String) -> Result<String> {
+        let body = self.post(JSON, data.into_bytes()).await?;
+        Ok(String::from_utf8(body)?)
+    }
+
+    pub async fn post_protobuf(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.post(PROTOBUF, data).await
+    }
+
+    /// Retries on the endpoint the strategy picks next, except for oversized
+    /// responses which every endpoint would return alike
+    async fn post(&self, content_type: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        let mut attempt = 0;
+        loop {
+            let index = match self.policy.strategy {
+                Strategy::Failover => self.current.load(Ordering::Acquire),
+                Strategy::RoundRobin => {
+                    self.round_robin.fetch_add(1, Ordering::Relaxed) % self.endpoints.len()
+                }
+            };
+            let endpoint = &self.endpoints[index];
+
+            log::debug!(
+                target: "nekoton_jni::transport",
+                request_id = request_id,
+                attempt = attempt,
+                endpoint = endpoint.as_str(),
+                bytes = data.len();
+                "Request started"
+            );
+            let started = Instant::now();
+            let request = self.post_to(endpoint, content_type, data.clone());
+            let result = match self.policy.timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, request).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow!("Request to {endpoint} timed out after {timeout:?}")),
+                },
+                None => request.await,
+            };
+            let error = match result {
+                Ok(body) => {
+                    let elapsed = started.elapsed();
+                    log::debug!(
+                        target: "nekoton_jni::transport",
+                        request_id = request_id,
+                        elapsed_ms = elapsed.as_millis() as u64,
+                        bytes = body.len();
+                        "Request finished"
+                    );
+                    self.record_latency(index, elapsed);
+                    if self.policy.strategy == Strategy::RoundRobin {
+                        // Keeps `endpoint()` pointing at one that works
+                        self.current.store(index, Ordering::Release);
+                    }
+                    return Ok(body);
+                }
+                Err(e) if e.is::<ResponseTooLarge>() => return Err(e),
+                Err(e) => e,
+            };
+
+            if self.policy.strategy == Strategy::Failover && self.endpoints.len() > 1 {
+                let next = (index + 1) % self.endpoints.len();
+                // Another request may have moved on already
+                let _ =
+                    self.current
+                        .compare_exchange(index, next, Ordering::AcqRel, Ordering::Acquire);
+            }
+            if attempt >= self.policy.retries {
+                return Err(error);
+            }
+            attempt += 1;
+            log::warn!(
+                target: "nekoton_jni::transport",
+                request_id = request_id,
+                attempt = attempt;
+                "Request to {endpoint} failed, retrying: {error:#}"
+            );
+        }
+    }
+
+    async fn post_to(&self, endpoint: &str, content_type: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let mut response = self
+            .client
+            .post(endpoint)
+            .header(CONTENT_TYPE, content_type)
+            .header(USER_AGENT, self.user_agent())
+            .body(data)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // The declared length is only a hint, the body is still counted while reading
+        let limit = self.max_response_bytes();
+        if matches!(response.content_length(), Some(length) if length > limit as u64) {
+            return Err(ResponseTooLarge { limit }.into());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(ResponseTooLarge { limit }.into());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+fn default_user_agent() -> HeaderValue {
+    HeaderValue::from_static(concat!("nekoton-kotlin/", env!("CARGO_PKG_VERSION")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::runtime;
+
+    /// Answers a single request with `response` and returns the endpoint
+    fn serve_once(response: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Reads the whole request first, closing with unread data resets the connection
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while let Ok(read @ 1..) = stream.read(&mut buffer) {
+                request.extend_from_slice(&buffer[..read]);
+                let request = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = request.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")?
+                                .parse()
+                                .ok()
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let _ = stream.write_all(response.as_bytes());
+        });
+        endpoint
+    }
+
+    fn post_with_limit(response: String, limit: usize) -> Result<String> {
+        let client =
+            HttpClient::with_endpoints(vec![serve_once(response)], RetryPolicy::default()).unwrap();
+        client.set_max_response_bytes(limit).unwrap();
+        runtime::block_on(client.post_json("{}".to_owned()))
+    }
+
+    fn with_length(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    fn chunked(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{body}\r\n0\r\n\r\n",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn body_within_limit_is_returned() {
+        assert_eq!(post_with_limit(with_length("{}"), 16).unwrap(), "{}");
+    }
+
+    #[test]
+    fn oversized_body_fails_as_response_too_large() {
+        let body = "a".repeat(64);
+        // Rejected by the declared length and, without one, while reading
+        for response in [with_length(&body), chunked(&body)] {
+            let error = post_with_limit(response, 16).unwrap_err();
+            assert_eq!(error.downcast_ref::<ResponseTooLarge>().unwrap().limit, 16);
+        }
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use jni::objects::{JClass, JString};
+use jni::sys::{jlong, jstring};
+use jni::JNIEnv;
+use nekoton::core::models::ReliableBehavior;
+use nekoton::models::NetworkCapabilities;
+use nekoton::transport::models::{
+    ExistingContract, PollContractState, RawContractState, RawTransaction,
+};
+use nekoton::transport::{Transport, TransportInfo};
+use nekoton_abi::{GenTimings, LastTransactionId, TransactionId};
+use nekoton_utils::Clock;
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use serde_json::Value;
+use ton_block::{
+    Account, AccountState, AccountStuff, Block, ConfigParams, Deserializable, Message,
+    MsgAddressInt, Serializable, Transaction,
+};
+use ton_executor::BlockchainConfig;
+use ton_types::{Cell, UInt256};
+
+use super::{TransportEntry, TRANSPORTS};
+use crate::cell;
+use crate::error::{self, NekotonError};
+
+const METHODS: &[&str] = &[
+    "sendMessage",
+    "getContractState",
+    "getLibraryCell",
+    "pollContractState",
+    "getAccountsByCodeHash",
+    "getTransactions",
+    "getTransaction",
+    "getDstTransaction",
+    "getLatestKeyBlock",
+    "getCapabilities",
+    "getBlockchainConfig",
+];
+
+/// Answers every query from fixtures, so tests run offline and reproducibly.
+/// Sent messages change no state, they are recorded and answered with the
+/// reply transaction of their destination, if any
+pub struct MockTransport {
+    // Ordered by address, the order `getAccountsByCodeHash` pages in
+    accounts: BTreeMap<String, (MsgAddressInt, AccountStuff)>,
+    // Newest first for every address
+    transactions: HashMap<String, Vec<RawTransaction>>,
+    config: Option<(ConfigParams, i32)>,
+    key_block: Option<Block>,
+    latency: Duration,
+    failures: HashMap<String, String>,
+    // By destination address
+    replies: HashMap<String, RawTransaction>,
+    sent_messages: Mutex<Vec<Cell>>,
+    // By the hash of the sent message they answer
+    answered: Mutex<HashMap<UInt256, RawTransaction>>,
+}
+
+fn decode_boc(value: &Value, name: &str) -> Result<Cell> {
+    let boc = value
+        .as_str()
+        .ok_or_else(|| anyhow!("`{name}` must be a base64 BOC"))?;
+    let boc = base64::decode(boc).with_context(|| format!("Invalid base64 in `{name}`"))?;
+    cell::parse_boc(&boc).with_context(|| format!("Invalid BOC in `{name}`"))
+}
+
+fn decode_transaction(value: &Value, name: &str) -> Result<RawTransaction> {
+    let cell = decode_boc(value, name)?;
+    Ok(RawTransaction {
+        hash: cell.repr_hash(),
+        data: Transaction::construct_from_cell(cell)?,
+    })
+}
+
+impl MockTransport {
+    /// `accounts` maps addresses to account BOCs, an address missing there does
+    /// not exist. `transactions` maps addresses to transaction BOCs in any order.
+    /// `configBoc` and `globalId` are what `getBlockchainConfig` returns,
+    /// `keyBlockBoc` the latest key block. `replies` maps addresses to the
+    /// transaction BOC `getDstTransaction` returns for any message sent there.
+    /// Every call waits `latencyMs` and fails with the message `failures` holds
+    /// for its method, if any
+    pub fn from_fixtures(fixtures: &str) -> Result<Self> {
+        let fixtures: Value = serde_json::from_str(fixtures).context("Invalid fixtures JSON")?;
+        let entries = |name: &str| -> Result<Vec<(String, Value)>> {
+            match &fixtures[name] {
+                Value::Null => Ok(Vec::new()),
+                Value::Object(entries) => Ok(entries.clone().into_iter().collect()),
+                _ => Err(anyhow!("`{name}` must be an object")),
+            }
+        };
+
+        let mut accounts = BTreeMap::new();
+        for (address, boc) in entries("accounts")? {
+            let address = nekoton_utils::repack_address(&address)?;
+            let account = match Account::construct_from_cell(decode_boc(&boc, "accounts")?)? {
+                Account::Account(account) => account,
+                Account::AccountNone => continue,
+            };
+            accounts.insert(address.to_string(), (address, account));
+        }
+
+        let mut transactions = HashMap::new();
+        for (address, bocs) in entries("transactions")? {
+            let address = nekoton_utils::repack_address(&address)?;
+            let bocs = bocs
+                .as_array()
+                .ok_or_else(|| anyhow!("Transactions of {address} must be an array"))?;
+            let mut list = bocs
+                .iter()
+                .map(|boc| decode_transaction(boc, "transactions"))
+                .collect::<Result<Vec<_>>>()?;
+            list.sort_by(|a, b| b.data.lt.cmp(&a.data.lt));
+            transactions.insert(address.to_string(), list);
+        }
+
+        let config = match &fixtures["configBoc"] {
+            Value::Null => None,
+            boc => {
+                let config = ConfigParams::construct_from_cell(decode_boc(boc, "configBoc")?)?;
+                let global_id = fixtures["globalId"]
+                    .as_i64()
+                    .and_then(|global_id| i32::try_from(global_id).ok())
+                    .ok_or_else(|| anyhow!("`globalId` is required along with `configBoc`"))?;
+                Some((config, global_id))
+            }
+        };
+        let key_block = match &fixtures["keyBlockBoc"] {
+            Value::Null => None,
+            boc => Some(Block::construct_from_cell(decode_boc(boc, "keyBlockBoc")?)?),
+        };
+        let latency = match &fixtures["latencyMs"] {
+            Value::Null => Duration::ZERO,
+            value => Duration::from_millis(
+                value
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("`latencyMs` must be a non-negative integer"))?,
+            ),
+        };
+
+        let mut failures = HashMap::new();
+        for (method, message) in entries("failures")? {
+            if !METHODS.contains(&method.as_str()) {
+                return Err(anyhow!("Unknown transport method in `failures`: {method}"));
+            }
+            let message = message
+                .as_str()
+                .ok_or_else(|| anyhow!("Failure of `{method}` must be a message"))?;
+            failures.insert(method, message.to_owned());
+        }
+
+        let mut replies = HashMap::new();
+        for (address, boc) in entries("replies")? {
+            let address = nekoton_utils::repack_address(&address)?;
+            replies.insert(address.to_string(), decode_transaction(&boc, "replies")?);
+        }
+
+        Ok(Self {
+            accounts,
+            transactions,
+            config,
+            key_block,
+            latency,
+            failures,
+            replies,
+            sent_messages: Mutex::new(Vec::new()),
+            answered: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// BOCs of the messages sent so far, oldest first
+    pub fn sent_messages(&self) -> Vec<Cell> {
+        self.sent_messages.lock().clone()
+    }
+
+    async fn enter(&self, method: &str) -> Result<()> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        match self.failures.get(method) {
+            Some(message) => Err(anyhow!("{message}")),
+            None => Ok(()),
+        }
+    }
+
+    fn account(&self, address: &MsgAddressInt) -> Option<&AccountStuff> {
+        self.accounts
+            .get(&address.to_string())
+            .map(|(_, account)| account)
+    }
+
+    fn all_transactions(&self) -> impl Iterator<Item = &RawTransaction> {
+        self.transactions.values().flatten()
+    }
+
+    fn blockchain_config(&self) -> Result<BlockchainConfig> {
+        let (config, global_id) = self
+            .config
+            .clone()
+            .ok_or_else(|| anyhow!("Fixtures have no `configBoc`"))?;
+        Ok(BlockchainConfig::with_config(config, global_id)?)
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for MockTransport {
+    fn info(&self) -> TransportInfo {
+        TransportInfo {
+            max_transactions_per_fetch: u8::MAX,
+            reliable_behavior: ReliableBehavior::IntensivePolling,
+            has_key_blocks: self.key_block.is_some(),
+        }
+    }
+
+    async fn send_message(&self, message: &Message) -> Result<()> {
+        self.enter("sendMessage").await?;
+        let cell = message.serialize()?;
+        let reply = message
+            .dst_ref()
+            .and_then(|dst| self.replies.get(&dst.to_string()));
+        if let Some(reply) = reply {
+            self.answered.lock().insert(cell.repr_hash(), reply.clone());
+        }
+        self.sent_messages.lock().push(cell);
+        Ok(())
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.enter("getContractState").await?;
+        let account = match self.account(address) {
+            Some(account) => account.clone(),
+            None => {
+                return Ok(RawContractState::NotExists {
+                    timings: GenTimings::Unknown,
+                })
+            }
+        };
+
+        // The hash is only known when the last transaction is among the fixtures
+        let latest_lt = account.storage.last_trans_lt;
+        let last_transaction_id =
+            match self
+                .transactions
+                .get(&address.to_string())
+                .and_then(|list| {
+                    list.iter()
+                        .find(|transaction| transaction.data.lt == latest_lt)
+                }) {
+                Some(transaction) => LastTransactionId::Exact(TransactionId {
+                    lt: latest_lt,
+                    hash: transaction.hash,
+                }),
+                None => LastTransactionId::Inexact { latest_lt },
+            };
+        Ok(RawContractState::Exists(ExistingContract {
+            account,
+            timings: GenTimings::Unknown,
+            last_transaction_id,
+        }))
+    }
+
+    async fn get_library_cell(&self, _hash: &UInt256) -> Result<Option<Cell>> {
+        self.enter("getLibraryCell").await?;
+        Ok(None)
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        self.enter("pollContractState").await?;
+        let latest_lt = self
+            .account(address)
+            .map(|account| account.storage.last_trans_lt)
+            .unwrap_or_default();
+        Ok(match latest_lt == last_trans_lt {
+            true => PollContractState::Unchanged {
+                timings: GenTimings::Unknown,
+            },
+            false => PollContractState::Changed,
+        })
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.enter("getAccountsByCodeHash").await?;
+        let after = continuation.as_ref().map(ToString::to_string);
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(key, _)| match &after {
+                Some(after) => *key > after,
+                None => true,
+            })
+            .filter(|(_, (_, account))| match &account.storage.state {
+                AccountState::AccountActive { state_init } => state_init
+                    .code
+                    .as_ref()
+                    .is_some_and(|code| code.repr_hash() == *code_hash),
+                _ => false,
+            })
+            .take(limit as usize)
+            .map(|(_, (address, _))| address.clone())
+            .collect())
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.enter("getTransactions").await?;
+        Ok(self
+            .transactions
+            .get(&address.to_string())
+            .map(|list| {
+                list.iter()
+                    .filter(|transaction| transaction.data.lt <= from_lt)
+                    .take(count as usize)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        self.enter("getTransaction").await?;
+        Ok(self
+            .all_transactions()
+            .find(|transaction| transaction.hash == *id)
+            .cloned())
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        self.enter("getDstTransaction").await?;
+        if let Some(reply) = self.answered.lock().get(message_hash) {
+            return Ok(Some(reply.clone()));
+        }
+        Ok(self
+            .all_transactions()
+            .find(|transaction| {
+                transaction
+                    .data
+                    .in_msg_cell()
+                    .is_some_and(|message| message.repr_hash() == *message_hash)
+            })
+            .cloned())
+    }
+
+    async fn get_latest_key_block(&self) -> Result<Block> {
+        self.enter("getLatestKeyBlock").await?;
+        self.key_block
+            .clone()
+            .ok_or_else(|| anyhow!("Fixtures have no `keyBlockBoc`"))
+    }
+
+    async fn get_capabilities(&self, _clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.enter("getCapabilities").await?;
+        let config = self.blockchain_config()?;
+        Ok(NetworkCapabilities {
+            global_id: config.global_id(),
+            raw: config.capabilites(),
+        })
+    }
+
+    async fn get_blockchain_config(
+        &self,
+        _clock: &dyn Clock,
+        _force: bool,
+    ) -> Result<BlockchainConfig> {
+        self.enter("getBlockchainConfig").await?;
+        self.blockchain_config()
+    }
+}
+
+pub fn create_mock_transport(fixtures: &str) -> Result<jlong> {
+    let mock = Arc::new(MockTransport::from_fixtures(fixtures)?);
+    Ok(TRANSPORTS.insert(Arc::new(TransportEntry {
+        transport: mock.clone(),
+        http: None,
+        gql: None,
+        mock: Some(mock),
+        signature_id: OnceCell::new(),
+    })))
+}
+
+/// JSON array of base64 BOCs, oldest first
+fn get_mock_sent_messages(transport_handle: jlong) -> Result<String> {
+    let mock = TRANSPORTS
+        .get(transport_handle)?
+        .mock
+        .clone()
+        .ok_or_else(|| anyhow!("Not a mock transport"))?;
+    let messages = mock
+        .sent_messages()
+        .iter()
+        .map(|message| Ok(base64::encode(cell::serialize_boc(message)?)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(serde_json::to_string(&messages)?)
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createMockTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    fixtures_json: JString,
+) -> jlong {
+    error::guard!(env, {
+        let fixtures: String = match env.get_string(&fixtures_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_mock_transport(&fixtures) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getMockSentMessages(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let messages = match get_mock_sent_messages(transport_handle) {
+            Ok(messages) => messages,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(messages) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use nekoton_utils::SimpleClock;
+    use serde_json::json;
+    use ton_block::{
+        AccountStatus, CurrencyCollection, ExternalInboundMessageHeader, TransactionDescr,
+        TransactionDescrOrdinary,
+    };
+
+    use super::*;
+    use crate::runtime;
+    use crate::transport::get_transport;
+
+    const ADDRESS: &str = "0:3333333333333333333333333333333333333333333333333333333333333333";
+    const OTHER: &str = "0:4444444444444444444444444444444444444444444444444444444444444444";
+
+    fn boc(cell: Cell) -> String {
+        base64::encode(cell::serialize_boc(&cell).unwrap())
+    }
+
+    fn parse_address(address: &str) -> MsgAddressInt {
+        nekoton_utils::repack_address(address).unwrap()
+    }
+
+    fn account_boc(address: &MsgAddressInt) -> String {
+        let account =
+            Account::with_address_and_ballance(address, &CurrencyCollection::with_grams(1_000));
+        boc(account.serialize().unwrap())
+    }
+
+    fn transaction(address: &MsgAddressInt, lt: u64) -> Transaction {
+        let mut transaction =
+            Transaction::with_address_and_status(address.address(), AccountStatus::AccStateActive);
+        transaction.set_logical_time(lt);
+        transaction
+            .write_description(&TransactionDescr::Ordinary(
+                TransactionDescrOrdinary::default(),
+            ))
+            .unwrap();
+        transaction
+    }
+
+    fn external_message(address: &MsgAddressInt) -> Message {
+        Message::with_ext_in_header(ExternalInboundMessageHeader {
+            dst: address.clone(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn contract_states_come_from_fixtures() {
+        let address = parse_address(ADDRESS);
+        let fixtures = json!({ "accounts": { ADDRESS: account_boc(&address) } });
+        let mock = MockTransport::from_fixtures(&fixtures.to_string()).unwrap();
+
+        match runtime::block_on(mock.get_contract_state(&address)).unwrap() {
+            RawContractState::Exists(contract) => {
+                assert_eq!(contract.account.storage.balance.grams.as_u128(), 1_000);
+                assert!(matches!(
+                    contract.last_transaction_id,
+                    LastTransactionId::Inexact { latest_lt: 0 }
+                ));
+            }
+            RawContractState::NotExists { .. } => panic!("Fixture account is missing"),
+        }
+        assert!(matches!(
+            runtime::block_on(mock.get_contract_state(&parse_address(OTHER))).unwrap(),
+            RawContractState::NotExists { .. }
+        ));
+    }
+
+    #[test]
+    fn transactions_are_paged_newest_first() {
+        let address = parse_address(ADDRESS);
+        let bocs = [1, 3, 2]
+            .iter()
+            .map(|lt| boc(transaction(&address, *lt).serialize().unwrap()))
+            .collect::<Vec<_>>();
+        let fixtures = json!({ "transactions": { ADDRESS: bocs } });
+        let mock = MockTransport::from_fixtures(&fixtures.to_string()).unwrap();
+
+        let page = runtime::block_on(mock.get_transactions(&address, 2, 10)).unwrap();
+        let lts = page
+            .iter()
+            .map(|transaction| transaction.data.lt)
+            .collect::<Vec<_>>();
+        assert_eq!(lts, [2, 1]);
+    }
+
+    #[test]
+    fn failures_fail_only_their_method() {
+        let fixtures = json!({ "failures": { "getLatestKeyBlock": "Node is down" } });
+        let mock = MockTransport::from_fixtures(&fixtures.to_string()).unwrap();
+
+        let error = runtime::block_on(mock.get_latest_key_block()).unwrap_err();
+        assert_eq!(error.to_string(), "Node is down");
+        assert!(runtime::block_on(mock.get_library_cell(&UInt256::default())).is_ok());
+        // Without a config the error comes from the fixtures, not from `failures`
+        let error = runtime::block_on(mock.get_capabilities(&SimpleClock)).unwrap_err();
+        assert_eq!(error.to_string(), "Fixtures have no `configBoc`");
+
+        let unknown = json!({ "failures": { "getEverything": "" } });
+        assert!(MockTransport::from_fixtures(&unknown.to_string()).is_err());
+    }
+
+    #[test]
+    fn sent_messages_are_answered_by_the_reply_of_their_destination() {
+        let address = parse_address(ADDRESS);
+        let reply = transaction(&address, 5).serialize().unwrap();
+        let fixtures = json!({ "replies": { ADDRESS: boc(reply.clone()) } });
+        let mock = MockTransport::from_fixtures(&fixtures.to_string()).unwrap();
+
+        let answered = external_message(&address);
+        let unanswered = external_message(&parse_address(OTHER));
+        runtime::block_on(mock.send_message(&answered)).unwrap();
+        runtime::block_on(mock.send_message(&unanswered)).unwrap();
+
+        let hash = answered.serialize().unwrap().repr_hash();
+        let transaction = runtime::block_on(mock.get_dst_transaction(&hash))
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.hash, reply.repr_hash());
+        let hash = unanswered.serialize().unwrap().repr_hash();
+        assert!(runtime::block_on(mock.get_dst_transaction(&hash))
+            .unwrap()
+            .is_none());
+
+        assert_eq!(mock.sent_messages().len(), 2);
+    }
+
+    #[test]
+    fn one_handle_serves_many_threads() {
+        let address = parse_address(ADDRESS);
+        let fixtures = json!({ "accounts": { ADDRESS: account_boc(&address) } });
+        let handle = create_mock_transport(&fixtures.to_string()).unwrap();
+
+        let threads = (0..32)
+            .map(|_| {
+                let address = address.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..16 {
+                        let transport = get_transport(handle).unwrap();
+                        let state =
+                            runtime::block_on(transport.get_contract_state(&address)).unwrap();
+                        assert!(matches!(state, RawContractState::Exists(_)));
+                        runtime::block_on(transport.send_message(&external_message(&address)))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let sent: Vec<String> =
+            serde_json::from_str(&get_mock_sent_messages(handle).unwrap()).unwrap();
+        assert_eq!(sent.len(), 32 * 16);
+        TRANSPORTS.remove(handle).unwrap();
+        assert!(get_transport(handle).is_err());
+    }
+}
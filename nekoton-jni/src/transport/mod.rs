@@ -0,0 +1,1738 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::{StreamExt, TryStreamExt};
+use jni::objects::{JByteArray, JClass, JObject, JString};
+use jni::sys::{jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::external::{
+    GqlConnection, GqlRequest, JrpcConnection, JrpcRequest, ProtoConnection, ProtoRequest,
+};
+use nekoton::transport::gql::GqlTransport;
+use nekoton::transport::jrpc::JrpcTransport;
+use nekoton::transport::models::{ExistingContract, RawContractState, RawTransaction};
+use nekoton::transport::proto::ProtoTransport;
+use nekoton::transport::Transport;
+use nekoton_abi::{GenTimings, LastTransactionId, TransactionId};
+use once_cell::sync::{Lazy, OnceCell};
+use serde_json::{json, Value};
+use ton_block::{
+    Account, Deserializable, GlobalCapabilities, Message, MsgAddressInt, Serializable,
+};
+use ton_types::{Cell, SliceData, UInt256};
+
+use self::http::{HttpClient, RetryPolicy};
+use self::mock::MockTransport;
+use self::quorum::QuorumTransport;
+use crate::callback::{self, JavaCallback};
+use crate::cell;
+use crate::clock;
+use crate::error::{self, ErrorCode, NekotonError};
+use crate::executor;
+use crate::models;
+use crate::registry::Registry;
+use crate::runtime;
+
+mod blocks;
+mod http;
+pub mod mock;
+mod quorum;
+mod ws;
+
+pub use self::http::ResponseTooLarge;
+pub use self::quorum::QuorumNotReached;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Cheapest query every GraphQL endpoint answers
+const GQL_PROBE: &str = r#"{"query":"{info{version}}"}"#;
+/// How long `sendExternalMessage` waits for the message to be included by default
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// Transactions fetched per request while looking for a delivered message
+const SCAN_BATCH_SIZE: u8 = 16;
+/// Most state requests `getContractStatesBatch` keeps in flight at once
+const BATCH_CONCURRENCY: usize = 8;
+/// Longest account history `getTransactionStates` replays to rebuild a state
+const MAX_REPLAYED_TRANSACTIONS: usize = 256;
+/// Furthest from chain time that `message_expire_at` takes a header time as read correctly
+const MAX_HEADER_DRIFT_SECS: u32 = 86_400;
+
+struct TransportEntry {
+    transport: Arc<dyn Transport>,
+    // Composite transports have no HTTP client of their own
+    http: Option<Arc<HttpClient>>,
+    // Only GraphQL transports have a block API
+    gql: Option<Arc<GqlTransport>>,
+    // Only mock transports record what was sent through them
+    mock: Option<Arc<MockTransport>>,
+    // Asked for on first use, signature ids never change within a network
+    signature_id: OnceCell<Option<i32>>,
+}
+
+static TRANSPORTS: Lazy<Registry<TransportEntry>> = Lazy::new(Registry::new);
+
+pub fn get_transport(handle: jlong) -> Result<Arc<dyn Transport>> {
+    Ok(TRANSPORTS.get(handle)?.transport.clone())
+}
+
+fn register(
+    transport: Arc<dyn Transport>,
+    http: Option<Arc<HttpClient>>,
+    gql: Option<Arc<GqlTransport>>,
+) -> jlong {
+    TRANSPORTS.insert(Arc::new(TransportEntry {
+        transport,
+        http,
+        gql,
+        mock: None,
+        signature_id: OnceCell::new(),
+    }))
+}
+
+struct GqlClient {
+    http: Arc<HttpClient>,
+    local: bool,
+}
+
+#[async_trait::async_trait]
+impl GqlConnection for GqlClient {
+    fn is_local(&self) -> bool {
+        self.local
+    }
+
+    async fn post(&self, req: GqlRequest) -> Result<String> {
+        self.http.post_json(req.data).await
+    }
+}
+
+struct JrpcClient {
+    http: Arc<HttpClient>,
+}
+
+#[async_trait::async_trait]
+impl JrpcConnection for JrpcClient {
+    async fn post(&self, req: JrpcRequest) -> Result<String> {
+        self.http.post_json(req.data).await
+    }
+}
+
+struct ProtoClient {
+    http: Arc<HttpClient>,
+}
+
+#[async_trait::async_trait]
+impl ProtoConnection for ProtoClient {
+    async fn post(&self, req: ProtoRequest) -> Result<Vec<u8>> {
+        self.http.post_protobuf(req.data).await
+    }
+}
+
+/// Either a single URL, a JSON array of URLs, or an object of `endpoints`
+/// with optional `timeoutMs`, `retries` and `strategy` (`failover` or `roundRobin`)
+fn parse_endpoints(config: &str) -> Result<(Vec<String>, RetryPolicy)> {
+    let config = config.trim();
+    if config.starts_with('[') {
+        let endpoints = serde_json::from_str(config).context("Invalid endpoints JSON")?;
+        return Ok((endpoints, RetryPolicy::default()));
+    }
+    if !config.starts_with('{') {
+        return Ok((vec![config.to_owned()], RetryPolicy::default()));
+    }
+
+    let config: serde_json::Map<String, Value> =
+        serde_json::from_str(config).context("Invalid transport config JSON")?;
+    if let Some(unknown) = config
+        .keys()
+        .find(|key| !["endpoints", "timeoutMs", "retries", "strategy"].contains(&key.as_str()))
+    {
+        return Err(anyhow!("Unknown transport config field: {unknown}"));
+    }
+    let endpoints = serde_json::from_value(config.get("endpoints").cloned().unwrap_or_default())
+        .context("`endpoints` must be an array of URLs")?;
+
+    let mut policy = RetryPolicy::default();
+    if let Some(timeout) = config.get("timeoutMs") {
+        let timeout = timeout
+            .as_u64()
+            .filter(|timeout| *timeout > 0)
+            .ok_or_else(|| anyhow!("`timeoutMs` must be a positive integer"))?;
+        policy.timeout = Some(Duration::from_millis(timeout));
+    }
+    if let Some(retries) = config.get("retries") {
+        policy.retries = retries
+            .as_u64()
+            .and_then(|retries| u32::try_from(retries).ok())
+            .ok_or_else(|| anyhow!("`retries` must be a non-negative integer"))?;
+    }
+    if let Some(strategy) = config.get("strategy") {
+        policy.strategy = strategy
+            .as_str()
+            .ok_or_else(|| anyhow!("`strategy` must be a string"))?
+            .parse()?;
+    }
+    Ok((endpoints, policy))
+}
+
+fn create_http_client(config: &str) -> Result<Arc<HttpClient>> {
+    let (endpoints, policy) = parse_endpoints(config)?;
+    Ok(Arc::new(HttpClient::with_endpoints(endpoints, policy)?))
+}
+
+/// With several endpoints the one with the lowest latency is selected first
+fn create_gql_transport(endpoints: &str) -> Result<jlong> {
+    let http = create_http_client(endpoints)?;
+    runtime::block_on(http.select_fastest(GQL_PROBE))?;
+
+    let endpoint = http.endpoint();
+    let connection = GqlClient {
+        http: http.clone(),
+        local: endpoint.contains("localhost") || endpoint.contains("127.0.0.1"),
+    };
+    let transport = Arc::new(GqlTransport::new(Arc::new(connection)));
+    Ok(register(transport.clone(), Some(http), Some(transport)))
+}
+
+fn create_jrpc_transport(endpoints: &str) -> Result<jlong> {
+    let http = create_http_client(endpoints)?;
+    let connection = JrpcClient { http: http.clone() };
+    let transport = Arc::new(JrpcTransport::new(Arc::new(connection)));
+    Ok(register(transport, Some(http), None))
+}
+
+fn create_proto_transport(endpoints: &str) -> Result<jlong> {
+    let http = create_http_client(endpoints)?;
+    let connection = ProtoClient { http: http.clone() };
+    let transport = Arc::new(ProtoTransport::new(Arc::new(connection)));
+    Ok(register(transport, Some(http), None))
+}
+
+fn create_quorum_transport(transport_handles: &str, quorum: jint) -> Result<jlong> {
+    let handles: Vec<jlong> =
+        serde_json::from_str(transport_handles).context("Invalid transport handles JSON")?;
+    let transports = handles
+        .into_iter()
+        .map(get_transport)
+        .collect::<Result<Vec<_>>>()?;
+    let quorum = usize::try_from(quorum).context("Invalid quorum")?;
+
+    let transport = Arc::new(QuorumTransport::new(transports, quorum)?);
+    Ok(register(transport, None, None))
+}
+
+fn get_http_client(transport_handle: jlong) -> Result<Arc<HttpClient>> {
+    TRANSPORTS
+        .get(transport_handle)?
+        .http
+        .clone()
+        .ok_or_else(|| anyhow!("Transport has no endpoints of its own"))
+}
+
+fn get_transport_info(transport_handle: jlong) -> Result<String> {
+    let http = get_http_client(transport_handle)?;
+    let latency_ms = |index| {
+        http.latency(index)
+            .map(|latency| latency.as_millis() as u64)
+    };
+    let current = http.current_index();
+    let policy = http.policy();
+
+    let endpoints = http
+        .endpoints()
+        .iter()
+        .enumerate()
+        .map(|(index, url)| json!({ "url": url, "latencyMs": latency_ms(index) }))
+        .collect::<Vec<_>>();
+    Ok(json!({
+        "endpoint": http.endpoint(),
+        "latencyMs": latency_ms(current),
+        "strategy": policy.strategy.name(),
+        "timeoutMs": policy.timeout.map(|timeout| timeout.as_millis() as u64),
+        "retries": policy.retries,
+        "endpoints": endpoints,
+    })
+    .to_string())
+}
+
+/// Id that signing with `NETWORK_SIGNATURE_ID` and this transport uses
+pub fn network_signature_id(transport_handle: jlong) -> Result<Option<i32>> {
+    let entry = TRANSPORTS.get(transport_handle)?;
+    if let Some(signature_id) = entry.signature_id.get() {
+        return Ok(*signature_id);
+    }
+    let capabilities = runtime::block_on(entry.transport.get_capabilities(clock::CLOCK.as_ref()))?;
+    Ok(*entry
+        .signature_id
+        .get_or_init(|| capabilities.signature_id()))
+}
+
+fn get_signature_id(transport_handle: jlong) -> Result<jlong> {
+    Ok(network_signature_id(transport_handle)?.map_or(-1, jlong::from))
+}
+
+/// Capabilities and the raw config as of the latest key block
+fn get_blockchain_config(transport_handle: jlong) -> Result<String> {
+    let entry = TRANSPORTS.get(transport_handle)?;
+    let config = runtime::block_on(
+        entry
+            .transport
+            .get_blockchain_config(clock::CLOCK.as_ref(), true),
+    )?;
+    let signature_id = config
+        .has_capability(GlobalCapabilities::CapSignatureWithId)
+        .then(|| config.global_id());
+    let _ = entry.signature_id.set(signature_id);
+
+    let config_cell = config.raw_config().serialize()?;
+    Ok(json!({
+        "globalId": config.global_id(),
+        "globalVersion": config.global_version(),
+        "capabilities": config.capabilites().to_string(),
+        "signatureId": signature_id,
+        "configBoc": base64::encode(cell::serialize_boc(&config_cell)?),
+    })
+    .to_string())
+}
+
+/// Also sent on the WebSocket handshake of its GraphQL subscriptions, from their next connection
+fn set_transport_user_agent(transport_handle: jlong, user_agent: &str) -> Result<()> {
+    get_http_client(transport_handle)?.set_user_agent(user_agent)
+}
+
+/// Also caps each message of its GraphQL subscriptions, from their next connection
+fn set_max_response_bytes(transport_handle: jlong, bytes: jlong) -> Result<()> {
+    let bytes = usize::try_from(bytes).context("Invalid max response size")?;
+    get_http_client(transport_handle)?.set_max_response_bytes(bytes)
+}
+
+/// Polls for the transaction produced by the message, `Ok(None)` once `timeout` has passed
+pub async fn wait_for_transaction(
+    transport: &dyn Transport,
+    message_hash: &UInt256,
+    timeout: Duration,
+) -> Result<Option<RawTransaction>> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if let Some(transaction) = transport.get_dst_transaction(message_hash).await? {
+            return Ok(Some(transaction));
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Ok(None);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// `accepted` is false when no transaction picked the message up within
+/// `wait_timeout_ms`, e.g. because the contract rejected it or it expired.
+/// Zero waits `CONFIRMATION_TIMEOUT`
+async fn send_external_message(
+    transport_handle: jlong,
+    message_boc: Vec<u8>,
+    wait_timeout_ms: jlong,
+) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let wait_timeout = match u64::try_from(wait_timeout_ms) {
+        Ok(0) => CONFIRMATION_TIMEOUT,
+        Ok(wait_timeout_ms) => Duration::from_millis(wait_timeout_ms),
+        Err(_) => {
+            return Err(anyhow!(
+                "Wait timeout must not be negative, got {wait_timeout_ms}"
+            ))
+        }
+    };
+    let cell = cell::parse_boc(&message_boc)?;
+    let message_hash = cell.repr_hash();
+    let message = Message::construct_from_cell(cell)?;
+    if !message.is_inbound_external() {
+        return Err(anyhow!("Only external inbound messages can be sent"));
+    }
+
+    transport
+        .send_message(&message)
+        .await
+        .context(ErrorCode::Send)?;
+    let transaction = wait_for_transaction(transport.as_ref(), &message_hash, wait_timeout).await?;
+
+    Ok(json!({
+        "messageHash": message_hash.to_hex_string(),
+        "accepted": transaction.is_some(),
+        "transaction": match transaction {
+            Some(transaction) => models::transaction_to_json(&transaction.hash, &transaction.data)?,
+            None => Value::Null,
+        },
+    })
+    .to_string())
+}
+
+fn last_trans_lt(state: &RawContractState) -> u64 {
+    match state {
+        RawContractState::Exists(contract) => contract.account.storage.last_trans_lt,
+        RawContractState::NotExists { .. } => 0,
+    }
+}
+
+/// Generation time of the state, or the corrected local clock when the transport omits it
+fn chain_time(state: &RawContractState) -> u32 {
+    let timings = match state {
+        RawContractState::Exists(contract) => &contract.timings,
+        RawContractState::NotExists { timings } => timings,
+    };
+    match timings {
+        GenTimings::Known { gen_utime, .. } => *gen_utime,
+        GenTimings::Unknown => clock::CLOCK.now_sec_u64() as u32,
+    }
+}
+
+/// Unix time after which the destination rejects the message, read from an
+/// ABI 2 body with `time` and `expire` headers or from a WalletV3 body.
+/// `None` for any other layout
+fn message_expire_at(message: &Message, now: u32) -> Option<u32> {
+    let body = message.body()?;
+    let plausible = |time: u32| time.abs_diff(now) <= MAX_HEADER_DRIFT_SECS;
+    abi_expire_at(body.clone(), plausible).or_else(|| wallet_v3_expire_at(body, plausible))
+}
+
+fn abi_expire_at(mut body: SliceData, plausible: impl Fn(u32) -> bool) -> Option<u32> {
+    if body.get_next_bit().ok()? {
+        body.get_next_bits(512).ok()?;
+    }
+    let read_headers = |mut body: SliceData| {
+        let time_secs = u32::try_from(body.get_next_u64().ok()? / 1000).ok()?;
+        let expire_at = body.get_next_u32().ok()?;
+        (plausible(time_secs) && expire_at >= time_secs && plausible(expire_at))
+            .then_some(expire_at)
+    };
+    // The `pubkey` header, when the ABI declares one, precedes `time`
+    read_headers(body.clone()).or_else(|| {
+        if body.get_next_bit().ok()? {
+            body.get_next_bits(256).ok()?;
+        }
+        read_headers(body)
+    })
+}
+
+fn wallet_v3_expire_at(mut body: SliceData, plausible: impl Fn(u32) -> bool) -> Option<u32> {
+    // Signature, subwallet id, expiration and seqno, then a send mode per message
+    let layout_bits = 512 + 32 * 3 + body.remaining_references() * 8;
+    if body.remaining_bits() != layout_bits {
+        return None;
+    }
+    body.get_next_bits(512 + 32).ok()?;
+    body.get_next_u32()
+        .ok()
+        .filter(|expire_at| plausible(*expire_at))
+}
+
+/// Transaction of `address` newer than `since_lt` that the message produced
+async fn find_new_transaction(
+    transport: &dyn Transport,
+    address: &MsgAddressInt,
+    message_hash: &UInt256,
+    since_lt: u64,
+) -> Result<Option<RawTransaction>> {
+    let mut from_lt = u64::MAX;
+    loop {
+        let transactions = transport
+            .get_transactions(address, from_lt, SCAN_BATCH_SIZE)
+            .await?;
+        let next_from_lt = match transactions.last() {
+            // Transports include the transaction at `from_lt` itself
+            Some(oldest) if transactions.len() == SCAN_BATCH_SIZE as usize => {
+                Some(oldest.data.lt - 1)
+            }
+            _ => None,
+        };
+        for transaction in transactions {
+            if transaction.data.lt <= since_lt {
+                return Ok(None);
+            }
+            let in_msg_hash = transaction.data.in_msg_cell().map(|cell| cell.repr_hash());
+            if in_msg_hash.as_ref() == Some(message_hash) {
+                return Ok(Some(transaction));
+            }
+        }
+        match next_from_lt {
+            Some(lt) => from_lt = lt,
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Sends the message, then watches `dst_address` until a transaction picks the
+/// message up (`delivered`), or until `timeout_secs` pass or chain time passes
+/// the message expiration without one (`expired`). Only transactions of the destination account are scanned, so this works the
+/// same on transports without a message index
+async fn send_message_and_wait(
+    transport_handle: jlong,
+    message_boc: Vec<u8>,
+    dst_address: String,
+    timeout_secs: jint,
+) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let dst_address = nekoton_utils::repack_address(&dst_address)?;
+    let timeout = u64::try_from(timeout_secs)
+        .ok()
+        .filter(|timeout_secs| *timeout_secs > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("Timeout must be a positive number of seconds"))?;
+    let cell = cell::parse_boc(&message_boc)?;
+    let message_hash = cell.repr_hash();
+    let message = Message::construct_from_cell(cell)?;
+    if !message.is_inbound_external() {
+        return Err(anyhow!("Only external inbound messages can be sent"));
+    }
+    if message.dst().as_ref() != Some(&dst_address) {
+        return Err(anyhow!("Message is not addressed to {dst_address}"));
+    }
+
+    let state = transport.get_contract_state(&dst_address).await?;
+    let mut seen_lt = last_trans_lt(&state);
+    let expire_at = message_expire_at(&message, chain_time(&state));
+    transport
+        .send_message(&message)
+        .await
+        .context(ErrorCode::Send)?;
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let transaction = loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let state = transport.get_contract_state(&dst_address).await?;
+        let lt = last_trans_lt(&state);
+        if lt > seen_lt {
+            let found =
+                find_new_transaction(transport.as_ref(), &dst_address, &message_hash, seen_lt)
+                    .await?;
+            if found.is_some() {
+                break found;
+            }
+            seen_lt = lt;
+        }
+        // A state newer than the expiration already shows every block that could include it
+        let expired = expire_at.is_some_and(|expire_at| chain_time(&state) > expire_at);
+        if expired || tokio::time::Instant::now() >= deadline {
+            break None;
+        }
+    };
+
+    Ok(match transaction {
+        Some(transaction) => json!({
+            "status": "delivered",
+            "messageHash": message_hash.to_hex_string(),
+            "transactionBoc": base64::encode(cell::serialize_boc(&transaction.data.serialize()?)?),
+            "transaction": models::transaction_to_json(&transaction.hash, &transaction.data)?,
+        }),
+        None => json!({
+            "status": "expired",
+            "messageHash": message_hash.to_hex_string(),
+            "transactionBoc": Value::Null,
+            "transaction": Value::Null,
+        }),
+    }
+    .to_string())
+}
+
+async fn get_contract_state(transport_handle: jlong, address: String) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(&address)?;
+    let state = transport.get_contract_state(&address).await?;
+    Ok(models::contract_state_to_json(&state)?.to_string())
+}
+
+/// Keyed by the addresses as given. States are fetched with at most
+/// `BATCH_CONCURRENCY` requests in flight, so large portfolios don't flood the endpoint
+async fn get_contract_states_batch(transport_handle: jlong, addresses: String) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let addresses: Vec<String> =
+        serde_json::from_str(&addresses).context("Addresses must be a JSON array of strings")?;
+
+    let states = futures_util::stream::iter(addresses)
+        .map(|address| {
+            let transport = transport.clone();
+            async move {
+                let parsed = nekoton_utils::repack_address(&address)?;
+                let state = transport
+                    .get_contract_state(&parsed)
+                    .await
+                    .with_context(|| format!("Failed to fetch the state of {address}"))?;
+                Ok::<_, anyhow::Error>((address, models::contract_state_to_json(&state)?))
+            }
+        })
+        .buffer_unordered(BATCH_CONCURRENCY)
+        .try_collect::<serde_json::Map<_, _>>()
+        .await?;
+    Ok(Value::Object(states).to_string())
+}
+
+/// Newest first, strictly older than `from_lt` so the lt of the oldest returned
+/// transaction can be passed back to fetch the next page; `0` starts from the latest
+async fn get_transactions(
+    transport_handle: jlong,
+    address: String,
+    from_lt: jlong,
+    count: jint,
+) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(&address)?;
+    let from_lt = match u64::try_from(from_lt).context("Invalid from_lt")? {
+        0 => u64::MAX,
+        // Transports include the transaction at `from_lt` itself
+        from_lt => from_lt - 1,
+    };
+    let count = u8::try_from(count).context("Count must be between 0 and 255")?;
+
+    let transactions = transport.get_transactions(&address, from_lt, count).await?;
+    Ok(transactions_to_json(&transactions)?.to_string())
+}
+
+fn transactions_to_json(transactions: &[RawTransaction]) -> Result<Value> {
+    transactions
+        .iter()
+        .map(|transaction| models::transaction_to_json(&transaction.hash, &transaction.data))
+        .collect::<Result<Vec<_>>>()
+        .map(Value::Array)
+}
+
+/// Newest first, starting at `continuation_lt` itself; `0` starts from the latest.
+/// `continuation` points at the transaction preceding the oldest returned one and
+/// is null once the start of the account history is reached
+fn get_transactions_batch(
+    transport_handle: jlong,
+    address: &str,
+    continuation_lt: jlong,
+    count: jint,
+) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let address = nekoton_utils::repack_address(address)?;
+    let from_lt = match u64::try_from(continuation_lt).context("Invalid continuation lt")? {
+        0 => u64::MAX,
+        lt => lt,
+    };
+    let count = u8::try_from(count).context("Count must be between 0 and 255")?;
+
+    let transactions = runtime::block_on(transport.get_transactions(&address, from_lt, count))?;
+    let continuation = match transactions.last() {
+        Some(oldest) if oldest.data.prev_trans_lt != 0 => json!({
+            "lt": oldest.data.prev_trans_lt.to_string(),
+            "hash": oldest.data.prev_trans_hash.to_hex_string(),
+        }),
+        _ => Value::Null,
+    };
+
+    Ok(json!({
+        "transactions": transactions_to_json(&transactions)?,
+        "continuation": continuation,
+    })
+    .to_string())
+}
+
+/// Addresses of the accounts whose code has hash `code_hash`, in the transport
+/// order. An empty `continuation` starts from the first page; the returned
+/// `continuation` is the last address of a full page and null after the last one.
+/// Only GQL and newer RPC endpoints index accounts by code hash
+fn get_accounts_by_code_hash(
+    transport_handle: jlong,
+    code_hash: &[u8],
+    continuation: &str,
+    limit: jint,
+) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let code_hash = models::hash_from_bytes(code_hash)?;
+    let continuation = match continuation.trim() {
+        "" => None,
+        address => Some(nekoton_utils::repack_address(address)?),
+    };
+    let limit = u8::try_from(limit)
+        .ok()
+        .filter(|limit| *limit > 0)
+        .ok_or_else(|| anyhow!("Limit must be between 1 and 255, got {limit}"))?;
+
+    let accounts =
+        runtime::block_on(transport.get_accounts_by_code_hash(&code_hash, limit, &continuation))?;
+    let continuation = match accounts.last() {
+        Some(last) if accounts.len() == limit as usize => Value::String(last.to_string()),
+        _ => Value::Null,
+    };
+
+    Ok(json!({
+        "accounts": accounts.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "continuation": continuation,
+    })
+    .to_string())
+}
+
+/// `Ok(None)` when the transport doesn't know the transaction
+fn get_transaction(transport_handle: jlong, hash: &[u8]) -> Result<Option<String>> {
+    let transport = get_transport(transport_handle)?;
+    let hash = models::hash_from_bytes(hash)?;
+    Ok(match runtime::block_on(transport.get_transaction(&hash))? {
+        Some(transaction) => {
+            Some(models::transaction_to_json(&transaction.hash, &transaction.data)?.to_string())
+        }
+        None => None,
+    })
+}
+
+/// The transaction that processed the message with hash `message_hash`, if any yet
+fn get_dst_transaction(transport_handle: jlong, message_hash: &[u8]) -> Result<Option<String>> {
+    let transport = get_transport(transport_handle)?;
+    let hash = models::hash_from_bytes(message_hash)?;
+    Ok(
+        match runtime::block_on(transport.get_dst_transaction(&hash))? {
+            Some(transaction) => {
+                Some(models::transaction_to_json(&transaction.hash, &transaction.data)?.to_string())
+            }
+            None => None,
+        },
+    )
+}
+
+/// Transports only serve the latest account state, so the states around the
+/// transaction are rebuilt by replaying every transaction of the account since it
+/// was created, at most `MAX_REPLAYED_TRANSACTIONS` of them. Throws when the
+/// replay doesn't end up with the state hashes the transactions recorded
+fn get_transaction_states(transport_handle: jlong, transaction_hash: &str) -> Result<String> {
+    let transport = get_transport(transport_handle)?;
+    let hash = models::parse_hash(transaction_hash)?;
+    let transaction = runtime::block_on(transport.get_transaction(&hash))?
+        .ok_or_else(|| anyhow!("Transaction not found: {transaction_hash}"))?;
+
+    // Only tick-tock transactions have no inbound message, and those run in the masterchain
+    let workchain = match transaction
+        .data
+        .read_in_msg()?
+        .and_then(|message| message.dst())
+    {
+        Some(destination) => destination.workchain_id(),
+        None => ton_block::MASTERCHAIN_ID,
+    };
+    let address =
+        MsgAddressInt::with_standart(None, workchain as i8, transaction.data.account_addr.clone())?;
+
+    // Newest first, back to the transaction that created the account
+    let mut chain = vec![transaction];
+    let is_complete = |chain: &[RawTransaction]| {
+        chain[chain.len() - 1].data.orig_status == ton_block::AccountStatus::AccStateNonexist
+    };
+    while !is_complete(&chain) {
+        let fetched = chain.len();
+        let from_lt = chain[fetched - 1].data.prev_trans_lt;
+        let page =
+            runtime::block_on(transport.get_transactions(&address, from_lt, SCAN_BATCH_SIZE))?;
+        for previous in page {
+            let prev_trans_lt = chain[chain.len() - 1].data.prev_trans_lt;
+            if previous.data.lt != prev_trans_lt {
+                break;
+            }
+            if chain.len() >= MAX_REPLAYED_TRANSACTIONS {
+                return Err(anyhow!(
+                    "{address} has more than {MAX_REPLAYED_TRANSACTIONS} transactions \
+                     up to lt {}, too many to replay",
+                    chain[0].data.lt
+                ));
+            }
+            chain.push(previous);
+            if is_complete(&chain) {
+                break;
+            }
+        }
+        if chain.len() == fetched {
+            return Err(anyhow!(
+                "Transaction of {address} at lt {from_lt} not found"
+            ));
+        }
+    }
+
+    let config = runtime::block_on(transport.get_blockchain_config(clock::CLOCK.as_ref(), false))?;
+    let mut account = Account::AccountNone.serialize()?;
+    let (transaction, earlier) = chain.split_first().expect("The chain is never empty");
+    for previous in earlier.iter().rev() {
+        executor::replay_transaction(&config, &mut account, &previous.data)?;
+    }
+    let before = match earlier.first() {
+        Some(previous) => replayed_state_to_json(&account, previous)?,
+        None => models::nonexistent_state_to_json(),
+    };
+    executor::replay_transaction(&config, &mut account, &transaction.data)?;
+    let after = replayed_state_to_json(&account, transaction)?;
+
+    Ok(json!({ "before": before, "after": after }).to_string())
+}
+
+fn replayed_state_to_json(account: &Cell, last_transaction: &RawTransaction) -> Result<Value> {
+    let state = match Account::construct_from_cell(account.clone())? {
+        Account::AccountNone => RawContractState::NotExists {
+            timings: GenTimings::Unknown,
+        },
+        Account::Account(account) => RawContractState::Exists(ExistingContract {
+            account,
+            timings: GenTimings::Unknown,
+            last_transaction_id: LastTransactionId::Exact(TransactionId {
+                lt: last_transaction.data.lt,
+                hash: last_transaction.hash,
+            }),
+        }),
+    };
+    models::contract_state_to_json(&state)
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createGqlTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    endpoint: JString,
+) -> jlong {
+    error::guard!(env, {
+        let endpoint: String = match env.get_string(&endpoint) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_gql_transport(&endpoint) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createJrpcTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    endpoint: JString,
+) -> jlong {
+    error::guard!(env, {
+        let endpoint: String = match env.get_string(&endpoint) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_jrpc_transport(&endpoint) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createProtoTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    endpoint: JString,
+) -> jlong {
+    error::guard!(env, {
+        let endpoint: String = match env.get_string(&endpoint) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_proto_transport(&endpoint) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_createQuorumTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handles_json: JString,
+    quorum: jint,
+) -> jlong {
+    error::guard!(env, {
+        let transport_handles: String = match env.get_string(&transport_handles_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        match create_quorum_transport(&transport_handles, quorum) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+/// Selected endpoint, its measured latency and the retry policy, as JSON.
+/// Quorum transports have no endpoints of their own
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransportInfo(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let info = match get_transport_info(transport_handle) {
+            Ok(info) => info,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(info) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `-1` when the network signs without an id
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getSignatureId(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) -> jlong {
+    error::guard!(env, {
+        match get_signature_id(transport_handle) {
+            Ok(signature_id) => signature_id,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                -1
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getBlockchainConfig(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let config = match get_blockchain_config(transport_handle) {
+            Ok(config) => config,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(config) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setTransportUserAgent(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    user_agent: JString,
+) {
+    error::guard!(env, {
+        let user_agent: String = match env.get_string(&user_agent) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        if let Err(e) = set_transport_user_agent(transport_handle, &user_agent) {
+            error::throw_as(&mut env, NekotonError::Transport, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_setMaxResponseBytes(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    bytes: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = set_max_response_bytes(transport_handle, bytes) {
+            error::throw_as(&mut env, NekotonError::Transport, e);
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getContractState(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let state = match runtime::block_on(get_contract_state(transport_handle, address)) {
+            Ok(state) => state,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+
+        match env.byte_array_from_slice(state.as_bytes()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `addressesJson` is a JSON array of addresses; the result maps each of them
+/// to the same state JSON `getContractState` returns
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getContractStatesBatch(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    addresses_json: JString,
+) -> jbyteArray {
+    error::guard!(env, {
+        let addresses: String = match env.get_string(&addresses_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let states = match runtime::block_on(get_contract_states_batch(transport_handle, addresses))
+        {
+            Ok(states) => states,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+
+        match env.byte_array_from_slice(states.as_bytes()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sendExternalMessage(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    message_boc: JByteArray,
+    wait_timeout_ms: jlong,
+) -> jstring {
+    error::guard!(env, {
+        let message_boc = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match runtime::block_on(send_external_message(
+            transport_handle,
+            message_boc,
+            wait_timeout_ms,
+        )) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sendMessageAndWait(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    signed_message_boc: JByteArray,
+    dst_address: JString,
+    timeout_secs: jint,
+) -> jstring {
+    error::guard!(env, {
+        let message_boc = match env.convert_byte_array(signed_message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let dst_address: String = match env.get_string(&dst_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match runtime::block_on(send_message_and_wait(
+            transport_handle,
+            message_boc,
+            dst_address,
+            timeout_secs,
+        )) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransactions(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    from_lt: jlong,
+    count: jint,
+) -> jbyteArray {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let transactions =
+            match runtime::block_on(get_transactions(transport_handle, address, from_lt, count)) {
+                Ok(transactions) => transactions,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Transport, e);
+                    return std::ptr::null_mut();
+                }
+            };
+
+        match env.byte_array_from_slice(transactions.as_bytes()) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Like `getContractState`, without blocking the calling thread. `callback` is a
+/// `NativeCallback` completed with the state JSON or the exception
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getContractStateAsync(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    callback: JObject,
+) {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        let callback = match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => callback,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return;
+            }
+        };
+        callback::spawn_completing(
+            callback,
+            NekotonError::Transport,
+            get_contract_state(transport_handle, address),
+        );
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_sendExternalMessageAsync(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    message_boc: JByteArray,
+    wait_timeout_ms: jlong,
+    callback: JObject,
+) {
+    error::guard!(env, {
+        let message_boc = match env.convert_byte_array(message_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        let callback = match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => callback,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return;
+            }
+        };
+        callback::spawn_completing(
+            callback,
+            NekotonError::Transport,
+            send_external_message(transport_handle, message_boc, wait_timeout_ms),
+        );
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransactionsAsync(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    from_lt: jlong,
+    count: jint,
+    callback: JObject,
+) {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return;
+            }
+        };
+        let callback = match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => callback,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return;
+            }
+        };
+        callback::spawn_completing(
+            callback,
+            NekotonError::Transport,
+            get_transactions(transport_handle, address, from_lt, count),
+        );
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    transaction_hash: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let hash = match env.convert_byte_array(transaction_hash) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let transaction = match get_transaction(transport_handle, &hash) {
+            Ok(Some(transaction)) => transaction,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(transaction) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransactionsBatch(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    continuation_lt: jlong,
+    count: jint,
+) -> jstring {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let batch = match get_transactions_batch(transport_handle, &address, continuation_lt, count)
+        {
+            Ok(batch) => batch,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(batch) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getAccountsByCodeHash(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    code_hash: JByteArray,
+    continuation: JString,
+    limit: jint,
+) -> jstring {
+    error::guard!(env, {
+        let code_hash = match env.convert_byte_array(code_hash) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let continuation: String = match env.get_string(&continuation) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let page =
+            match get_accounts_by_code_hash(transport_handle, &code_hash, &continuation, limit) {
+                Ok(page) => page,
+                Err(e) => {
+                    error::throw_as(&mut env, NekotonError::Transport, e);
+                    return std::ptr::null_mut();
+                }
+            };
+        match env.new_string(page) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getDstTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    message_hash: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let hash = match env.convert_byte_array(message_hash) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let transaction = match get_dst_transaction(transport_handle, &hash) {
+            Ok(Some(transaction)) => transaction,
+            Ok(None) => return std::ptr::null_mut(),
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(transaction) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getTransactionStates(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    transaction_hash: JString,
+) -> jstring {
+    error::guard!(env, {
+        let transaction_hash: String = match env.get_string(&transaction_hash) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let states = match get_transaction_states(transport_handle, &transaction_hash) {
+            Ok(states) => states,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(states) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupTransport(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = TRANSPORTS.remove(transport_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::registry::InvalidHandle;
+
+    #[test]
+    fn gql_transports_keep_their_own_endpoints() {
+        let first = create_gql_transport("http://localhost:8001/graphql").unwrap();
+        let second = create_gql_transport("http://localhost:8002/graphql").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            get_http_client(first).unwrap().endpoint(),
+            "http://localhost:8001/graphql"
+        );
+        assert_eq!(
+            get_http_client(second).unwrap().endpoint(),
+            "http://localhost:8002/graphql"
+        );
+    }
+
+    #[test]
+    fn cleaned_up_transport_fails_lookups() {
+        let first = create_gql_transport("http://localhost:8001/graphql").unwrap();
+        let second = create_gql_transport("http://localhost:8002/graphql").unwrap();
+        TRANSPORTS.remove(first).unwrap();
+
+        let error = get_transport(first).err().unwrap();
+        assert!(error.is::<InvalidHandle>());
+        assert!(TRANSPORTS.remove(first).is_err());
+        assert!(get_transport(second).is_ok());
+    }
+
+    #[test]
+    fn handles_are_unique_across_threads() {
+        let threads = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..16)
+                        .map(|_| create_gql_transport("http://localhost:8001/graphql").unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let handles = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(handles.len(), 8 * 16);
+    }
+
+    #[test]
+    fn network_signature_id_is_kept_per_transport() {
+        let handles = [Some(1), None].map(|signature_id| {
+            let handle = mock::create_mock_transport("{}").unwrap();
+            TRANSPORTS
+                .get(handle)
+                .unwrap()
+                .signature_id
+                .set(signature_id)
+                .unwrap();
+            handle
+        });
+        let network =
+            |handle| crate::crypto::parse_signature_id(crate::crypto::NETWORK_SIGNATURE_ID, handle);
+        assert_eq!(network(handles[0]).unwrap(), Some(1));
+        assert_eq!(network(handles[1]).unwrap(), None);
+        assert_eq!(get_signature_id(handles[0]).unwrap(), 1);
+        assert_eq!(get_signature_id(handles[1]).unwrap(), -1);
+
+        // Without a config the mock can't answer, so nothing is cached for it
+        let unknown = mock::create_mock_transport("{}").unwrap();
+        assert!(network(unknown).is_err());
+        for handle in handles.into_iter().chain([unknown]) {
+            TRANSPORTS.remove(handle).unwrap();
+        }
+        assert!(network(unknown).is_err());
+    }
+
+    #[test]
+    fn transaction_states_are_replayed_from_the_account_creation() {
+        let address = nekoton_utils::repack_address(
+            "0:3333333333333333333333333333333333333333333333333333333333333333",
+        )
+        .unwrap();
+        let config = ton_executor::BlockchainConfig::default();
+        let mut account = Account::AccountNone.serialize().unwrap();
+        let transactions = [1_000_000_000u64, 2_000_000_000]
+            .iter()
+            .map(|value| {
+                let message = Message::with_int_header(
+                    ton_block::InternalMessageHeader::with_addresses_and_bounce(
+                        MsgAddressInt::default(),
+                        address.clone(),
+                        ton_block::CurrencyCollection::with_grams(*value),
+                        false,
+                    ),
+                );
+                let last_trans_lt = Account::construct_from_cell(account.clone())
+                    .unwrap()
+                    .last_tr_time()
+                    .unwrap_or_default();
+                executor::execute_message(
+                    config.clone(),
+                    &mut account,
+                    &message,
+                    1_700_000_000,
+                    last_trans_lt,
+                )
+                .unwrap()
+                .serialize()
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let hashes = transactions
+            .iter()
+            .map(|transaction| transaction.repr_hash().to_hex_string())
+            .collect::<Vec<_>>();
+
+        let boc = |cell: &Cell| base64::encode(cell::serialize_boc(cell).unwrap());
+        let fixtures = json!({
+            "transactions": {
+                address.to_string(): transactions.iter().map(boc).collect::<Vec<_>>(),
+            },
+            "configBoc": boc(&config.raw_config().serialize().unwrap()),
+            "globalId": config.global_id(),
+        });
+        let handle = mock::create_mock_transport(&fixtures.to_string()).unwrap();
+
+        let states = |hash: &str| -> Value {
+            serde_json::from_str(&get_transaction_states(handle, hash).unwrap()).unwrap()
+        };
+        let first = states(&hashes[0]);
+        assert_eq!(first["before"], models::nonexistent_state_to_json());
+        assert_eq!(first["after"]["status"], "uninit");
+        assert_eq!(first["after"]["lastTransactionHash"], hashes[0]);
+
+        let second = states(&hashes[1]);
+        assert_eq!(second["before"], first["after"]);
+        assert_eq!(second["after"]["lastTransactionHash"], hashes[1]);
+        let balance = |state: &Value| state["balance"].as_str().unwrap().parse::<u64>().unwrap();
+        assert!(balance(&second["after"]) > balance(&second["before"]));
+
+        assert!(get_transaction_states(handle, &"00".repeat(32)).is_err());
+        TRANSPORTS.remove(handle).unwrap();
+    }
+
+    #[test]
+    fn sent_external_messages_wait_only_their_timeout() {
+        let answered = nekoton_utils::repack_address(&format!("0:{}", "33".repeat(32))).unwrap();
+        let unanswered = nekoton_utils::repack_address(&format!("0:{}", "44".repeat(32))).unwrap();
+        let mut reply = ton_block::Transaction::with_address_and_status(
+            answered.address(),
+            ton_block::AccountStatus::AccStateActive,
+        );
+        reply
+            .write_description(&ton_block::TransactionDescr::Ordinary(Default::default()))
+            .unwrap();
+        let reply = reply.serialize().unwrap();
+        let fixtures = json!({
+            "replies": { answered.to_string(): base64::encode(cell::serialize_boc(&reply).unwrap()) },
+        });
+        let handle = mock::create_mock_transport(&fixtures.to_string()).unwrap();
+
+        let send = |dst: &MsgAddressInt, wait_timeout_ms| {
+            let message = Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+                dst: dst.clone(),
+                ..Default::default()
+            });
+            let boc = cell::serialize_boc(&message.serialize().unwrap()).unwrap();
+            runtime::block_on(send_external_message(handle, boc, wait_timeout_ms))
+        };
+        let result: Value = serde_json::from_str(&send(&answered, 1).unwrap()).unwrap();
+        assert_eq!(result["accepted"], true);
+        assert_eq!(
+            result["transaction"]["hash"],
+            reply.repr_hash().to_hex_string()
+        );
+
+        let started = std::time::Instant::now();
+        let result: Value = serde_json::from_str(&send(&unanswered, 1).unwrap()).unwrap();
+        assert_eq!(result["accepted"], false);
+        assert!(result["transaction"].is_null());
+        assert!(started.elapsed() < CONFIRMATION_TIMEOUT);
+
+        assert!(send(&unanswered, -1).is_err());
+        TRANSPORTS.remove(handle).unwrap();
+    }
+
+    fn wallet_v3_message(dst: &MsgAddressInt, expire_at: u32) -> Message {
+        let mut body = ton_types::BuilderData::new();
+        body.append_raw(&[0; 64], 512).unwrap();
+        for value in [0x4BA92D8A, expire_at, 7] {
+            body.append_u32(value).unwrap();
+        }
+        let mut message = Message::with_ext_in_header(ton_block::ExternalInboundMessageHeader {
+            dst: dst.clone(),
+            ..Default::default()
+        });
+        message.set_body(SliceData::load_builder(body).unwrap());
+        message
+    }
+
+    #[test]
+    fn message_expiration_is_read_from_known_bodies() {
+        let address = nekoton_utils::repack_address(&format!("0:{}", "33".repeat(32))).unwrap();
+        let now = clock::CLOCK.now_sec_u64() as u32;
+        let public_key = ed25519_dalek::PublicKey::from(
+            &ed25519_dalek::SecretKey::from_bytes(&[1; 32]).unwrap(),
+        );
+
+        for (header, public_key, signature) in [
+            (
+                r#"["pubkey", "time", "expire"]"#,
+                Some(public_key),
+                Some([2; 64]),
+            ),
+            (r#"["pubkey", "time", "expire"]"#, None, Some([2; 64])),
+            (r#"["pubkey", "time", "expire"]"#, Some(public_key), None),
+            (r#"["time", "expire"]"#, None, Some([2; 64])),
+            (r#"["time", "expire"]"#, None, None),
+        ] {
+            let abi = format!(
+                r#"{{"ABI version": 2, "version": "2.2", "header": {header},
+                    "functions": [{{"name": "touch", "inputs": [], "outputs": []}}],
+                    "events": [], "data": []}}"#
+            );
+            let contract = ton_abi::Contract::load(abi.as_bytes()).unwrap();
+            let function = contract.function("touch").unwrap();
+            let (body, _, expire_at) =
+                crate::contract::encode_call(function, &address, "", "{}", public_key, 60).unwrap();
+            let body = ton_abi::Function::fill_sign(
+                &contract.abi_version,
+                signature.as_ref().map(|signature| signature.as_slice()),
+                public_key.as_ref().map(ed25519_dalek::PublicKey::as_bytes),
+                body,
+            )
+            .unwrap();
+            let mut message = Message::with_ext_in_header(Default::default());
+            message.set_body(SliceData::load_builder(body).unwrap());
+            assert_eq!(
+                message_expire_at(&message, now),
+                Some(expire_at),
+                "{header}"
+            );
+            // Header times far from the chain time are taken as misread
+            assert_eq!(
+                message_expire_at(&message, now + 2 * MAX_HEADER_DRIFT_SECS),
+                None
+            );
+        }
+
+        let message = wallet_v3_message(&address, now + 60);
+        assert_eq!(message_expire_at(&message, now), Some(now + 60));
+
+        let mut message = Message::with_ext_in_header(Default::default());
+        assert_eq!(message_expire_at(&message, now), None);
+        message.set_body(SliceData::from_raw(vec![0xFF; 32], 256));
+        assert_eq!(message_expire_at(&message, now), None);
+    }
+
+    #[test]
+    fn expired_messages_stop_the_wait_before_its_timeout() {
+        let address = nekoton_utils::repack_address(&format!("0:{}", "33".repeat(32))).unwrap();
+        let handle = mock::create_mock_transport("{}").unwrap();
+        let now = clock::CLOCK.now_sec_u64() as u32;
+        let boc = cell::serialize_boc(&wallet_v3_message(&address, now - 10).serialize().unwrap())
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let result =
+            runtime::block_on(send_message_and_wait(handle, boc, address.to_string(), 30)).unwrap();
+        let result: Value = serde_json::from_str(&result).unwrap();
+        assert_eq!(result["status"], "expired");
+        assert!(result["transaction"].is_null());
+        assert!(started.elapsed() < Duration::from_secs(30));
+        TRANSPORTS.remove(handle).unwrap();
+    }
+}
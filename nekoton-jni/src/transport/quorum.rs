@@ -0,0 +1,292 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use nekoton::models::NetworkCapabilities;
+use nekoton::transport::models::{PollContractState, RawContractState, RawTransaction};
+use nekoton::transport::{Transport, TransportInfo};
+use nekoton_utils::Clock;
+use serde_json::{json, Value};
+use ton_block::{MsgAddressInt, Serializable};
+use ton_types::{Cell, UInt256};
+
+const QUORUM_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Thrown to Java as `NekotonException.QuorumNotReached` carrying `results`
+#[derive(Debug)]
+pub struct QuorumNotReached {
+    pub quorum: usize,
+    pub results: Value,
+}
+
+impl fmt::Display for QuorumNotReached {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fewer than {} transports agreed", self.quorum)
+    }
+}
+
+impl std::error::Error for QuorumNotReached {}
+
+/// Sends every read query to all inner transports and only returns a result
+/// once `quorum` of them produce the same fingerprint
+pub struct QuorumTransport {
+    transports: Vec<Arc<dyn Transport>>,
+    quorum: usize,
+}
+
+impl QuorumTransport {
+    pub fn new(transports: Vec<Arc<dyn Transport>>, quorum: usize) -> Result<Self> {
+        if quorum == 0 || quorum > transports.len() {
+            return Err(anyhow!(
+                "Quorum must be between 1 and the number of transports ({})",
+                transports.len()
+            ));
+        }
+        Ok(Self { transports, quorum })
+    }
+
+    async fn agree<T, Q, F, K>(&self, query: Q, fingerprint: K) -> Result<T>
+    where
+        T: Send,
+        Q: Fn(Arc<dyn Transport>) -> F + Send + Sync,
+        F: Future<Output = Result<T>> + Send,
+        K: Fn(&T) -> Result<String> + Send + Sync,
+    {
+        let mut pending = self
+            .transports
+            .iter()
+            .enumerate()
+            .map(|(index, transport)| {
+                let request = query(transport.clone());
+                async move { (index, request.await) }
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let deadline = tokio::time::Instant::now() + QUORUM_TIMEOUT;
+        let mut groups: Vec<(String, usize, T)> = Vec::new();
+        let mut results = Vec::new();
+
+        loop {
+            let (index, result) = match tokio::time::timeout_at(deadline, pending.next()).await {
+                Ok(Some(item)) => item,
+                Ok(None) => break,
+                Err(_) => {
+                    results.push(json!({ "error": "timeout" }));
+                    break;
+                }
+            };
+
+            let (key, value) = match result.and_then(|value| Ok((fingerprint(&value)?, value))) {
+                Ok(item) => item,
+                Err(e) => {
+                    results.push(json!({ "transport": index, "error": e.to_string() }));
+                    continue;
+                }
+            };
+            results.push(json!({ "transport": index, "result": key }));
+
+            let position = match groups.iter().position(|(group, ..)| *group == key) {
+                Some(position) => {
+                    groups[position].1 += 1;
+                    position
+                }
+                None => {
+                    groups.push((key, 1, value));
+                    groups.len() - 1
+                }
+            };
+            if groups[position].1 >= self.quorum {
+                return Ok(groups.swap_remove(position).2);
+            }
+        }
+
+        Err(QuorumNotReached {
+            quorum: self.quorum,
+            results: Value::Array(results),
+        }
+        .into())
+    }
+
+    async fn first_ok<T, Q, F>(&self, query: Q) -> Result<T>
+    where
+        Q: Fn(Arc<dyn Transport>) -> F + Send + Sync,
+        F: Future<Output = Result<T>> + Send,
+    {
+        let mut last_error = None;
+        for transport in &self.transports {
+            match query(transport.clone()).await {
+                Ok(value) => return Ok(value),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("No transports available")))
+    }
+}
+
+fn state_fingerprint(state: &RawContractState) -> Result<String> {
+    Ok(match state {
+        RawContractState::NotExists { .. } => "none".to_owned(),
+        RawContractState::Exists(contract) => {
+            contract.account.serialize()?.repr_hash().to_hex_string()
+        }
+    })
+}
+
+fn transaction_fingerprint(transaction: &Option<RawTransaction>) -> Result<String> {
+    Ok(match transaction {
+        Some(transaction) => transaction.hash.to_hex_string(),
+        None => "none".to_owned(),
+    })
+}
+
+#[async_trait::async_trait]
+impl Transport for QuorumTransport {
+    fn info(&self) -> TransportInfo {
+        self.transports[0].info()
+    }
+
+    async fn send_message(&self, message: &ton_block::Message) -> Result<()> {
+        let mut accepted = false;
+        let mut last_error = None;
+        for transport in &self.transports {
+            match transport.send_message(message).await {
+                Ok(()) => accepted = true,
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match (accepted, last_error) {
+            (false, Some(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_contract_state(&self, address: &MsgAddressInt) -> Result<RawContractState> {
+        self.agree(
+            |transport| async move { transport.get_contract_state(address).await },
+            state_fingerprint,
+        )
+        .await
+    }
+
+    async fn get_library_cell(&self, hash: &UInt256) -> Result<Option<Cell>> {
+        self.agree(
+            |transport| async move { transport.get_library_cell(hash).await },
+            |cell| {
+                Ok(match cell {
+                    Some(cell) => cell.repr_hash().to_hex_string(),
+                    None => "none".to_owned(),
+                })
+            },
+        )
+        .await
+    }
+
+    async fn poll_contract_state(
+        &self,
+        address: &MsgAddressInt,
+        last_trans_lt: u64,
+    ) -> Result<PollContractState> {
+        // Only a change hint, the state itself is re-read through the quorum
+        self.first_ok(|transport| async move {
+            transport.poll_contract_state(address, last_trans_lt).await
+        })
+        .await
+    }
+
+    async fn get_accounts_by_code_hash(
+        &self,
+        code_hash: &UInt256,
+        limit: u8,
+        continuation: &Option<MsgAddressInt>,
+    ) -> Result<Vec<MsgAddressInt>> {
+        self.agree(
+            |transport| async move {
+                transport
+                    .get_accounts_by_code_hash(code_hash, limit, continuation)
+                    .await
+            },
+            |accounts| {
+                Ok(accounts
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","))
+            },
+        )
+        .await
+    }
+
+    async fn get_transactions(
+        &self,
+        address: &MsgAddressInt,
+        from_lt: u64,
+        count: u8,
+    ) -> Result<Vec<RawTransaction>> {
+        self.agree(
+            |transport| async move { transport.get_transactions(address, from_lt, count).await },
+            |transactions| {
+                Ok(transactions
+                    .iter()
+                    .map(|transaction| transaction.hash.to_hex_string())
+                    .collect::<Vec<_>>()
+                    .join(","))
+            },
+        )
+        .await
+    }
+
+    async fn get_transaction(&self, id: &UInt256) -> Result<Option<RawTransaction>> {
+        self.agree(
+            |transport| async move { transport.get_transaction(id).await },
+            transaction_fingerprint,
+        )
+        .await
+    }
+
+    async fn get_dst_transaction(&self, message_hash: &UInt256) -> Result<Option<RawTransaction>> {
+        self.agree(
+            |transport| async move { transport.get_dst_transaction(message_hash).await },
+            transaction_fingerprint,
+        )
+        .await
+    }
+
+    async fn get_latest_key_block(&self) -> Result<ton_block::Block> {
+        self.agree(
+            |transport| async move { transport.get_latest_key_block().await },
+            |block| Ok(block.serialize()?.repr_hash().to_hex_string()),
+        )
+        .await
+    }
+
+    async fn get_capabilities(&self, clock: &dyn Clock) -> Result<NetworkCapabilities> {
+        self.agree(
+            |transport| async move { transport.get_capabilities(clock).await },
+            |capabilities| Ok(format!("{}:{}", capabilities.global_id, capabilities.raw)),
+        )
+        .await
+    }
+
+    /// Every fee estimate and local execution runs against this config, so it
+    /// must be agreed on like any state
+    async fn get_blockchain_config(
+        &self,
+        clock: &dyn Clock,
+        force: bool,
+    ) -> Result<ton_executor::BlockchainConfig> {
+        self.agree(
+            |transport| async move { transport.get_blockchain_config(clock, force).await },
+            |config| {
+                Ok(format!(
+                    "{}:{}",
+                    config.global_id(),
+                    config.raw_config().serialize()?.repr_hash().to_hex_string()
+                ))
+            },
+        )
+        .await
+    }
+}
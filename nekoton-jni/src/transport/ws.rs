@@ -0,0 +1,316 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::{SinkExt, Stream, StreamExt};
+use jni::objects::{JClass, JObject, JString};
+use jni::sys::jlong;
+use jni::JNIEnv;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::error::Error as WsError;
+use tokio_tungstenite::tungstenite::http::header::USER_AGENT;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use ton_block::MsgAddressInt;
+
+use super::http::{HttpClient, ResponseTooLarge};
+use super::TRANSPORTS;
+use crate::callback::JavaCallback;
+use crate::error::{self, NekotonError};
+use crate::registry::Registry;
+use crate::runtime;
+
+/// Delay before reconnecting after the socket was closed or failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const ACCOUNT_SUBSCRIPTION_ID: &str = "account";
+const MESSAGES_SUBSCRIPTION_ID: &str = "messages";
+
+static GQL_SUBSCRIPTIONS: Lazy<Registry<GqlSubscription>> = Lazy::new(Registry::new);
+
+/// Aborts the socket task once the handle is released
+struct GqlSubscription {
+    task: JoinHandle<()>,
+}
+
+impl Drop for GqlSubscription {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The GraphQL endpoint is also served over WebSocket at the same path
+fn websocket_url(endpoint: &str) -> Result<String> {
+    if let Some(rest) = endpoint.strip_prefix("https://") {
+        Ok(format!("wss://{rest}"))
+    } else if let Some(rest) = endpoint.strip_prefix("http://") {
+        Ok(format!("ws://{rest}"))
+    } else {
+        Err(anyhow!("Unsupported GraphQL endpoint: {endpoint}"))
+    }
+}
+
+fn start_message(id: &str, query: String) -> WsMessage {
+    let message = json!({
+        "id": id,
+        "type": "start",
+        "payload": { "query": query },
+    });
+    WsMessage::Text(message.to_string())
+}
+
+/// Messages and frames longer than `limit` bytes fail the connection
+async fn connect(
+    url: &str,
+    user_agent: HeaderValue,
+    limit: usize,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let mut request = url.into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert(
+        "Sec-WebSocket-Protocol",
+        HeaderValue::from_static("graphql-ws"),
+    );
+    headers.insert(USER_AGENT, user_agent);
+    let config = WebSocketConfig {
+        max_message_size: Some(limit),
+        max_frame_size: Some(limit),
+        ..Default::default()
+    };
+    let (socket, _) = tokio_tungstenite::connect_async_with_config(request, Some(config), false)
+        .await
+        .context("Failed to connect to the GraphQL WebSocket")?;
+    Ok(socket)
+}
+
+/// `None` once the socket is closed
+async fn next_message<S>(stream: &mut S, limit: usize) -> Result<Option<WsMessage>>
+where
+    S: Stream<Item = Result<WsMessage, WsError>> + Unpin,
+{
+    match stream.next().await {
+        Some(Ok(message)) => Ok(Some(message)),
+        Some(Err(WsError::Capacity(_))) => Err(ResponseTooLarge { limit }.into()),
+        Some(Err(e)) => Err(e.into()),
+        None => Ok(None),
+    }
+}
+
+/// Runs one connection using the `graphql-ws` protocol until it is closed
+async fn run_subscription(
+    url: &str,
+    http: &HttpClient,
+    address: &MsgAddressInt,
+    callback: &JavaCallback,
+) -> Result<()> {
+    // Identified and capped like the HTTP requests of the same transport
+    let limit = http.max_response_bytes();
+    let (mut sink, mut stream) = connect(url, http.user_agent(), limit).await?.split();
+
+    let init = json!({ "type": "connection_init", "payload": {} });
+    sink.send(WsMessage::Text(init.to_string())).await?;
+    sink.send(start_message(
+        ACCOUNT_SUBSCRIPTION_ID,
+        format!(
+            r#"subscription{{accounts(filter:{{id:{{eq:"{address}"}}}}){{id acc_type balance(format:DEC) last_trans_lt(format:DEC) boc}}}}"#
+        ),
+    ))
+    .await?;
+    sink.send(start_message(
+        MESSAGES_SUBSCRIPTION_ID,
+        format!(
+            r#"subscription{{messages(filter:{{dst:{{eq:"{address}"}}}}){{id src dst value(format:DEC) created_lt(format:DEC) boc}}}}"#
+        ),
+    ))
+    .await?;
+
+    while let Some(message) = next_message(&mut stream, limit).await? {
+        let text = match message {
+            WsMessage::Text(text) => text,
+            WsMessage::Ping(data) => {
+                sink.send(WsMessage::Pong(data)).await?;
+                continue;
+            }
+            WsMessage::Close(_) => break,
+            _ => continue,
+        };
+
+        let message: Value = serde_json::from_str(&text).context("Invalid GraphQL message")?;
+        match message["type"].as_str() {
+            Some("data") => {
+                let (method, field) = match message["id"].as_str() {
+                    Some(ACCOUNT_SUBSCRIPTION_ID) => ("onAccountChanged", "accounts"),
+                    Some(MESSAGES_SUBSCRIPTION_ID) => ("onMessageReceived", "messages"),
+                    _ => continue,
+                };
+                let data = &message["payload"]["data"][field];
+                if !data.is_null() {
+                    callback.notify(method, data.to_string());
+                }
+            }
+            Some("error" | "connection_error") => {
+                return Err(anyhow!(
+                    "GraphQL subscription error: {}",
+                    message["payload"]
+                ));
+            }
+            // Acks and keep-alives
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reconnects until the subscription is released, picking up the settings the
+/// transport has by then
+async fn subscription_loop(
+    url: String,
+    http: Arc<HttpClient>,
+    address: MsgAddressInt,
+    callback: JavaCallback,
+) {
+    loop {
+        if let Err(e) = run_subscription(&url, &http, &address, &callback).await {
+            log::warn!("GraphQL subscription for {address} dropped: {e:?}");
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+fn gql_subscribe_account(
+    transport_handle: jlong,
+    address: &str,
+    callback: JavaCallback,
+) -> Result<jlong> {
+    let entry = TRANSPORTS.get(transport_handle)?;
+    let http = match (&entry.http, &entry.gql) {
+        (Some(http), Some(_)) => http.clone(),
+        _ => return Err(anyhow!("Subscriptions require a GraphQL transport")),
+    };
+    let url = websocket_url(http.endpoint())?;
+    let address = nekoton_utils::repack_address(address)?;
+
+    let task = runtime::spawn(subscription_loop(url, http, address, callback));
+    Ok(GQL_SUBSCRIPTIONS.insert(Arc::new(GqlSubscription { task })))
+}
+
+/// `callback` implements `onAccountChanged` and `onMessageReceived`, both taking
+/// the JSON of the changed account or the incoming message
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_gqlSubscribeAccount(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    address: JString,
+    callback: JObject,
+) -> jlong {
+    error::guard!(env, {
+        let address: String = match env.get_string(&address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let callback = match JavaCallback::new(&mut env, callback) {
+            Ok(callback) => callback,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return 0;
+            }
+        };
+
+        match gql_subscribe_account(transport_handle, &address, callback) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw_as(&mut env, NekotonError::Transport, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_cleanupGqlSubscription(
+    mut env: JNIEnv,
+    _class: JClass,
+    subscription_handle: jlong,
+) {
+    error::guard!(env, {
+        if let Err(e) = GQL_SUBSCRIPTIONS.remove(subscription_handle) {
+            error::throw(&mut env, e.into());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    use super::*;
+
+    fn user_agent() -> HeaderValue {
+        HeaderValue::from_static("nekoton-kotlin-test")
+    }
+
+    #[test]
+    fn handshake_carries_the_user_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut received = None;
+            let record =
+                |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+                    received = request.headers().get(USER_AGENT).cloned();
+                    response.headers_mut().insert(
+                        "Sec-WebSocket-Protocol",
+                        HeaderValue::from_static("graphql-ws"),
+                    );
+                    Ok(response)
+                };
+            let _socket = tokio_tungstenite::tungstenite::accept_hdr(stream, record).unwrap();
+            received
+        });
+
+        runtime::block_on(connect(&url, user_agent(), 1024)).unwrap();
+        assert_eq!(server.join().unwrap(), Some(user_agent()));
+    }
+
+    #[test]
+    fn oversized_message_fails_as_response_too_large() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let accept_protocol =
+                |_: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+                    response.headers_mut().insert(
+                        "Sec-WebSocket-Protocol",
+                        HeaderValue::from_static("graphql-ws"),
+                    );
+                    Ok(response)
+                };
+            let mut socket =
+                tokio_tungstenite::tungstenite::accept_hdr(stream, accept_protocol).unwrap();
+            socket.send(WsMessage::Text("a".repeat(64))).unwrap();
+            // Keeps the socket open until the client gives up on it
+            let _ = socket.read();
+        });
+
+        let error = runtime::block_on(async {
+            let mut socket = connect(&url, user_agent(), 16).await?;
+            next_message(&mut socket, 16).await
+        })
+        .unwrap_err();
+        assert_eq!(error.downcast_ref::<ResponseTooLarge>().unwrap().limit, 16);
+    }
+}
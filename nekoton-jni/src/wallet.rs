@@ -0,0 +1,1242 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::PublicKey;
+use jni::objects::{JByteArray, JClass, JString};
+use jni::sys::{jbyteArray, jint, jlong, jstring};
+use jni::JNIEnv;
+use nekoton::core::models::Expiration;
+use nekoton::core::ton_wallet::{
+    self, ever_wallet, multisig, wallet_v3, Gift, MultisigType, TransferAction, WalletType,
+};
+use nekoton::crypto::UnsignedMessage;
+use nekoton::transport::models::{ExistingContract, RawContractState};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use ton_abi::{Contract, Function, Token, TokenValue};
+use ton_block::{AccountState, Deserializable, Message, MsgAddressInt, Serializable, StateInit};
+use ton_types::{Cell, SliceData, UInt256};
+
+use crate::error::{self, NekotonError};
+use crate::external::{self, Signable};
+use crate::{cell, clock, runtime, transport};
+
+const EVER_WALLET_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.3",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "sendTransaction",
+            "inputs": [
+                {"name": "dest", "type": "address"},
+                {"name": "value", "type": "uint128"},
+                {"name": "bounce", "type": "bool"},
+                {"name": "flags", "type": "uint8"},
+                {"name": "payload", "type": "cell"}
+            ],
+            "outputs": []
+        }
+    ],
+    "data": [],
+    "events": [],
+    "fields": []
+}"#;
+
+/// Transfer functions of the ABI 2.0 multisigs, `SafeMultisigWallet` and its kin
+const SAFE_MULTISIG_ABI: &str = r#"{
+    "ABI version": 2,
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "sendTransaction",
+            "inputs": [
+                {"name": "dest", "type": "address"},
+                {"name": "value", "type": "uint128"},
+                {"name": "bounce", "type": "bool"},
+                {"name": "flags", "type": "uint8"},
+                {"name": "payload", "type": "cell"}
+            ],
+            "outputs": []
+        },
+        {
+            "name": "submitTransaction",
+            "inputs": [
+                {"name": "dest", "type": "address"},
+                {"name": "value", "type": "uint128"},
+                {"name": "bounce", "type": "bool"},
+                {"name": "allBalance", "type": "bool"},
+                {"name": "payload", "type": "cell"}
+            ],
+            "outputs": [
+                {"name": "transId", "type": "uint64"}
+            ]
+        }
+    ],
+    "data": [],
+    "events": []
+}"#;
+
+/// Transfer functions of `Multisig2` and `Multisig2_1`, whose `submitTransaction`
+/// also takes the recipient's StateInit
+const MULTISIG2_ABI: &str = r#"{
+    "ABI version": 2,
+    "version": "2.3",
+    "header": ["pubkey", "time", "expire"],
+    "functions": [
+        {
+            "name": "sendTransaction",
+            "inputs": [
+                {"name": "dest", "type": "address"},
+                {"name": "value", "type": "uint128"},
+                {"name": "bounce", "type": "bool"},
+                {"name": "flags", "type": "uint8"},
+                {"name": "payload", "type": "cell"}
+            ],
+            "outputs": []
+        },
+        {
+            "name": "submitTransaction",
+            "inputs": [
+                {"name": "dest", "type": "address"},
+                {"name": "value", "type": "uint128"},
+                {"name": "bounce", "type": "bool"},
+                {"name": "allBalance", "type": "bool"},
+                {"name": "payload", "type": "cell"},
+                {"name": "stateInit", "type": "optional(cell)"}
+            ],
+            "outputs": [
+                {"name": "transId", "type": "uint64"}
+            ]
+        }
+    ],
+    "data": [],
+    "events": [],
+    "fields": []
+}"#;
+
+static EVER_WALLET: Lazy<Contract> =
+    Lazy::new(|| Contract::load(EVER_WALLET_ABI.as_bytes()).expect("Invalid EverWallet ABI"));
+
+/// Most messages one EverWallet transfer can carry
+const EVER_WALLET_MAX_MESSAGES: usize = 4;
+
+/// `sendTransactionRaw` overloads for 1 to 4 messages. They share a name and differ
+/// in function id, while a `Contract` keys functions by name and keeps only one, so
+/// each overload is loaded from an ABI of its own
+static EVER_WALLET_RAW: Lazy<Vec<Function>> = Lazy::new(|| {
+    (1..=EVER_WALLET_MAX_MESSAGES)
+        .map(|messages| {
+            let inputs = (0..messages)
+                .flat_map(|i| {
+                    [
+                        json!({ "name": format!("flags{i}"), "type": "uint8" }),
+                        json!({ "name": format!("message{i}"), "type": "cell" }),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let abi = json!({
+                "ABI version": 2,
+                "version": "2.3",
+                "header": ["pubkey", "time", "expire"],
+                "functions": [{ "name": "sendTransactionRaw", "inputs": inputs, "outputs": [] }],
+                "data": [],
+                "events": [],
+                "fields": [],
+            });
+            Contract::load(abi.to_string().as_bytes())
+                .and_then(|contract| contract.function("sendTransactionRaw").cloned())
+                .expect("Invalid EverWallet raw transfer ABI")
+        })
+        .collect()
+});
+
+static SAFE_MULTISIG: Lazy<Contract> =
+    Lazy::new(|| Contract::load(SAFE_MULTISIG_ABI.as_bytes()).expect("Invalid SafeMultisig ABI"));
+
+static MULTISIG2: Lazy<Contract> =
+    Lazy::new(|| Contract::load(MULTISIG2_ABI.as_bytes()).expect("Invalid Multisig2 ABI"));
+
+const SEND_MODE_DEFAULT: u8 = 3;
+const SEND_MODE_ALL_BALANCE: u8 = 128;
+
+/// Returns a single gift as an object and several gifts as an array.
+/// `wallet_type` is any type `parse_wallet_type` accepts
+fn decode_wallet_transfer(wallet_type: &str, body_boc: &[u8]) -> Result<String> {
+    let wallet_type = parse_wallet_type(wallet_type)?;
+    let body = SliceData::load_cell(cell::parse_boc(body_boc)?)?;
+    let mut gifts = match wallet_type {
+        WalletType::EverWallet => decode_ever_wallet_transfer(body)?,
+        WalletType::WalletV3 => decode_wallet_v3_transfer(body)?,
+        WalletType::Multisig(MultisigType::Multisig2 | MultisigType::Multisig2_1) => {
+            decode_abi_transfer(&MULTISIG2, body)?
+        }
+        WalletType::Multisig(_) => decode_abi_transfer(&SAFE_MULTISIG, body)?,
+        _ => return Err(anyhow!("Unsupported wallet type")),
+    };
+
+    let result = match gifts.len() {
+        0 => return Err(anyhow!("Transfer body contains no messages")),
+        1 => gifts.remove(0),
+        _ => Value::Array(gifts),
+    };
+    Ok(result.to_string())
+}
+
+fn decode_abi_transfer(contract: &Contract, body: SliceData) -> Result<Vec<Value>> {
+    let decoded = contract
+        .decode_input(body, false, false)
+        .context("Body does not match the wallet transfer layout")?;
+    let tokens = decoded.tokens;
+
+    match decoded.function_name.as_str() {
+        "sendTransaction" => Ok(vec![gift_from_tokens(
+            &tokens,
+            token_u8(&tokens, "flags")?,
+        )?]),
+        "submitTransaction" => {
+            let send_mode = match token_bool(&tokens, "allBalance")? {
+                true => SEND_MODE_ALL_BALANCE,
+                false => SEND_MODE_DEFAULT,
+            };
+            Ok(vec![gift_from_tokens(&tokens, send_mode)?])
+        }
+        name => Err(anyhow!("Unexpected wallet function: {name}")),
+    }
+}
+
+/// Raw transfers are told apart by the function id, which encodes the message count
+fn decode_ever_wallet_transfer(body: SliceData) -> Result<Vec<Value>> {
+    for function in EVER_WALLET_RAW.iter() {
+        if function.is_my_input_message(body.clone(), false)? {
+            let tokens = function
+                .decode_input(body, false, false)
+                .context("Body does not match the wallet transfer layout")?;
+            return gifts_from_raw_tokens(&tokens);
+        }
+    }
+    decode_abi_transfer(&EVER_WALLET, body)
+}
+
+fn gifts_from_raw_tokens(tokens: &[Token]) -> Result<Vec<Value>> {
+    tokens
+        .chunks(2)
+        .map(|pair| match pair {
+            [flags, message] => {
+                let send_mode = match &flags.value {
+                    TokenValue::Uint(value) => value.number.to_string().parse::<u8>()?,
+                    _ => return Err(anyhow!("Invalid flags token")),
+                };
+                match &message.value {
+                    TokenValue::Cell(message) => gift_from_message(message.clone(), send_mode),
+                    _ => Err(anyhow!("Invalid message token")),
+                }
+            }
+            _ => Err(anyhow!("Unpaired raw transaction input")),
+        })
+        .collect()
+}
+
+fn decode_wallet_v3_transfer(mut body: SliceData) -> Result<Vec<Value>> {
+    // Both the signed body and the signing payload are accepted
+    const SIGNATURE_BITS: usize = 512;
+    const HEADER_BITS: usize = 32 * 3;
+    let messages = body.remaining_references();
+    let expected_bits = HEADER_BITS + messages * 8;
+    if body.remaining_bits() == SIGNATURE_BITS + expected_bits {
+        body.get_next_bits(SIGNATURE_BITS)?;
+    } else if body.remaining_bits() != expected_bits {
+        return Err(anyhow!("Body does not match the WalletV3 transfer layout"));
+    }
+
+    let _subwallet_id = body.get_next_u32()?;
+    let _expire_at = body.get_next_u32()?;
+    let _seqno = body.get_next_u32()?;
+
+    (0..messages)
+        .map(|_| {
+            let send_mode = body.get_next_byte()?;
+            let message = body.checked_drain_reference()?;
+            gift_from_message(message, send_mode)
+        })
+        .collect()
+}
+
+fn gift_from_tokens(tokens: &[Token], send_mode: u8) -> Result<Value> {
+    let destination = match &find_token(tokens, "dest")?.value {
+        TokenValue::Address(address) => address.to_string(),
+        _ => return Err(anyhow!("Invalid destination token")),
+    };
+    let amount = match &find_token(tokens, "value")?.value {
+        TokenValue::Uint(value) => value.number.to_string(),
+        _ => return Err(anyhow!("Invalid amount token")),
+    };
+    let payload = match &find_token(tokens, "payload")?.value {
+        TokenValue::Cell(payload) => payload_to_json(payload)?,
+        _ => return Err(anyhow!("Invalid payload token")),
+    };
+
+    Ok(json!({
+        "destination": destination,
+        "amount": amount,
+        "bounce": token_bool(tokens, "bounce")?,
+        "sendMode": send_mode,
+        "payload": payload,
+    }))
+}
+
+fn gift_from_message(message: Cell, send_mode: u8) -> Result<Value> {
+    let message = Message::construct_from_cell(message)?;
+    let header = message
+        .int_header()
+        .ok_or_else(|| anyhow!("Wallet can only send internal messages"))?;
+    let payload = match message.body() {
+        Some(body) => payload_to_json(&body.into_cell())?,
+        None => Value::Null,
+    };
+
+    Ok(json!({
+        "destination": header.dst.to_string(),
+        "amount": header.value.grams.as_u128().to_string(),
+        "bounce": header.bounce,
+        "sendMode": send_mode,
+        "payload": payload,
+    }))
+}
+
+fn payload_to_json(payload: &Cell) -> Result<Value> {
+    // An empty cell is what wallets attach when there is no payload
+    if payload.bit_length() == 0 && payload.references_count() == 0 {
+        return Ok(Value::Null);
+    }
+    Ok(Value::String(base64::encode(cell::serialize_boc(payload)?)))
+}
+
+fn find_token<'a>(tokens: &'a [Token], name: &str) -> Result<&'a Token> {
+    tokens
+        .iter()
+        .find(|token| token.name == name)
+        .ok_or_else(|| anyhow!("Missing `{name}` input"))
+}
+
+fn token_bool(tokens: &[Token], name: &str) -> Result<bool> {
+    match find_token(tokens, name)?.value {
+        TokenValue::Bool(value) => Ok(value),
+        _ => Err(anyhow!("Invalid `{name}` token")),
+    }
+}
+
+fn token_u8(tokens: &[Token], name: &str) -> Result<u8> {
+    match &find_token(tokens, name)?.value {
+        TokenValue::Uint(value) => Ok(value.number.to_string().parse()?),
+        _ => Err(anyhow!("Invalid `{name}` token")),
+    }
+}
+
+/// Contract families with a transfer flow of their own; every multisig type nekoton
+/// knows, e.g. `SafeMultisigWallet` or `Multisig2`, shares one
+fn parse_wallet_type(wallet_type: &str) -> Result<WalletType> {
+    let parsed = WalletType::from_str(wallet_type)
+        .map_err(|_| anyhow!("Unknown wallet type: {wallet_type}"))?;
+    match parsed {
+        WalletType::EverWallet | WalletType::WalletV3 | WalletType::Multisig(_) => Ok(parsed),
+        _ => Err(anyhow!("Unsupported wallet type: {wallet_type}")),
+    }
+}
+
+fn wallet_state_init(wallet_type: WalletType, public_key: &PublicKey) -> Result<StateInit> {
+    match wallet_type {
+        WalletType::EverWallet => ever_wallet::make_state_init(public_key),
+        WalletType::WalletV3 => wallet_v3::InitData::from_key(public_key)
+            .with_wallet_id(wallet_v3::WALLET_ID)
+            .make_state_init(),
+        WalletType::Multisig(multisig_type) => {
+            Ok(multisig::prepare_state_init(public_key, multisig_type))
+        }
+        _ => Err(anyhow!("Unsupported wallet type")),
+    }
+}
+
+fn compute_wallet_address(wallet_type: &str, public_key: &[u8], workchain: jint) -> Result<String> {
+    let wallet_type = parse_wallet_type(wallet_type)?;
+    let public_key = PublicKey::from_bytes(public_key)?;
+    let workchain = i8::try_from(workchain).context("Invalid workchain")?;
+    Ok(ton_wallet::compute_address(&public_key, wallet_type, workchain).to_string())
+}
+
+/// What the first message to the wallet has to carry, unless the transfer does it
+fn get_wallet_state_init(wallet_type: &str, public_key: &[u8]) -> Result<Vec<u8>> {
+    let wallet_type = parse_wallet_type(wallet_type)?;
+    let public_key = PublicKey::from_bytes(public_key)?;
+    cell::serialize_boc(&wallet_state_init(wallet_type, &public_key)?.serialize()?)
+}
+
+/// Every type `parse_wallet_type` accepts, as named by nekoton
+const SUPPORTED_WALLET_TYPES: &[&str] = &[
+    "EverWallet",
+    "WalletV3",
+    "SafeMultisigWallet",
+    "SafeMultisigWallet24h",
+    "SetcodeMultisigWallet",
+    "SetcodeMultisigWallet24h",
+    "BridgeMultisigWallet",
+    "SurfWallet",
+    "Multisig2",
+    "Multisig2_1",
+];
+
+/// Hex code hash of each supported wallet type, for `getAccountsByCodeHash`.
+/// The code in a bundled state init doesn't depend on the key, so any key will do
+fn get_wallet_code_hashes() -> Result<String> {
+    let secret = ed25519_dalek::SecretKey::from_bytes(&[0; 32])?;
+    let public_key = PublicKey::from(&secret);
+
+    let mut hashes = serde_json::Map::new();
+    for name in SUPPORTED_WALLET_TYPES {
+        let state_init = wallet_state_init(parse_wallet_type(name)?, &public_key)?;
+        let code = state_init
+            .code
+            .ok_or_else(|| anyhow!("State init of {name} has no code"))?;
+        hashes.insert(
+            name.to_string(),
+            Value::String(code.repr_hash().to_hex_string()),
+        );
+    }
+    Ok(Value::Object(hashes).to_string())
+}
+
+/// Same shape `decodeWalletTransfer` produces, plus an optional `stateInit` BOC
+fn parse_gift(value: &Value) -> Result<Gift> {
+    let destination = value["destination"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Gift `destination` must be an address"))?;
+    let amount = match &value["amount"] {
+        Value::String(amount) => amount.parse().ok(),
+        amount => amount.as_u64(),
+    }
+    .ok_or_else(|| anyhow!("Gift `amount` must be a non-negative integer"))?;
+    let decode_cell = |key: &str| -> Result<Option<Cell>> {
+        match &value[key] {
+            Value::Null => Ok(None),
+            Value::String(boc) => Ok(Some(cell::parse_boc(&base64::decode(boc)?)?)),
+            _ => Err(anyhow!("Gift `{key}` must be a base64 BOC")),
+        }
+    };
+
+    Ok(Gift {
+        flags: match &value["sendMode"] {
+            Value::Null => SEND_MODE_DEFAULT,
+            mode => mode
+                .as_u64()
+                .and_then(|mode| u8::try_from(mode).ok())
+                .ok_or_else(|| anyhow!("Invalid gift `sendMode`"))?,
+        },
+        bounce: value["bounce"].as_bool().unwrap_or(true),
+        destination: nekoton_utils::repack_address(destination)?,
+        amount,
+        body: decode_cell("payload")?
+            .map(SliceData::load_cell)
+            .transpose()?,
+        state_init: decode_cell("stateInit")?
+            .map(StateInit::construct_from_cell)
+            .transpose()?,
+    })
+}
+
+/// Wraps a transfer signed by the wallet's own key, so it shares `signMessage`
+struct WalletMessage(Box<dyn UnsignedMessage>);
+
+impl Signable for WalletMessage {
+    fn hash(&self) -> &[u8] {
+        self.0.hash()
+    }
+
+    fn expire_at(&self) -> u32 {
+        self.0.expire_at()
+    }
+
+    fn sign(&self, signature: &[u8; 64]) -> Result<Vec<u8>> {
+        let signed = self.0.sign(signature)?;
+        cell::serialize_boc(&signed.message.serialize()?)
+    }
+}
+
+fn parse_multisig_type(wallet_type: &str) -> Result<MultisigType> {
+    match parse_wallet_type(wallet_type)? {
+        WalletType::Multisig(multisig_type) => Ok(multisig_type),
+        _ => Err(anyhow!("Not a multisig wallet type: {wallet_type}")),
+    }
+}
+
+fn parse_expiration(expire_sec: jint) -> Result<Expiration> {
+    u32::try_from(expire_sec)
+        .ok()
+        .filter(|expire_sec| *expire_sec > 0)
+        .map(Expiration::Timeout)
+        .ok_or_else(|| anyhow!("Expiration must be a positive number of seconds"))
+}
+
+fn get_existing_wallet(
+    transport_handle: jlong,
+    address: &MsgAddressInt,
+) -> Result<ExistingContract> {
+    let transport = transport::get_transport(transport_handle)?;
+    match runtime::block_on(transport.get_contract_state(address))
+        .context(NekotonError::Transport)?
+    {
+        RawContractState::Exists(contract) => Ok(contract),
+        RawContractState::NotExists { .. } => Err(anyhow!("Wallet {address} does not exist")),
+    }
+}
+
+/// Seqno and expiration follow each contract family: WalletV3 signs the next
+/// seqno, EverWallet and multisigs use the `time`/`expire` headers. Wallets
+/// that are not deployed yet carry their `StateInit`, except multisigs, which
+/// report `deployFirst` instead of a message handle
+fn prepare_wallet_transfer(
+    transport_handle: jlong,
+    wallet_type: &str,
+    public_key: &[u8],
+    workchain: jint,
+    gifts: &str,
+    expire_sec: jint,
+) -> Result<String> {
+    let wallet_type = parse_wallet_type(wallet_type)?;
+    let public_key = PublicKey::from_bytes(public_key)?;
+    let workchain = i8::try_from(workchain).context("Invalid workchain")?;
+    let expiration = parse_expiration(expire_sec)?;
+    let gifts = match serde_json::from_str(gifts).context("Invalid gifts JSON")? {
+        Value::Array(gifts) => gifts.iter().map(parse_gift).collect::<Result<Vec<_>>>()?,
+        gift => vec![parse_gift(&gift)?],
+    };
+
+    let address = ton_wallet::compute_address(&public_key, wallet_type, workchain);
+    let contract = get_existing_wallet(transport_handle, &address)?;
+
+    let clock = clock::CLOCK.as_ref();
+    let action = match wallet_type {
+        WalletType::EverWallet => ever_wallet::prepare_transfer(
+            clock,
+            &public_key,
+            &contract.account,
+            address.clone(),
+            gifts,
+            expiration,
+        )?,
+        WalletType::WalletV3 => wallet_v3::prepare_transfer(
+            clock,
+            &public_key,
+            &contract.account,
+            0,
+            gifts,
+            expiration,
+        )?,
+        WalletType::Multisig(multisig_type) => {
+            let [gift]: [Gift; 1] = gifts
+                .try_into()
+                .map_err(|_| anyhow!("Multisig wallets send exactly one gift per transfer"))?;
+            // Multisigs are deployed by a message of their own, not by the first transfer
+            if !matches!(
+                contract.account.storage.state,
+                AccountState::AccountActive { .. }
+            ) {
+                TransferAction::DeployFirst
+            } else {
+                let custodians =
+                    ton_wallet::get_wallet_custodians(clock, &contract, &public_key, wallet_type)?;
+                multisig::prepare_transfer(
+                    clock,
+                    multisig_type,
+                    &public_key,
+                    custodians.len() > 1,
+                    address.clone(),
+                    gift,
+                    expiration,
+                )?
+            }
+        }
+        _ => return Err(anyhow!("Unsupported wallet type")),
+    };
+
+    let result = match action {
+        TransferAction::DeployFirst => json!({
+            "address": address.to_string(),
+            "deployFirst": true,
+        }),
+        TransferAction::Sign(message) => {
+            let handle = external::insert_unsigned(Arc::new(WalletMessage(message)));
+            json!({
+                "address": address.to_string(),
+                "deployFirst": false,
+                "messageHandle": handle,
+            })
+        }
+    };
+    Ok(result.to_string())
+}
+
+/// Always goes through `submitTransaction`, so the transfer waits for the other
+/// custodians' confirmations even if a single one would be enough
+fn multisig_submit_transaction(
+    wallet_type: &str,
+    public_key: &[u8],
+    wallet_address: &str,
+    gift: &str,
+    expire_sec: jint,
+) -> Result<jlong> {
+    let multisig_type = parse_multisig_type(wallet_type)?;
+    let public_key = PublicKey::from_bytes(public_key)?;
+    let address = nekoton_utils::repack_address(wallet_address)?;
+    let expiration = parse_expiration(expire_sec)?;
+    let gift = parse_gift(&serde_json::from_str(gift).context("Invalid gift JSON")?)?;
+
+    match multisig::prepare_transfer(
+        clock::CLOCK.as_ref(),
+        multisig_type,
+        &public_key,
+        true,
+        address,
+        gift,
+        expiration,
+    )? {
+        TransferAction::Sign(message) => {
+            Ok(external::insert_unsigned(Arc::new(WalletMessage(message))))
+        }
+        TransferAction::DeployFirst => Err(anyhow!("Wallet must be deployed first")),
+    }
+}
+
+fn multisig_confirm_transaction(
+    wallet_type: &str,
+    public_key: &[u8],
+    wallet_address: &str,
+    transaction_id: &str,
+    expire_sec: jint,
+) -> Result<jlong> {
+    let multisig_type = parse_multisig_type(wallet_type)?;
+    let public_key = PublicKey::from_bytes(public_key)?;
+    let address = nekoton_utils::repack_address(wallet_address)?;
+    let expiration = parse_expiration(expire_sec)?;
+    let transaction_id: u64 = transaction_id
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid transaction id: {transaction_id}"))?;
+
+    let message = multisig::prepare_confirm_transaction(
+        clock::CLOCK.as_ref(),
+        multisig_type,
+        &public_key,
+        address,
+        transaction_id,
+        expiration,
+    )?;
+    Ok(external::insert_unsigned(Arc::new(WalletMessage(message))))
+}
+
+/// Bit `i` of a confirmations mask stands for the custodian with index `i`
+fn multisig_get_pending_transactions(
+    transport_handle: jlong,
+    wallet_type: &str,
+    wallet_address: &str,
+) -> Result<String> {
+    let multisig_type = parse_multisig_type(wallet_type)?;
+    let address = nekoton_utils::repack_address(wallet_address)?;
+    let contract = get_existing_wallet(transport_handle, &address)?;
+
+    let clock = clock::CLOCK.as_ref();
+    let custodians =
+        multisig::get_custodians(multisig_type, clock, Cow::Borrowed(&contract.account))?;
+    let transactions = multisig::get_pending_transactions(
+        multisig_type,
+        clock,
+        Cow::Borrowed(&contract.account),
+        &custodians,
+    )?;
+
+    let mask_of = |keys: &[UInt256]| {
+        custodians
+            .iter()
+            .enumerate()
+            .filter(|(_, custodian)| keys.contains(custodian))
+            .fold(0u32, |mask, (index, _)| mask | (1 << index))
+    };
+    let transactions = transactions
+        .iter()
+        .map(|transaction| {
+            Ok(json!({
+                "id": transaction.id.to_string(),
+                "creator": hex::encode(transaction.creator.as_slice()),
+                "index": transaction.index,
+                "destination": transaction.dest.to_string(),
+                "value": transaction.value.to_string(),
+                "bounce": transaction.bounce,
+                "sendFlags": transaction.send_flags,
+                "payload": base64::encode(cell::serialize_boc(&transaction.payload)?),
+                "signsRequired": transaction.signs_required,
+                "signsReceived": transaction.signs_received,
+                "confirmations": transaction
+                    .confirmations
+                    .iter()
+                    .map(|key| hex::encode(key.as_slice()))
+                    .collect::<Vec<_>>(),
+                "confirmationsMask": mask_of(&transaction.confirmations),
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "address": address.to_string(),
+        "custodians": custodians
+            .iter()
+            .map(|key| hex::encode(key.as_slice()))
+            .collect::<Vec<_>>(),
+        "transactions": transactions,
+    })
+    .to_string())
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_computeWalletAddress(
+    mut env: JNIEnv,
+    _class: JClass,
+    wallet_type: JString,
+    public_key: JByteArray,
+    workchain: jint,
+) -> jstring {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let address = match compute_wallet_address(&wallet_type, &public_key, workchain) {
+            Ok(address) => address,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(address) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getWalletStateInit(
+    mut env: JNIEnv,
+    _class: JClass,
+    wallet_type: JString,
+    public_key: JByteArray,
+) -> jbyteArray {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let state_init = match get_wallet_state_init(&wallet_type, &public_key) {
+            Ok(state_init) => state_init,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.byte_array_from_slice(&state_init) {
+            Ok(arr) => arr.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_getWalletCodeHashes(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    error::guard!(env, {
+        let hashes = match get_wallet_code_hashes() {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(hashes) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// `messageHandle` in the result is signed and released through the
+/// unsigned message functions, like the handles of `createExternalMessage`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_prepareWalletTransfer(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    wallet_type: JString,
+    public_key: JByteArray,
+    workchain: jint,
+    gifts_json: JString,
+    expire_sec: jint,
+) -> jstring {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let gifts: String = match env.get_string(&gifts_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match prepare_wallet_transfer(
+            transport_handle,
+            &wallet_type,
+            &public_key,
+            workchain,
+            &gifts,
+            expire_sec,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Returns an unsigned message handle, see `prepareWalletTransfer`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_multisigSubmitTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    wallet_type: JString,
+    public_key: JByteArray,
+    wallet_address: JString,
+    gift_json: JString,
+    expire_sec: jint,
+) -> jlong {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let wallet_address: String = match env.get_string(&wallet_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let gift: String = match env.get_string(&gift_json) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+
+        match multisig_submit_transaction(
+            &wallet_type,
+            &public_key,
+            &wallet_address,
+            &gift,
+            expire_sec,
+        ) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+/// Returns an unsigned message handle, see `prepareWalletTransfer`
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_multisigConfirmTransaction(
+    mut env: JNIEnv,
+    _class: JClass,
+    wallet_type: JString,
+    public_key: JByteArray,
+    wallet_address: JString,
+    transaction_id: JString,
+    expire_sec: jint,
+) -> jlong {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let public_key = match env.convert_byte_array(public_key) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let wallet_address: String = match env.get_string(&wallet_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+        let transaction_id: String = match env.get_string(&transaction_id) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return 0;
+            }
+        };
+
+        match multisig_confirm_transaction(
+            &wallet_type,
+            &public_key,
+            &wallet_address,
+            &transaction_id,
+            expire_sec,
+        ) {
+            Ok(handle) => handle,
+            Err(e) => {
+                error::throw(&mut env, e);
+                0
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_multisigGetPendingTransactions(
+    mut env: JNIEnv,
+    _class: JClass,
+    transport_handle: jlong,
+    wallet_type: JString,
+    wallet_address: JString,
+) -> jstring {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let wallet_address: String = match env.get_string(&wallet_address) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match multisig_get_pending_transactions(
+            transport_handle,
+            &wallet_type,
+            &wallet_address,
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn Java_com_mazekine_nekoton_Native_decodeWalletTransfer(
+    mut env: JNIEnv,
+    _class: JClass,
+    wallet_type: JString,
+    body_boc: JByteArray,
+) -> jstring {
+    error::guard!(env, {
+        let wallet_type: String = match env.get_string(&wallet_type) {
+            Ok(s) => s.into(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+        let body_boc = match env.convert_byte_array(body_boc) {
+            Ok(b) => b,
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                return std::ptr::null_mut();
+            }
+        };
+
+        let result = match decode_wallet_transfer(&wallet_type, &body_boc) {
+            Ok(result) => result,
+            Err(e) => {
+                error::throw(&mut env, e);
+                return std::ptr::null_mut();
+            }
+        };
+        match env.new_string(result) {
+            Ok(s) => s.into_raw(),
+            Err(e) => {
+                error::throw(&mut env, e.into());
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ton_abi::{ParamType, Uint};
+    use ton_block::{CurrencyCollection, InternalMessageHeader, MsgAddress};
+    use ton_types::BuilderData;
+
+    use super::*;
+
+    fn amount(index: usize) -> u64 {
+        1_000_000_000 * (index as u64 + 1)
+    }
+
+    fn raw_transfer_body(messages: usize) -> Vec<u8> {
+        let tokens = (0..messages)
+            .flat_map(|i| {
+                let destination = MsgAddressInt::from_str(&destination(i)).unwrap();
+                let mut header = InternalMessageHeader::with_addresses(
+                    MsgAddressInt::default(),
+                    destination,
+                    CurrencyCollection::with_grams(amount(i)),
+                );
+                header.bounce = true;
+                let message = Message::with_int_header(header).serialize().unwrap();
+                [
+                    Token::new(
+                        &format!("flags{i}"),
+                        TokenValue::Uint(Uint::new(SEND_MODE_DEFAULT as u128, 8)),
+                    ),
+                    Token::new(&format!("message{i}"), TokenValue::Cell(message)),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let (body, _) = EVER_WALLET_RAW[messages - 1]
+            .create_unsigned_call(&HashMap::new(), &tokens, false, true, None)
+            .unwrap();
+        cell::serialize_boc(&body.into_cell().unwrap()).unwrap()
+    }
+
+    fn decode_gifts(wallet_type: &str, body: &[u8]) -> Vec<Value> {
+        match serde_json::from_str(&decode_wallet_transfer(wallet_type, body).unwrap()).unwrap() {
+            Value::Array(gifts) => gifts,
+            gift => vec![gift],
+        }
+    }
+
+    fn destination(index: usize) -> String {
+        format!("0:{}", hex::encode([index as u8 + 1; 32]))
+    }
+
+    fn abi_transfer_body(contract: &Contract, function: &str, tokens: &[Token]) -> Vec<u8> {
+        let (body, _) = contract
+            .function(function)
+            .unwrap()
+            .create_unsigned_call(&HashMap::new(), tokens, false, true, None)
+            .unwrap();
+        cell::serialize_boc(&body.into_cell().unwrap()).unwrap()
+    }
+
+    fn submit_transaction_tokens(all_balance: bool) -> Vec<Token> {
+        vec![
+            Token::new(
+                "dest",
+                TokenValue::Address(MsgAddress::from_str(&destination(0)).unwrap()),
+            ),
+            Token::new("value", TokenValue::Uint(Uint::new(amount(0) as u128, 128))),
+            Token::new("bounce", TokenValue::Bool(false)),
+            Token::new("allBalance", TokenValue::Bool(all_balance)),
+            Token::new("payload", TokenValue::Cell(Cell::default())),
+        ]
+    }
+
+    fn assert_multisig_gift(gift: &Value, send_mode: u8) {
+        assert_eq!(gift["destination"], destination(0));
+        assert_eq!(gift["amount"], amount(0).to_string());
+        assert_eq!(gift["bounce"], false);
+        assert_eq!(gift["sendMode"], send_mode);
+        assert_eq!(gift["payload"], Value::Null);
+    }
+
+    #[test]
+    fn decodes_raw_ever_wallet_transfers_of_every_size() {
+        for messages in 1..=EVER_WALLET_MAX_MESSAGES {
+            let gifts = decode_gifts("EverWallet", &raw_transfer_body(messages));
+            assert_eq!(gifts.len(), messages);
+            for (i, gift) in gifts.iter().enumerate() {
+                assert_eq!(gift["destination"], destination(i));
+                assert_eq!(gift["amount"], amount(i).to_string());
+                assert_eq!(gift["sendMode"], SEND_MODE_DEFAULT);
+                assert_eq!(gift["bounce"], true);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_wallet_v3_transfers_with_and_without_signature() {
+        let mut header = InternalMessageHeader::with_addresses(
+            MsgAddressInt::default(),
+            MsgAddressInt::from_str(&destination(0)).unwrap(),
+            CurrencyCollection::with_grams(amount(0)),
+        );
+        header.bounce = true;
+        let message = Message::with_int_header(header).serialize().unwrap();
+
+        let mut payload = BuilderData::new();
+        for value in [wallet_v3::WALLET_ID, 1_700_000_000, 7] {
+            payload.append_u32(value).unwrap();
+        }
+        payload.append_u8(SEND_MODE_ALL_BALANCE).unwrap();
+        payload.checked_append_reference(message).unwrap();
+        let mut signed = BuilderData::new();
+        signed.append_raw(&[0xEE; 64], 512).unwrap();
+        signed.append_builder(&payload).unwrap();
+
+        for body in [payload, signed] {
+            let body = cell::serialize_boc(&body.into_cell().unwrap()).unwrap();
+            let gifts = decode_gifts("WalletV3", &body);
+            assert_eq!(gifts.len(), 1);
+            assert_eq!(gifts[0]["destination"], destination(0));
+            assert_eq!(gifts[0]["amount"], amount(0).to_string());
+            assert_eq!(gifts[0]["sendMode"], SEND_MODE_ALL_BALANCE);
+            assert_eq!(gifts[0]["payload"], Value::Null);
+        }
+    }
+
+    #[test]
+    fn decodes_multisig_transfers_by_their_abi() {
+        let body = abi_transfer_body(
+            &SAFE_MULTISIG,
+            "submitTransaction",
+            &submit_transaction_tokens(false),
+        );
+        for wallet_type in [
+            "SafeMultisigWallet",
+            "SetcodeMultisigWallet24h",
+            "SurfWallet",
+        ] {
+            assert_multisig_gift(&decode_gifts(wallet_type, &body)[0], SEND_MODE_DEFAULT);
+        }
+
+        let mut tokens = submit_transaction_tokens(true);
+        tokens.push(Token::new(
+            "stateInit",
+            TokenValue::Optional(ParamType::Cell, None),
+        ));
+        let body = abi_transfer_body(&MULTISIG2, "submitTransaction", &tokens);
+        for wallet_type in ["Multisig2", "Multisig2_1"] {
+            assert_multisig_gift(&decode_gifts(wallet_type, &body)[0], SEND_MODE_ALL_BALANCE);
+        }
+        // The ABI 2.0 layout has no `stateInit` to match the Multisig2 function id
+        assert!(decode_wallet_transfer("SafeMultisigWallet", &body).is_err());
+    }
+
+    #[test]
+    fn bodies_of_another_layout_are_rejected() {
+        let raw = raw_transfer_body(2);
+        for wallet_type in ["WalletV3", "SafeMultisigWallet", "Multisig2"] {
+            assert!(
+                decode_wallet_transfer(wallet_type, &raw).is_err(),
+                "{wallet_type}"
+            );
+        }
+        let empty = cell::serialize_boc(&Cell::default()).unwrap();
+        for wallet_type in ["EverWallet", "WalletV3", "SafeMultisigWallet", "Multisig2"] {
+            assert!(
+                decode_wallet_transfer(wallet_type, &empty).is_err(),
+                "{wallet_type}"
+            );
+        }
+        // Only the names `parse_wallet_type` knows
+        for wallet_type in ["Multisig", "everwallet", "HighloadWalletV2"] {
+            assert!(
+                decode_wallet_transfer(wallet_type, &raw).is_err(),
+                "{wallet_type}"
+            );
+        }
+    }
+}